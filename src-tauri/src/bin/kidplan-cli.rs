@@ -0,0 +1,838 @@
+use clap::{Args, Parser, Subcommand};
+use kidplan_downloader_lib::dedupe::DuplicateMode;
+use kidplan_downloader_lib::endpoints::Endpoints;
+use kidplan_downloader_lib::export::{self, ZipLayout, ZipSettings};
+use kidplan_downloader_lib::gallery::{self, GalleryLanguage};
+use kidplan_downloader_lib::manifest::{self, EntryStatus, Manifest, Verification};
+use kidplan_downloader_lib::naming;
+use kidplan_downloader_lib::progress::LogReporter;
+use kidplan_downloader_lib::queue::JobQueue;
+use kidplan_downloader_lib::schedule::{Schedule, ScheduleConfig, ScheduledRun, Scheduler};
+use kidplan_downloader_lib::storage::StorageConfig;
+use kidplan_downloader_lib::thumbnails;
+use kidplan_downloader_lib::upload::UploadConfig;
+use kidplan_downloader_lib::{
+    downloader, kindergartens, sync, Album, AppState, Credentials, DownloadResult,
+    DownloadSettings, KindergartenAlbums,
+};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+/// Headless Kidplan album downloader.
+#[derive(Parser)]
+#[command(name = "kidplan-cli", version, about)]
+struct Cli {
+    /// Kidplan account e-mail
+    #[arg(long, env = "KIDPLAN_EMAIL", global = true)]
+    email: Option<String>,
+
+    /// Kidplan account password
+    #[arg(long, env = "KIDPLAN_PASSWORD", hide_env_values = true, global = true)]
+    password: Option<String>,
+
+    /// Kidplan base URL, e.g. a local mock server
+    #[arg(long, env = "KIDPLAN_BASE_URL", global = true)]
+    base_url: Option<String>,
+
+    /// Host allowed to serve album images (repeatable)
+    #[arg(
+        long = "image-host",
+        env = "KIDPLAN_IMAGE_HOSTS",
+        value_delimiter = ',',
+        global = true
+    )]
+    image_hosts: Vec<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the kindergartens available to the account
+    ListKindergartens,
+    /// List the albums of a kindergarten
+    ListAlbums(KidArgs),
+    /// Download albums to a local directory
+    Download(DownloadArgs),
+    /// Download only albums that are new or changed since the last sync
+    Sync(DownloadArgs),
+    /// Keep syncing on a schedule, e.g. `--at 02:00` or `--at 6h`
+    Schedule(ScheduleArgs),
+    /// Check downloaded files against the manifest and the image counts reported by Kidplan
+    Verify(VerifyArgs),
+    /// Show where the first images of the selected albums would be saved
+    Preview(PreviewArgs),
+    /// Pack downloaded albums, or any folder with --dir, into ZIP archives
+    Export(ExportArgs),
+    /// Write a static HTML gallery of the downloaded albums, viewable in any browser
+    Gallery(GalleryArgs),
+    /// Print the path of a thumbnail of an album or one of its images, making it if needed
+    Thumbnail(ThumbnailArgs),
+}
+
+#[derive(Args)]
+struct KidArgs {
+    /// Kindergarten id (may be omitted when the account has only one;
+    /// `download` accepts it repeatedly to fetch from several)
+    #[arg(long = "kid")]
+    kid_ids: Vec<i64>,
+}
+
+impl KidArgs {
+    /// The one kindergarten asked for, for commands working on a single one.
+    fn single(&self) -> Result<Option<i64>, Box<dyn std::error::Error>> {
+        match self.kid_ids.as_slice() {
+            [] => Ok(None),
+            [id] => Ok(Some(*id)),
+            _ => Err("Only download accepts more than one --kid".into()),
+        }
+    }
+}
+
+#[derive(Args)]
+struct AlbumSelection {
+    #[command(flatten)]
+    kid: KidArgs,
+
+    /// Album id to include (repeatable); all albums when omitted
+    #[arg(long = "album")]
+    album_ids: Vec<String>,
+
+    /// Output directory; relative paths are placed under the Downloads folder
+    #[arg(long, default_value_t = DownloadSettings::default().out_dir)]
+    out_dir: String,
+}
+
+#[derive(Args)]
+struct DownloadArgs {
+    #[command(flatten)]
+    selection: AlbumSelection,
+
+    /// Delay between image requests in milliseconds
+    #[arg(long, default_value_t = DownloadSettings::default().delay_ms)]
+    delay_ms: u64,
+
+    /// Maximum images per album (0 = unlimited)
+    #[arg(long, default_value_t = DownloadSettings::default().limit_per_album)]
+    limit: usize,
+
+    /// Number of images to download in parallel (1-8)
+    #[arg(long, default_value_t = DownloadSettings::default().concurrency)]
+    concurrency: usize,
+
+    /// What to do with photos already in the library: skip, hardlink or symlink
+    #[arg(long, default_value_t = DuplicateMode::default())]
+    duplicates: DuplicateMode,
+
+    /// File name template, e.g. "{year}/{album_title}/{index:03} {image_id}.{ext}"
+    #[arg(long)]
+    template: Option<String>,
+
+    /// Write the album date, title and kindergarten into each JPEG's EXIF
+    #[arg(long)]
+    write_exif: bool,
+
+    /// Write an .xmp sidecar with album and kindergarten details next to each image
+    #[arg(long)]
+    write_xmp: bool,
+
+    /// Pack the albums into ZIP archives in the output directory afterwards
+    #[arg(long)]
+    zip: bool,
+
+    #[command(flatten)]
+    zip_options: ZipArgs,
+
+    #[command(flatten)]
+    storage: StorageArgs,
+
+    #[command(flatten)]
+    upload: UploadArgs,
+}
+
+#[derive(Args)]
+struct UploadArgs {
+    /// Push each saved image to this Immich server, in an album per Kidplan album
+    #[arg(
+        long,
+        env = "KIDPLAN_IMMICH_URL",
+        requires = "immich_api_key",
+        conflicts_with = "photoprism_url"
+    )]
+    immich_url: Option<String>,
+
+    /// Immich API key
+    #[arg(long, env = "KIDPLAN_IMMICH_API_KEY", hide_env_values = true)]
+    immich_api_key: Option<String>,
+
+    /// Push each saved image to this PhotoPrism server, in an album per Kidplan album
+    #[arg(long, env = "KIDPLAN_PHOTOPRISM_URL", requires = "photoprism_token")]
+    photoprism_url: Option<String>,
+
+    /// PhotoPrism app password
+    #[arg(long, env = "KIDPLAN_PHOTOPRISM_TOKEN", hide_env_values = true)]
+    photoprism_token: Option<String>,
+}
+
+impl UploadArgs {
+    fn config(&self) -> Option<UploadConfig> {
+        if let Some(url) = &self.immich_url {
+            return Some(UploadConfig::Immich {
+                url: url.clone(),
+                api_key: self.immich_api_key.clone().unwrap_or_default(),
+            });
+        }
+        self.photoprism_url.as_ref().map(|url| UploadConfig::PhotoPrism {
+            url: url.clone(),
+            token: self.photoprism_token.clone().unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Args)]
+struct StorageArgs {
+    /// Upload images to this WebDAV folder instead of the output directory
+    #[arg(long, env = "KIDPLAN_WEBDAV_URL", conflicts_with = "s3_endpoint")]
+    webdav_url: Option<String>,
+
+    /// WebDAV user name
+    #[arg(long, env = "KIDPLAN_WEBDAV_USER", requires = "webdav_url")]
+    webdav_user: Option<String>,
+
+    /// WebDAV password
+    #[arg(
+        long,
+        env = "KIDPLAN_WEBDAV_PASSWORD",
+        hide_env_values = true,
+        requires = "webdav_url"
+    )]
+    webdav_password: Option<String>,
+
+    /// Upload images to an S3-compatible server instead of the output directory
+    #[arg(
+        long,
+        env = "KIDPLAN_S3_ENDPOINT",
+        requires_all = ["s3_bucket", "s3_access_key", "s3_secret_key"]
+    )]
+    s3_endpoint: Option<String>,
+
+    /// S3 bucket
+    #[arg(long, env = "KIDPLAN_S3_BUCKET")]
+    s3_bucket: Option<String>,
+
+    /// S3 region
+    #[arg(long, env = "KIDPLAN_S3_REGION", default_value = "us-east-1")]
+    s3_region: String,
+
+    /// S3 access key
+    #[arg(long, env = "KIDPLAN_S3_ACCESS_KEY")]
+    s3_access_key: Option<String>,
+
+    /// S3 secret key
+    #[arg(long, env = "KIDPLAN_S3_SECRET_KEY", hide_env_values = true)]
+    s3_secret_key: Option<String>,
+
+    /// Folder inside the bucket
+    #[arg(long, env = "KIDPLAN_S3_PREFIX", default_value = "")]
+    s3_prefix: String,
+}
+
+impl StorageArgs {
+    fn config(&self) -> StorageConfig {
+        if let Some(url) = &self.webdav_url {
+            return StorageConfig::WebDav {
+                url: url.clone(),
+                username: self.webdav_user.clone(),
+                password: self.webdav_password.clone(),
+            };
+        }
+        match &self.s3_endpoint {
+            Some(endpoint) => StorageConfig::S3 {
+                endpoint: endpoint.clone(),
+                bucket: self.s3_bucket.clone().unwrap_or_default(),
+                region: self.s3_region.clone(),
+                access_key: self.s3_access_key.clone().unwrap_or_default(),
+                secret_key: self.s3_secret_key.clone().unwrap_or_default(),
+                prefix: self.s3_prefix.clone(),
+            },
+            None => StorageConfig::Local,
+        }
+    }
+}
+
+#[derive(Args)]
+struct ZipArgs {
+    /// Compression level: 0 stores files as they are, 1-9 deflate
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=9))]
+    zip_level: u8,
+
+    /// One archive per album (per-album) or one for all (combined)
+    #[arg(long, default_value_t = ZipLayout::default())]
+    zip_layout: ZipLayout,
+}
+
+impl ZipArgs {
+    fn settings(&self) -> ZipSettings {
+        ZipSettings {
+            level: self.zip_level,
+            layout: self.zip_layout,
+        }
+    }
+}
+
+impl DownloadArgs {
+    fn settings(&self) -> DownloadSettings {
+        DownloadSettings {
+            out_dir: self.selection.out_dir.clone(),
+            delay_ms: self.delay_ms,
+            limit_per_album: self.limit,
+            concurrency: self.concurrency,
+            duplicates: self.duplicates,
+            path_template: self.template.clone(),
+            write_exif: self.write_exif,
+            write_xmp: self.write_xmp,
+            zip: self.zip.then(|| self.zip_options.settings()),
+            storage: self.storage.config(),
+            upload: self.upload.config(),
+        }
+    }
+}
+
+#[derive(Args)]
+struct ScheduleArgs {
+    #[command(flatten)]
+    download: DownloadArgs,
+
+    /// Daily time (HH:MM) or interval (e.g. 90m, 6h) to sync at
+    #[arg(long)]
+    at: Schedule,
+
+    /// Sync once now, record the run and exit
+    #[arg(long)]
+    once: bool,
+
+    /// Where to keep the schedule, its history and its job queue. Defaults
+    /// to a folder per library in the user's app data dir, so nothing of it
+    /// ends up in the library itself
+    #[arg(long, env = "KIDPLAN_STATE_DIR")]
+    state_dir: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    #[command(flatten)]
+    selection: AlbumSelection,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    #[command(flatten)]
+    selection: AlbumSelection,
+
+    #[command(flatten)]
+    zip_options: ZipArgs,
+
+    /// Folder to write the archives to (default: the output directory)
+    #[arg(long)]
+    dest: Option<std::path::PathBuf>,
+
+    /// Pack this folder instead of albums, e.g. one album's folder
+    #[arg(long, conflicts_with = "album_ids")]
+    dir: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+struct GalleryArgs {
+    /// Output directory of the downloads; relative paths are placed under the Downloads folder
+    #[arg(long, default_value_t = DownloadSettings::default().out_dir)]
+    out_dir: String,
+
+    /// Language of the gallery's texts: en or no
+    #[arg(long, default_value_t = GalleryLanguage::default())]
+    lang: GalleryLanguage,
+}
+
+#[derive(Args)]
+struct ThumbnailArgs {
+    /// Output directory of the downloads; relative paths are placed under the Downloads folder
+    #[arg(long, default_value_t = DownloadSettings::default().out_dir)]
+    out_dir: String,
+
+    /// Album id
+    #[arg(long)]
+    album: String,
+
+    /// Image id within the album; the album's cover when omitted
+    #[arg(long)]
+    image: Option<String>,
+
+    /// Longest side of the thumbnail in pixels
+    #[arg(long, default_value_t = thumbnails::DEFAULT_SIZE)]
+    size: u32,
+}
+
+#[derive(Args)]
+struct PreviewArgs {
+    #[command(flatten)]
+    selection: AlbumSelection,
+
+    /// File name template to try out
+    #[arg(long)]
+    template: String,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    // Packing a folder needs no Kidplan account
+    if let Command::Export(args @ ExportArgs { dir: Some(dir), .. }) = &cli.command {
+        let state = Arc::new(AppState::default());
+        cancel_on_ctrl_c(state.clone());
+        let name = dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "album".to_string());
+        let dest = args
+            .dest
+            .clone()
+            .unwrap_or_else(|| downloader::resolve_out_dir(&args.selection.out_dir));
+        let archive = export::zip_directory(
+            dir,
+            &dest.join(format!("{}.zip", name)),
+            &args.zip_options.settings(),
+            &LogReporter,
+            &state.controls.cancellation(),
+        )?;
+        println!("{}", archive.display());
+        return Ok(ExitCode::SUCCESS);
+    }
+    // Neither do galleries and thumbnails of what is already downloaded
+    if let Command::Gallery(args) = &cli.command {
+        let root = downloader::resolve_out_dir(&args.out_dir);
+        let gallery = gallery::write_gallery(&root, args.lang)?;
+        println!(
+            "{} albums, {} photos: {}",
+            gallery.albums,
+            gallery.images,
+            gallery.index.display()
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Command::Thumbnail(args) = &cli.command {
+        let root = downloader::resolve_out_dir(&args.out_dir);
+        let thumbnail =
+            thumbnails::find(&root, &args.album, args.image.as_deref(), args.size)?;
+        return match thumbnail {
+            Some(path) => {
+                println!("{}", path.display());
+                Ok(ExitCode::SUCCESS)
+            }
+            None => Err(match &args.image {
+                Some(image) => format!("Image {} of album {} is not downloaded", image, args.album),
+                None => format!("Nothing of album {} is downloaded", args.album),
+            }
+            .into()),
+        };
+    }
+
+    let credentials = Credentials {
+        email: cli.email.ok_or("Missing --email (or KIDPLAN_EMAIL)")?,
+        password: cli
+            .password
+            .ok_or("Missing --password (or KIDPLAN_PASSWORD)")?,
+    };
+
+    let mut endpoints = Endpoints::default();
+    if let Some(base_url) = &cli.base_url {
+        endpoints = Endpoints::new(base_url, endpoints.image_hosts);
+    }
+    if !cli.image_hosts.is_empty() {
+        endpoints.image_hosts = cli.image_hosts;
+    }
+
+    let state = Arc::new(AppState::with_endpoints(endpoints));
+    let endpoints = &state.endpoints;
+    let client = downloader::build_client(state.cookie_jar.clone());
+    *state.client.lock().await = Some(client.clone());
+
+    match cli.command {
+        Command::ListKindergartens => {
+            let kids = downloader::fetch_kindergarten_ids(&client, endpoints, &credentials).await?;
+            for kid in kids {
+                println!("{}\t{}", kid.id, kid.name);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::ListAlbums(args) => {
+            login(&state, &client, &credentials, args.single()?).await?;
+            let albums = downloader::fetch_albums(&client, endpoints).await?;
+            for album in albums {
+                let count = album
+                    .image_count
+                    .map(|c| c.to_string())
+                    .unwrap_or_else(|| "?".to_string());
+                println!("{}\t{}\t{}", album.id, count, album.title);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Download(args) if args.selection.kid.kid_ids.len() > 1 => {
+            let groups =
+                select_kindergarten_albums(&state, &client, &credentials, &args.selection).await?;
+            let settings = args.settings();
+            cancel_on_ctrl_c(state.clone());
+
+            let result = kindergartens::download_kindergartens(
+                &state,
+                &credentials,
+                groups,
+                settings,
+                &LogReporter,
+            )
+            .await?;
+            Ok(print_result(&result))
+        }
+        Command::Download(args) => {
+            let albums = select_albums(&state, &client, &credentials, &args.selection).await?;
+            let settings = args.settings();
+            cancel_on_ctrl_c(state.clone());
+
+            let result =
+                downloader::download_albums(&state, albums, settings, &LogReporter).await?;
+            Ok(print_result(&result))
+        }
+        Command::Sync(args) => {
+            let albums = select_albums(&state, &client, &credentials, &args.selection).await?;
+            let settings = args.settings();
+            cancel_on_ctrl_c(state.clone());
+
+            let summary = sync::sync_albums(&state, &albums, settings, &LogReporter).await?;
+            println!(
+                "Synced: {} ({} changed, {} unchanged albums; {} skipped, {} failed)",
+                summary,
+                summary.changed_albums,
+                summary.unchanged_albums,
+                summary.result.skipped,
+                summary.result.failed
+            );
+            Ok(if summary.result.failed > 0 {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            })
+        }
+        Command::Schedule(args) => {
+            let selection = &args.download.selection;
+            if !selection.album_ids.is_empty() {
+                return Err("schedule syncs every album; --album is not supported".into());
+            }
+            login(
+                &state,
+                &client,
+                &credentials,
+                selection.kid.kid_ids.first().copied(),
+            )
+            .await?;
+            let settings = args.download.settings();
+            let state_dir = match &args.state_dir {
+                Some(dir) => dir.clone(),
+                None => schedule_dir(&downloader::resolve_out_dir(&settings.out_dir))?,
+            };
+            let scheduler = Scheduler::open(&state_dir)?;
+            // Scheduled syncs run as jobs of the queue, which runs alongside
+            let queue = JobQueue::open(&state_dir)?;
+            let run_queue = queue.run(&state, &LogReporter);
+            scheduler.configure(Some(ScheduleConfig {
+                enabled: true,
+                schedule: args.at.clone(),
+                kid_ids: selection.kid.kid_ids.clone(),
+                settings,
+            }))?;
+            if args.once {
+                let run = tokio::select! {
+                    run = scheduler.run_now(&state, &queue, None) => run,
+                    _ = run_queue => unreachable!("the job queue runs forever"),
+                };
+                return Ok(match run {
+                    Some(run) => print_run(&run),
+                    None => ExitCode::FAILURE,
+                });
+            }
+            println!(
+                "Syncing {}; next run at {}",
+                args.at,
+                scheduler
+                    .next_run()
+                    .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default()
+            );
+            let on_run = |run: &ScheduledRun| {
+                print_run(run);
+            };
+            tokio::join!(scheduler.run(&state, &queue, None, &on_run), run_queue);
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Preview(args) => {
+            let albums = select_albums(&state, &client, &credentials, &args.selection).await?;
+            let kindergarten = state.kindergarten.lock().await.clone();
+            let paths = naming::preview(
+                &args.template,
+                &albums,
+                kindergarten.as_ref().map(|k| k.name.as_str()),
+            )?;
+            for path in paths {
+                println!("{}", path);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Export(args) => {
+            let out_dir = downloader::resolve_out_dir(&args.selection.out_dir);
+            let dest = args.dest.clone().unwrap_or_else(|| out_dir.clone());
+            let albums = select_albums(&state, &client, &credentials, &args.selection).await?;
+            let manifest = Manifest::open(&out_dir)?;
+            cancel_on_ctrl_c(state.clone());
+            let archives = export::zip_albums(
+                &manifest,
+                &albums,
+                &dest,
+                &args.zip_options.settings(),
+                &LogReporter,
+                &state.controls.cancellation(),
+            )?;
+            for archive in &archives {
+                println!("{}", archive.display());
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Verify(args) => {
+            let albums = select_albums(&state, &client, &credentials, &args.selection).await?;
+            let out_dir = downloader::resolve_out_dir(&args.selection.out_dir);
+            let manifest = Manifest::open(&out_dir)?;
+            let mut incomplete = 0;
+            for album in &albums {
+                let entries: Vec<_> = manifest
+                    .entries()
+                    .filter(|e| e.album_id == album.id)
+                    .collect();
+                let failed = entries
+                    .iter()
+                    .filter(|e| e.status == EntryStatus::Failed)
+                    .count();
+                let complete: Vec<_> = entries
+                    .iter()
+                    .filter(|e| e.status == EntryStatus::Complete)
+                    .collect();
+                let mut damaged = 0;
+                for entry in &complete {
+                    let check = manifest.verify(entry);
+                    if check != Verification::Ok {
+                        damaged += 1;
+                        eprintln!("  {:?}: {}", check, entry.path);
+                    }
+                }
+                let expected = album.image_count;
+                let ok = damaged == 0
+                    && failed == 0
+                    && expected.map_or(!complete.is_empty(), |n| complete.len() >= n);
+                if !ok {
+                    incomplete += 1;
+                }
+                let mut notes = Vec::new();
+                if damaged > 0 {
+                    notes.push(format!("{} damaged", damaged));
+                }
+                if failed > 0 {
+                    notes.push(format!("{} failed", failed));
+                }
+                println!(
+                    "{}\t{}\t{}/{}{}\t{}",
+                    if ok { "OK" } else { "INCOMPLETE" },
+                    album.id,
+                    complete.len(),
+                    expected
+                        .map(|n| n.to_string())
+                        .unwrap_or_else(|| "?".to_string()),
+                    if notes.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" ({})", notes.join(", "))
+                    },
+                    album.title
+                );
+            }
+            println!(
+                "{} of {} albums complete",
+                albums.len() - incomplete,
+                albums.len()
+            );
+            Ok(if incomplete > 0 {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            })
+        }
+        Command::Gallery(_) | Command::Thumbnail(_) => {
+            unreachable!("handled before logging in")
+        }
+    }
+}
+
+/// Print the summary of a download and turn it into the exit code.
+fn print_result(result: &DownloadResult) -> ExitCode {
+    println!(
+        "{}: {} albums, {} downloaded, {} skipped ({} duplicates), {} failed",
+        if result.cancelled { "Cancelled" } else { "Done" },
+        result.total_albums,
+        result.total_images,
+        result.skipped,
+        result.duplicates,
+        result.failed
+    );
+    if result.uploaded > 0 {
+        println!("{} uploaded to the photo library", result.uploaded);
+    }
+    if let Some(error) = &result.export_error {
+        eprintln!("Packing the albums as ZIP failed: {}", error);
+    }
+    if result.failed > 0 || result.cancelled || result.export_error.is_some() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// The app data folder for the schedule of the library in `out_dir`, next
+/// to the app's own but apart from it, and named after the library's path
+/// so each library keeps its own schedule.
+fn schedule_dir(
+    out_dir: &std::path::Path,
+) -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    let data_dir = dirs::data_dir().ok_or("no app data directory for this user")?;
+    let library = manifest::sha256_hex(out_dir.to_string_lossy().as_bytes());
+    Ok(data_dir
+        .join("com.kidplan.downloader")
+        .join("cli-schedules")
+        .join(&library[..16]))
+}
+
+/// Print the outcome of a scheduled sync and turn it into the exit code.
+fn print_run(run: &ScheduledRun) -> ExitCode {
+    let at = run
+        .started_at
+        .with_timezone(&chrono::Local)
+        .format("%Y-%m-%d %H:%M");
+    match &run.error {
+        Some(error) => {
+            eprintln!("{} sync failed ({:?}): {}", at, error.code, error.message);
+            ExitCode::FAILURE
+        }
+        None => {
+            println!(
+                "{} sync: {} new albums, {} new photos, {} failed",
+                at, run.new_albums, run.new_images, run.result.failed
+            );
+            if let Some(error) = &run.result.export_error {
+                eprintln!("{} packing the albums as ZIP failed: {}", at, error);
+            }
+            if run.result.failed > 0 || run.result.export_error.is_some() {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            }
+        }
+    }
+}
+
+/// Stop the running download when Ctrl+C is pressed.
+fn cancel_on_ctrl_c(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("Cancelling...");
+            state.controls.cancel();
+        }
+    });
+}
+
+/// Log in to the requested kindergarten, picking the only one when no id is given.
+async fn login(
+    state: &AppState,
+    client: &reqwest::Client,
+    credentials: &Credentials,
+    kid_id: Option<i64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let endpoints = &state.endpoints;
+    let kids = downloader::fetch_kindergarten_ids(client, endpoints, credentials).await?;
+    *state.kindergartens.lock().await = kids.clone();
+    let kid_id = match (kid_id, kids.as_slice()) {
+        (Some(id), _) => id,
+        (None, [only]) => only.id,
+        (None, _) => {
+            return Err(format!(
+                "Account has {} kindergartens; choose one with --kid (see list-kindergartens)",
+                kids.len()
+            )
+            .into())
+        }
+    };
+    downloader::login(client, endpoints, credentials, kid_id).await?;
+    state.select_kindergarten(kid_id).await;
+    *state.credentials.lock().await = Some(credentials.clone());
+    Ok(())
+}
+
+async fn select_albums(
+    state: &AppState,
+    client: &reqwest::Client,
+    credentials: &Credentials,
+    selection: &AlbumSelection,
+) -> Result<Vec<Album>, Box<dyn std::error::Error>> {
+    login(state, client, credentials, selection.kid.single()?).await?;
+    let albums = downloader::fetch_albums(client, &state.endpoints).await?;
+    if selection.album_ids.is_empty() {
+        return Ok(albums);
+    }
+    for id in &selection.album_ids {
+        if !albums.iter().any(|a| &a.id == id) {
+            return Err(format!("Album {} not found", id).into());
+        }
+    }
+    Ok(albums
+        .into_iter()
+        .filter(|a| selection.album_ids.contains(&a.id))
+        .collect())
+}
+
+/// Albums of each of the requested kindergartens, filtered by `--album`.
+async fn select_kindergarten_albums(
+    state: &AppState,
+    client: &reqwest::Client,
+    credentials: &Credentials,
+    selection: &AlbumSelection,
+) -> Result<Vec<KindergartenAlbums>, Box<dyn std::error::Error>> {
+    let kids = downloader::fetch_kindergarten_ids(client, &state.endpoints, credentials).await?;
+    *state.kindergartens.lock().await = kids;
+    let mut groups =
+        kindergartens::fetch_albums(state, credentials, &selection.kid.kid_ids).await?;
+    if selection.album_ids.is_empty() {
+        return Ok(groups);
+    }
+    for id in &selection.album_ids {
+        if !groups.iter().flat_map(|g| &g.albums).any(|a| &a.id == id) {
+            return Err(format!("Album {} not found", id).into());
+        }
+    }
+    for group in &mut groups {
+        group.albums.retain(|a| selection.album_ids.contains(&a.id));
+    }
+    Ok(groups)
+}
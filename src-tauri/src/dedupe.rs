@@ -0,0 +1,176 @@
+use crate::error::{Error, Result};
+use crate::manifest::{write_atomic, EntryStatus, Manifest, ManifestEntry, STATE_DIR};
+use crate::storage::Listing;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const HASH_INDEX_FILE: &str = "hashes.json";
+
+/// What to do with an image whose content is already in the library.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateMode {
+    /// Don't store it again; the manifest points at the existing file.
+    #[default]
+    Skip,
+    /// Hardlink the existing file into the album folder.
+    Hardlink,
+    /// Symlink (relative) to the existing file from the album folder.
+    Symlink,
+}
+
+impl FromStr for DuplicateMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(DuplicateMode::Skip),
+            "hardlink" => Ok(DuplicateMode::Hardlink),
+            "symlink" => Ok(DuplicateMode::Symlink),
+            other => Err(format!(
+                "unknown duplicate mode '{}' (expected skip, hardlink or symlink)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DuplicateMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DuplicateMode::Skip => "skip",
+            DuplicateMode::Hardlink => "hardlink",
+            DuplicateMode::Symlink => "symlink",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedFile {
+    /// Path relative to the library root, with `/` separators.
+    pub path: String,
+    pub size: u64,
+}
+
+/// SHA-256 of every image stored in a library, mapped to the first file
+/// holding that content, so the same photo is detected across albums and runs.
+pub struct HashIndex {
+    root: PathBuf,
+    files: HashMap<String, IndexedFile>,
+}
+
+impl HashIndex {
+    /// Load the index of the library at `manifest.root()`, adding any complete
+    /// manifest entries it does not know yet (e.g. libraries from before the
+    /// index existed, or a run that crashed before saving it).
+    pub fn open(manifest: &Manifest) -> Result<Self> {
+        let root = manifest.root().to_path_buf();
+        let path = index_path(&root);
+        let files = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(Error::fs(&path, e)),
+        };
+        let mut index = Self { root, files };
+        for entry in manifest.entries() {
+            index.insert_entry(entry);
+        }
+        Ok(index)
+    }
+
+    /// The stored file with this content, if it is still present and intact
+    /// according to `stored`, the listing of the library's storage.
+    pub fn find(&self, sha256: &str, stored: &Listing) -> Option<&IndexedFile> {
+        let file = self.files.get(sha256)?;
+        (stored.get(&file.path) == Some(&file.size)).then_some(file)
+    }
+
+    /// Remember `path` as holding `sha256`, unless an intact file already does.
+    pub fn insert(&mut self, sha256: &str, path: &str, size: u64, stored: &Listing) {
+        if self.find(sha256, stored).is_none() {
+            self.files.insert(
+                sha256.to_string(),
+                IndexedFile {
+                    path: path.to_string(),
+                    size,
+                },
+            );
+        }
+    }
+
+    /// Remember a complete manifest entry by the content it was downloaded
+    /// with, unless that is known already.
+    pub fn insert_entry(&mut self, entry: &ManifestEntry) {
+        if entry.status != EntryStatus::Complete {
+            return;
+        }
+        if let Some(sha256) = entry.content_sha256() {
+            self.files
+                .entry(sha256.to_string())
+                .or_insert_with(|| IndexedFile {
+                    path: entry.path.clone(),
+                    size: entry.size,
+                });
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = self.root.join(STATE_DIR);
+        std::fs::create_dir_all(&dir).map_err(|e| Error::fs(&dir, e))?;
+        write_atomic(&index_path(&self.root), &serde_json::to_vec(&self.files)?)
+    }
+}
+
+fn index_path(root: &Path) -> PathBuf {
+    root.join(STATE_DIR).join(HASH_INDEX_FILE)
+}
+
+/// Create `dest` as a hard or symbolic link to `original` (relative to
+/// `root`), replacing whatever is at `dest` in one step.
+pub fn link_duplicate(root: &Path, original: &str, dest: &Path, mode: DuplicateMode) -> Result<()> {
+    let mut tmp = dest.as_os_str().to_owned();
+    tmp.push(".part");
+    let tmp = PathBuf::from(tmp);
+    let _ = std::fs::remove_file(&tmp);
+
+    let linked = match mode {
+        DuplicateMode::Skip => return Ok(()),
+        DuplicateMode::Hardlink => std::fs::hard_link(root.join(original), &tmp),
+        DuplicateMode::Symlink => symlink(&relative_target(root, original, dest), &tmp),
+    };
+    let result = linked.and_then(|_| std::fs::rename(&tmp, dest));
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(Error::fs(dest, e));
+    }
+    Ok(())
+}
+
+/// `original` as seen from the directory containing `dest`, so the library
+/// can be moved without breaking its links.
+fn relative_target(root: &Path, original: &str, dest: &Path) -> PathBuf {
+    let depth = dest
+        .parent()
+        .and_then(|dir| dir.strip_prefix(root).ok())
+        .map(|rel| rel.components().count())
+        .unwrap_or(0);
+    let mut target = PathBuf::new();
+    for _ in 0..depth {
+        target.push("..");
+    }
+    target.extend(original.split('/'));
+    target
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
@@ -1,40 +1,69 @@
+use crate::dedupe::{DuplicateMode, HashIndex};
+use crate::endpoints::Endpoints;
+use crate::error::{Error, ErrorCode, Result};
+use crate::export;
+use crate::manifest::{remove_partial, sha256_hex, Manifest, Resume};
+use crate::metadata::{embed_exif, parse_date, sidecar_path, xmp_sidecar, ImageMetadata};
+use crate::naming::{numbered, PathContext, PathTemplate};
+use crate::progress::ProgressReporter;
+use crate::storage::{Listing, Storage};
+use crate::upload::{Asset, Uploaded};
 use crate::{Album, AppState, Credentials, DownloadProgress, DownloadResult, DownloadSettings, Kindergarten};
+use futures::stream::{self, StreamExt};
+use futures::FutureExt;
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
 use scraper::{Html, Selector};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
-use tauri::{AppHandle, Emitter};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tokio::time::{Duration, Instant};
 use urlencoding::encode;
 
-const LOGIN_URL: &str = "https://app.kidplan.com/LogOn";
-const KINDERGARTEN_IDS_URL: &str = "https://app.kidplan.com/Account/GetKinderGartenIds";
+const UA: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 13_6) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/122.0 Safari/537.36";
+
+/// Build the HTTP client used for every Kidplan request, sharing `jar` so the
+/// session cookies survive between calls.
+pub fn build_client(jar: std::sync::Arc<reqwest::cookie::Jar>) -> reqwest::Client {
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_static(UA));
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .cookie_provider(jar)
+        .redirect(reqwest::redirect::Policy::limited(10))
+        .build()
+        .expect("Failed to build HTTP client")
+}
 
 pub async fn fetch_kindergarten_ids(
     client: &reqwest::Client,
+    endpoints: &Endpoints,
     creds: &Credentials,
-) -> Result<Vec<Kindergarten>, String> {
+) -> Result<Vec<Kindergarten>> {
     let url = format!(
         "{}?username={}&password={}",
-        KINDERGARTEN_IDS_URL,
+        endpoints.kindergarten_ids_url(),
         encode(&creds.email),
         encode(&creds.password)
     );
-    let resp = client.get(&url).send().await.map_err(|e| e.to_string())?;
+    let resp = client.get(&url).send().await?;
     if !resp.status().is_success() {
-        return Err(format!("Kindergarten lookup failed: {}", resp.status()));
+        return Err(Error::http(resp.status(), "Kindergarten lookup"));
     }
-    let kids: Vec<Kindergarten> = resp.json().await.map_err(|e| e.to_string())?;
+    let kids: Vec<Kindergarten> = resp.json().await?;
     if kids.is_empty() {
-        return Err("Login failed: no kindergarten IDs returned. Check credentials.".to_string());
+        // Kidplan answers unknown credentials with an empty list
+        return Err(Error::InvalidCredentials);
     }
     Ok(kids)
 }
 
 pub async fn login(
     client: &reqwest::Client,
+    endpoints: &Endpoints,
     creds: &Credentials,
     kid_id: i64,
-) -> Result<(), String> {
-    let url = format!("{}?kid={}", LOGIN_URL, kid_id);
+) -> Result<()> {
+    let url = format!("{}?kid={}", endpoints.login_url(), kid_id);
     let params = [
         ("UserName", creds.email.as_str()),
         ("Password", creds.password.as_str()),
@@ -44,10 +73,13 @@ pub async fn login(
         .post(&url)
         .form(&params)
         .send()
-        .await
-        .map_err(|e| e.to_string())?;
-    if !resp.status().is_success() {
-        return Err(format!("Login failed: {}", resp.status()));
+        .await?;
+    let status = resp.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        return Err(Error::InvalidCredentials);
+    }
+    if !status.is_success() {
+        return Err(Error::http(status, "Login"));
     }
     Ok(())
 }
@@ -62,11 +94,44 @@ struct AlbumJson {
     album_url: Option<String>,
     #[serde(alias = "NumberOfImages")]
     number_of_images: Option<usize>,
+    #[serde(flatten)]
+    other: serde_json::Map<String, serde_json::Value>,
+}
+
+// Fields of `GetAlbumsAsJson` that may hold each piece of album metadata,
+// most specific first.
+const ALBUM_DATE_KEYS: &[&str] = &["AlbumDate", "Date", "EventDate", "CreatedDate", "Created"];
+const ALBUM_DESCRIPTION_KEYS: &[&str] = &["Description", "AlbumDescription", "Text"];
+const ALBUM_COVER_KEYS: &[&str] = &["CoverImageUrl", "CoverUrl", "ThumbnailUrl", "ImageUrl"];
+const ALBUM_AUTHOR_KEYS: &[&str] = &["Author", "AuthorName", "CreatedByName", "CreatedBy"];
+const ALBUM_DEPARTMENT_KEYS: &[&str] = &["Department", "DepartmentName", "GroupName"];
+
+/// Remove `keys` from `fields`, returning the first usable value among them.
+fn take_field<T>(
+    fields: &mut serde_json::Map<String, serde_json::Value>,
+    keys: &[&str],
+    parse: impl Fn(&serde_json::Value) -> Option<T>,
+) -> Option<T> {
+    keys.iter()
+        .filter_map(|key| fields.remove(*key))
+        .fold(None, |found, value| found.or_else(|| parse(&value)))
+}
+
+fn text_value(value: &serde_json::Value) -> Option<String> {
+    let text = match value {
+        serde_json::Value::String(s) => s.trim().to_string(),
+        serde_json::Value::Number(n) => n.to_string(),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
 }
 
-pub async fn fetch_albums(client: &reqwest::Client) -> Result<Vec<Album>, String> {
-    let base_url = "https://app.kidplan.com/bilder/album";
-    let json_url = "https://app.kidplan.com/bilder/GetAlbumsAsJson";
+pub async fn fetch_albums(
+    client: &reqwest::Client,
+    endpoints: &Endpoints,
+) -> Result<Vec<Album>> {
+    let base_url = endpoints.album_base_url();
+    let json_url = endpoints.albums_json_url();
     let mut albums = Vec::new();
     let mut seen_ids = HashSet::new();
     let mut skip = 0;
@@ -81,16 +146,21 @@ pub async fn fetch_albums(client: &reqwest::Client) -> Result<Vec<Album>, String
             "{}?take={}&skip={}&noCache={}",
             json_url, page_size, skip, ts
         );
-        let resp = client.get(&url).send().await.map_err(|e| e.to_string())?;
-        if !resp.status().is_success() {
-            return Err(format!("Album JSON fetch failed: {}", resp.status()));
+        let resp = client.get(&url).send().await?;
+        let status = resp.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return Err(Error::SessionExpired);
         }
-        let data: Vec<AlbumJson> = resp.json().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(Error::http(status, "Album list"));
+        }
+        let data: Vec<AlbumJson> = resp.json().await?;
         if data.is_empty() {
             break;
         }
+        let received = data.len();
         let mut new_items = 0;
-        for item in &data {
+        for mut item in data {
             let id = item.album_id.clone().unwrap_or_default();
             if id.is_empty() || seen_ids.contains(&id) {
                 continue;
@@ -99,39 +169,46 @@ pub async fn fetch_albums(client: &reqwest::Client) -> Result<Vec<Album>, String
             let raw_url = item.album_url.clone().unwrap_or_default();
             
             // Use normalize_url to properly resolve relative/absolute URLs
-            let album_url = normalize_url(&raw_url, base_url);
+            let album_url = normalize_url(&raw_url, &base_url);
             
             albums.push(Album {
                 id,
                 title: item.title.clone().unwrap_or_else(|| "Untitled".to_string()),
                 url: album_url,
                 image_count: item.number_of_images,
+                date: take_field(&mut item.other, ALBUM_DATE_KEYS, parse_date),
+                description: take_field(&mut item.other, ALBUM_DESCRIPTION_KEYS, text_value),
+                cover_url: take_field(&mut item.other, ALBUM_COVER_KEYS, text_value)
+                    .map(|url| normalize_url(&url, &base_url)),
+                author: take_field(&mut item.other, ALBUM_AUTHOR_KEYS, text_value),
+                department: take_field(&mut item.other, ALBUM_DEPARTMENT_KEYS, text_value),
+                extra: item.other,
             });
             new_items += 1;
         }
-        if new_items == 0 || data.len() < page_size {
+        if new_items == 0 || received < page_size {
             break;
         }
-        skip += data.len();
+        skip += received;
     }
     Ok(albums)
 }
 
-fn is_album_image_url(url: &str) -> bool {
+fn is_album_image_url(url: &str, endpoints: &Endpoints) -> bool {
     if let Ok(parsed) = url::Url::parse(url) {
         if let Some(host) = parsed.host_str() {
-            return host.ends_with("img.kidplan.com")
+            return endpoints.is_image_host(host)
                 && parsed.path().starts_with("/albumpicture/");
         }
     }
     false
 }
 
-fn upgrade_image_url(url: &str) -> String {
+fn upgrade_image_url(url: &str, endpoints: &Endpoints) -> String {
     let decoded = html_escape::decode_html_entities(url).to_string();
     if let Ok(mut parsed) = url::Url::parse(&decoded) {
         if let Some(host) = parsed.host_str() {
-            if host.ends_with("img.kidplan.com")
+            if endpoints.is_image_host(host)
                 && parsed.path().starts_with("/albumpicture/")
             {
                 // Remove size parameter to get full resolution
@@ -154,10 +231,10 @@ fn upgrade_image_url(url: &str) -> String {
     decoded
 }
 
-fn extract_image_id(url: &str) -> Option<String> {
+fn extract_image_id(url: &str, endpoints: &Endpoints) -> Option<String> {
     if let Ok(parsed) = url::Url::parse(url) {
         if let Some(host) = parsed.host_str() {
-            if host.ends_with("img.kidplan.com")
+            if endpoints.is_image_host(host)
                 && parsed.path().starts_with("/albumpicture/")
             {
                 for (k, v) in parsed.query_pairs() {
@@ -181,7 +258,7 @@ fn get_image_extension(url: &str) -> String {
     ".jpg".to_string()
 }
 
-pub fn extract_image_urls(html_text: &str, _base_url: &str) -> Vec<String> {
+pub fn extract_image_urls(html_text: &str, _base_url: &str, endpoints: &Endpoints) -> Vec<String> {
     let document = Html::parse_document(html_text);
     let mut urls = HashSet::new();
 
@@ -199,21 +276,18 @@ pub fn extract_image_urls(html_text: &str, _base_url: &str) -> Vec<String> {
             if let Some(val) = el.value().attr(attr) {
                 let decoded = html_escape::decode_html_entities(val).to_string();
                 let full = normalize_url(&decoded, _base_url);
-                if is_album_image_url(&full) {
-                    urls.insert(upgrade_image_url(&full));
+                if is_album_image_url(&full, endpoints) {
+                    urls.insert(upgrade_image_url(&full, endpoints));
                 }
             }
         }
         // srcset
         if let Some(srcset) = el.value().attr("srcset") {
             for part in srcset.split(',') {
-                let candidate = part.trim().split_whitespace().next().unwrap_or("");
-                let full = normalize_url(
-                    &html_escape::decode_html_entities(candidate).to_string(),
-                    _base_url,
-                );
-                if is_album_image_url(&full) {
-                    urls.insert(upgrade_image_url(&full));
+                let candidate = part.split_whitespace().next().unwrap_or("");
+                let full = normalize_url(&html_escape::decode_html_entities(candidate), _base_url);
+                if is_album_image_url(&full, endpoints) {
+                    urls.insert(upgrade_image_url(&full, endpoints));
                 }
             }
         }
@@ -225,8 +299,8 @@ pub fn extract_image_urls(html_text: &str, _base_url: &str) -> Vec<String> {
         if let Some(href) = el.value().attr("href") {
             let decoded = html_escape::decode_html_entities(href).to_string();
             let full = normalize_url(&decoded, _base_url);
-            if is_album_image_url(&full) {
-                urls.insert(upgrade_image_url(&full));
+            if is_album_image_url(&full, endpoints) {
+                urls.insert(upgrade_image_url(&full, endpoints));
             }
         }
     }
@@ -235,8 +309,8 @@ pub fn extract_image_urls(html_text: &str, _base_url: &str) -> Vec<String> {
     let re = regex::Regex::new(r#"https?://[^"'\s>]+"#).unwrap();
     for m in re.find_iter(html_text) {
         let url = html_escape::decode_html_entities(m.as_str()).to_string();
-        if is_album_image_url(&url) {
-            urls.insert(upgrade_image_url(&url));
+        if is_album_image_url(&url, endpoints) {
+            urls.insert(upgrade_image_url(&url, endpoints));
         }
     }
 
@@ -245,6 +319,62 @@ pub fn extract_image_urls(html_text: &str, _base_url: &str) -> Vec<String> {
     sorted
 }
 
+/// Captions shown with album images, keyed by full-size image URL as
+/// returned from [`extract_image_urls`].
+///
+/// A caption is taken from `data-caption` or `title` on the image or its
+/// link, a surrounding `<figure>`'s `<figcaption>`, or failing those the
+/// image's `alt` text.
+pub fn extract_image_captions(
+    html_text: &str,
+    base_url: &str,
+    endpoints: &Endpoints,
+) -> HashMap<String, String> {
+    let document = Html::parse_document(html_text);
+    let img_sel = Selector::parse("img").unwrap();
+    let caption_sel = Selector::parse("figcaption").unwrap();
+    let mut captions = HashMap::new();
+
+    for img in document.select(&img_sel) {
+        let link = img
+            .ancestors()
+            .filter_map(scraper::ElementRef::wrap)
+            .find(|el| el.value().name() == "a");
+        let full_url = link
+            .and_then(|a| a.value().attr("href"))
+            .into_iter()
+            .chain(img.value().attr("src"))
+            .map(|u| normalize_url(&html_escape::decode_html_entities(u), base_url))
+            .find(|u| is_album_image_url(u, endpoints));
+        let Some(full_url) = full_url else { continue };
+
+        let attr = |name: &str| {
+            img.value()
+                .attr(name)
+                .or_else(|| link.and_then(|a| a.value().attr(name)))
+        };
+        let figcaption = img
+            .ancestors()
+            .filter_map(scraper::ElementRef::wrap)
+            .find(|el| el.value().name() == "figure")
+            .and_then(|figure| figure.select(&caption_sel).next())
+            .map(|c| c.text().collect::<String>());
+        let caption = attr("data-caption")
+            .map(str::to_string)
+            .or_else(|| attr("title").map(str::to_string))
+            .or(figcaption)
+            .or_else(|| img.value().attr("alt").map(str::to_string))
+            .map(|c| c.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|c| !c.is_empty());
+        if let Some(caption) = caption {
+            captions
+                .entry(upgrade_image_url(&full_url, endpoints))
+                .or_insert(caption);
+        }
+    }
+    captions
+}
+
 fn normalize_url(url: &str, base: &str) -> String {
     let trimmed = url.trim();
     if trimmed.is_empty() {
@@ -265,7 +395,21 @@ fn normalize_url(url: &str, base: &str) -> String {
     trimmed.to_string()
 }
 
-fn slugify(value: &str) -> String {
+/// Resolve the configured output directory, placing relative paths under the
+/// user's Downloads folder (or home dir as a fallback).
+pub fn resolve_out_dir(out_dir: &str) -> PathBuf {
+    let raw = PathBuf::from(out_dir);
+    if raw.is_absolute() {
+        raw
+    } else {
+        dirs::download_dir()
+            .or_else(dirs::home_dir)
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(raw)
+    }
+}
+
+pub fn slugify(value: &str) -> String {
     let mut slug = String::new();
     for ch in value.trim().chars() {
         if ch.is_ascii_alphanumeric() {
@@ -282,70 +426,94 @@ fn slugify(value: &str) -> String {
     collapsed.trim_matches('-').to_string()
 }
 
+/// Download the given albums using the session held in `state`.
+///
+/// Every progress update is passed to `reporter`, so the same engine can drive
+/// the GUI, the CLI or a test harness.
 pub async fn download_albums(
-    app: &AppHandle,
     state: &AppState,
     albums: Vec<Album>,
     settings: DownloadSettings,
-) -> Result<DownloadResult, String> {
+    reporter: &dyn ProgressReporter,
+) -> Result<DownloadResult> {
     eprintln!("[DEBUG] download_albums called with {} albums, settings: {:?}", albums.len(), settings);
 
     let client_guard = state.client.lock().await;
     let client = client_guard
         .as_ref()
-        .ok_or("Not logged in")?
+        .ok_or(Error::NotLoggedIn)?
         .clone();
     drop(client_guard);
     eprintln!("[DEBUG] Got client, proceeding...");
 
-    let mut seen_urls: HashSet<String> = HashSet::new();
     let mut total_downloaded = 0usize;
     let mut total_skipped = 0usize;
     let mut total_failed = 0usize;
+    let mut total_duplicates = 0usize;
+    let mut total_uploaded = 0usize;
+    let concurrency = settings.concurrency.clamp(1, MAX_CONCURRENCY);
+    let limiter = RateLimiter::new(Duration::from_millis(settings.delay_ms));
+    let template = settings
+        .path_template
+        .as_deref()
+        .filter(|t| !t.trim().is_empty())
+        .map(str::parse::<PathTemplate>)
+        .transpose()?;
+    let kindergarten = state.kindergarten.lock().await.as_ref().map(|k| k.name.clone());
+    let today = chrono::Local::now().date_naive();
+    let mut claimed: HashMap<PathBuf, (String, String)> = HashMap::new();
+    let relogin = Relogin::new();
+    let pause = PauseGate::default();
+    let cancel = state.controls.cancellation();
+    // Albums reached before a cancel stopped the download
+    let mut stopped_at = None;
 
-    // Ensure output directory — resolve relative paths against home dir
-    let out_dir = {
-        let raw = PathBuf::from(&settings.out_dir);
-        if raw.is_absolute() {
-            raw
-        } else {
-            dirs::download_dir()
-                .or_else(dirs::home_dir)
-                .unwrap_or_else(|| PathBuf::from("."))
-                .join(raw)
-        }
-    };
+    // Ensure output directory
+    let out_dir = resolve_out_dir(&settings.out_dir);
     eprintln!("[DEBUG] Output directory: {:?}", out_dir);
-    std::fs::create_dir_all(&out_dir).map_err(|e| format!("Failed to create output dir {:?}: {}", out_dir, e))?;
+    std::fs::create_dir_all(&out_dir).map_err(|e| Error::fs(&out_dir, e))?;
+    let manifest = Manifest::open(&out_dir)?;
+    let mut hashes = HashIndex::open(&manifest)?;
+    let manifest = std::sync::Mutex::new(manifest);
+    let storage = settings.storage.open(&out_dir)?;
+    eprintln!("[DEBUG] Storing images in {}", storage.describe());
+    // Listed once up front, so telling what is already stored takes one
+    // request rather than one per image
+    let mut listing = storage.list().await?;
+    let library = settings.upload.as_ref().map(|config| config.open());
 
     for (album_idx, album) in albums.iter().enumerate() {
-        // Check cancel flag
-        {
-            let cancel = state.cancel_flag.lock().await;
-            if *cancel {
-                break;
-            }
+        pause
+            .wait(state, reporter, || DownloadProgress {
+                album_title: album.title.clone(),
+                album_index: album_idx + 1,
+                album_total: albums.len(),
+                image_index: 0,
+                image_total: 0,
+                filename: String::new(),
+                status: String::new(),
+                error_type: None,
+                paused: true,
+                result: None,
+                job_id: None,
+            })
+            .await;
+        if cancel.is_cancelled() {
+            stopped_at = Some(album_idx);
+            break;
         }
 
         eprintln!("[DEBUG] Fetching album {}/{}: {} -> {}", album_idx + 1, albums.len(), album.title, album.url);
 
-        // Fetch album page
-        let resp = client
-            .get(&album.url)
-            .send()
-            .await
-            .map_err(|e| e.to_string())?;
-        let status = resp.status();
-        let final_url = resp.url().to_string();
-        let album_html = resp.text().await.map_err(|e| e.to_string())?;
-
-        eprintln!("[DEBUG] Album page response: status={}, final_url={}, html_len={}", status, final_url, album_html.len());
-
-        // Check if we got redirected to a login page
-        let lower_html = album_html.to_lowercase();
-        if lower_html.contains("log in kidplan") || lower_html.contains("id=\"loginform\"") {
-            eprintln!("[DEBUG] WARNING: Album page looks like a login page! Session may have expired.");
-            let _ = app.emit("download-progress", DownloadProgress {
+        let page = cancel
+            .run_until_cancelled(fetch_album_page(state, &client, album, &relogin))
+            .await;
+        let Some(page) = page else {
+            stopped_at = Some(album_idx);
+            break;
+        };
+        let Some(album_html) = page? else {
+            reporter.report(&DownloadProgress {
                 album_title: album.title.clone(),
                 album_index: album_idx + 1,
                 album_total: albums.len(),
@@ -353,13 +521,21 @@ pub async fn download_albums(
                 image_total: 0,
                 filename: String::new(),
                 status: "failed: session expired (redirected to login page)".to_string(),
-                error_type: Some("session_expired".to_string()),
+                error_type: Some(ErrorCode::SessionExpired),
+                paused: false,
+                result: None,
+                job_id: None,
             });
             total_failed += 1;
             continue;
-        }
+        };
 
-        let image_urls = extract_image_urls(&album_html, &album.url);
+        let image_urls = extract_image_urls(&album_html, &album.url, &state.endpoints);
+        let captions = if settings.write_xmp {
+            extract_image_captions(&album_html, &album.url, &state.endpoints)
+        } else {
+            HashMap::new()
+        };
 
         eprintln!("[DEBUG] Extracted {} image URLs from album '{}'", image_urls.len(), album.title);
         if image_urls.is_empty() {
@@ -371,7 +547,7 @@ pub async fn download_albums(
         }
 
         // Emit album start event
-        let _ = app.emit("download-progress", DownloadProgress {
+        reporter.report(&DownloadProgress {
             album_title: album.title.clone(),
             album_index: album_idx + 1,
             album_total: albums.len(),
@@ -380,6 +556,9 @@ pub async fn download_albums(
             filename: String::new(),
             status: format!("scanning: found {} images", image_urls.len()),
             error_type: None,
+            paused: false,
+            result: None,
+            job_id: None,
         });
 
         let limited = if settings.limit_per_album > 0 && image_urls.len() > settings.limit_per_album {
@@ -389,228 +568,873 @@ pub async fn download_albums(
         };
 
         let album_dir = out_dir.join(slugify(&album.title));
-        std::fs::create_dir_all(&album_dir).map_err(|e| e.to_string())?;
+        let album_meta = ImageMetadata {
+            date: album.date,
+            album_id: album.id.clone(),
+            album_title: album.title.clone(),
+            kindergarten: kindergarten.clone(),
+            ..ImageMetadata::default()
+        };
 
-        for (img_idx, image_url) in limited.iter().enumerate() {
-            // Check cancel flag
-            {
-                let cancel = state.cancel_flag.lock().await;
-                if *cancel {
-                    break;
+        let image_total = limited.len();
+        let mut planned = Vec::with_capacity(limited.len());
+        let jobs: Vec<_> = limited
+            .iter()
+            .enumerate()
+            .map(|(img_idx, image_url)| {
+                // Naming and the manifest lookup run here, in album order, so
+                // the result does not depend on how many downloads are in
+                // flight
+                let image_id = image_key(image_url, &state.endpoints);
+                let path = match &template {
+                    Some(template) => {
+                        let (id, ext) = image_id_and_ext(image_url, img_idx, &state.endpoints);
+                        out_dir.join(template.render(&PathContext {
+                            album,
+                            kindergarten: kindergarten.as_deref(),
+                            image_id: &id,
+                            index: img_idx + 1,
+                            ext: &ext,
+                            date: album.date.map_or(today, |d| d.date()),
+                        }))
+                    }
+                    None => album_dir.join(image_filename(image_url, img_idx, &state.endpoints)),
+                };
+                let manifest = manifest.lock().unwrap();
+                let dest_path = unique_path(path, &album.id, &image_id, &mut claimed, &manifest);
+                let resume = manifest.resume_state(&album.id, &image_id, &listing);
+                let rel_path = manifest.relative(&dest_path);
+                drop(manifest);
+                let stored = listing.contains_key(&rel_path);
+                planned.push(dest_path.clone());
+                let job = ImageJob {
+                    index: img_idx,
+                    url: image_url,
+                    image_id,
+                    filename: dest_path
+                        .file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    dest_path,
+                    rel_path,
+                };
+                let client = &client;
+                let limiter = &limiter;
+                let relogin = &relogin;
+                let pause = &pause;
+                let cancel = &cancel;
+                let album_total = albums.len();
+                async move {
+                    pause
+                        .wait(state, reporter, || DownloadProgress {
+                            album_title: album.title.clone(),
+                            album_index: album_idx + 1,
+                            album_total,
+                            image_index: job.index,
+                            image_total,
+                            filename: String::new(),
+                            status: String::new(),
+                            error_type: None,
+                            paused: true,
+                            result: None,
+                            job_id: None,
+                        })
+                        .await;
+                    let outcome = if cancel.is_cancelled() {
+                        ImageOutcome::Cancelled
+                    } else if resume == Resume::Complete {
+                        ImageOutcome::Skipped("skipped: already downloaded")
+                    } else if resume == Resume::Untracked && stored {
+                        // Downloaded before the manifest existed
+                        ImageOutcome::Existing
+                    } else {
+                        // Dropping the fetch on cancel aborts its request or
+                        // backoff wait
+                        cancel
+                            .run_until_cancelled(fetch_and_hash(
+                                state,
+                                client,
+                                &job,
+                                image_total,
+                                limiter,
+                                relogin,
+                            ))
+                            .await
+                            .unwrap_or(ImageOutcome::Cancelled)
+                    };
+                    (job, outcome)
                 }
-            }
+                .boxed()
+            })
+            .collect();
+        // The jobs are prepared up front and boxed, which keeps the download
+        // future `Send` for the GUI's commands
+        let mut outcomes = stream::iter(jobs).buffered(concurrency);
 
-            // Dedupe
-            if seen_urls.contains(image_url) {
-                total_skipped += 1;
-                let _ = app.emit("download-progress", DownloadProgress {
+        let mut album_uploads = Vec::new();
+
+        // Outcomes arrive in album order, so progress is reported in order
+        // too, and the first copy of a photo in album order is the one stored
+        while let Some((job, outcome)) = outcomes.next().await {
+            let mut filename = job.filename.clone();
+            let mut upload_failure = None;
+            let mut upload_cancelled = false;
+            let (status, error_type) = match outcome {
+                ImageOutcome::Cancelled => {
+                    stopped_at = Some(album_idx + 1);
+                    // Nothing is half written by this run, since files are
+                    // renamed into place whole, but an earlier run killed
+                    // mid-write may have left pieces of the images not done
+                    for path in &planned[job.index..] {
+                        remove_partial(path);
+                    }
+                    break;
+                }
+                ImageOutcome::Fetched { bytes, sha256: content_sha256 } => {
+                    let meta = ImageMetadata {
+                        image_id: job.image_id.clone(),
+                        source_url: job.url.to_string(),
+                        caption: captions.get(job.url).cloned(),
+                        ..album_meta.clone()
+                    };
+                    // Dropping the write on cancel aborts its upload, as
+                    // with the fetch; remote storages move a file into place
+                    // only once it is complete
+                    let stored = cancel
+                        .run_until_cancelled(store_image(
+                            storage.as_ref(),
+                            &mut listing,
+                            &hashes,
+                            &job,
+                            &bytes,
+                            &content_sha256,
+                            &settings,
+                            &meta,
+                        ))
+                        .await;
+                    let Some(stored) = stored else {
+                        stopped_at = Some(album_idx + 1);
+                        for path in &planned[job.index..] {
+                            remove_partial(path);
+                        }
+                        break;
+                    };
+                    // Duplicates go too, so the library puts them in this
+                    // album as well; it recognises their content
+                    if let (Ok(_), Some(library)) = (&stored, &library) {
+                        let asset = Asset {
+                            album_title: &album.title,
+                            id: &format!("{}/{}", album.id, job.image_id),
+                            filename: &job.filename,
+                            bytes: &bytes,
+                            taken: album.date,
+                        };
+                        match cancel.run_until_cancelled(library.upload(&asset)).await {
+                            Some(Ok(uploaded)) => {
+                                if matches!(uploaded, Uploaded::Created(_)) {
+                                    total_uploaded += 1;
+                                }
+                                album_uploads.push(uploaded);
+                            }
+                            Some(Err(e)) => {
+                                upload_failure = Some((
+                                    format!("failed: upload to {} - {}", library.describe(), e),
+                                    e.code(),
+                                ));
+                            }
+                            // The image is stored, so it is recorded before
+                            // the download stops
+                            None => upload_cancelled = true,
+                        }
+                    }
+                    let mut manifest = manifest.lock().unwrap();
+                    match stored {
+                        Ok(Stored::Written { size, sha256 }) => {
+                            total_downloaded += 1;
+                            hashes.insert(&content_sha256, &job.rel_path, size, &listing);
+                            manifest.record_complete(
+                                &album.id,
+                                &job.image_id,
+                                job.url,
+                                &job.dest_path,
+                                size,
+                                sha256,
+                                content_sha256,
+                            )?;
+                            ("downloaded".to_string(), None)
+                        }
+                        Ok(Stored::Duplicate { original, size }) => {
+                            total_skipped += 1;
+                            total_duplicates += 1;
+                            let sha256 = manifest
+                                .entry_at(&original)
+                                .and_then(|e| e.sha256.clone())
+                                .unwrap_or_else(|| content_sha256.clone());
+                            // When skipping, the manifest entry points at the
+                            // stored copy so later runs see the image as done
+                            let path = match settings.duplicates {
+                                DuplicateMode::Skip => {
+                                    filename.clear();
+                                    manifest.root().join(&original)
+                                }
+                                _ => job.dest_path.clone(),
+                            };
+                            manifest.record_complete(
+                                &album.id,
+                                &job.image_id,
+                                job.url,
+                                &path,
+                                size,
+                                sha256,
+                                content_sha256,
+                            )?;
+                            let how = match settings.duplicates {
+                                DuplicateMode::Skip => "",
+                                DuplicateMode::Hardlink => " (hardlinked)",
+                                DuplicateMode::Symlink => " (symlinked)",
+                            };
+                            (format!("skipped: duplicate of {}{}", original, how), None)
+                        }
+                        Err(e) => {
+                            total_failed += 1;
+                            manifest.record_failed(&album.id, &job.image_id, job.url, &job.dest_path)?;
+                            (
+                                format!("failed: file write error - {}", e),
+                                Some(ErrorCode::FilesystemError),
+                            )
+                        }
+                    }
+                }
+                ImageOutcome::Existing => {
+                    total_skipped += 1;
+                    let mut manifest = manifest.lock().unwrap();
+                    if settings.storage.is_local() {
+                        manifest.adopt(&album.id, &job.image_id, job.url, &job.dest_path)?;
+                    } else {
+                        let size = listing.get(&job.rel_path).copied().unwrap_or_default();
+                        manifest.adopt_stored(&album.id, &job.image_id, job.url, &job.dest_path, size)?;
+                    }
+                    if let Some(entry) = manifest.get(&album.id, &job.image_id) {
+                        hashes.insert_entry(entry);
+                    }
+                    ("skipped: file exists".to_string(), None)
+                }
+                ImageOutcome::Skipped(reason) => {
+                    total_skipped += 1;
+                    (reason.to_string(), None)
+                }
+                ImageOutcome::Failed(failure) => {
+                    total_failed += 1;
+                    manifest.lock().unwrap().record_failed(
+                        &album.id,
+                        &job.image_id,
+                        job.url,
+                        &job.dest_path,
+                    )?;
+                    (failure.status, Some(failure.error_type))
+                }
+            };
+            reporter.report(&DownloadProgress {
+                album_title: album.title.clone(),
+                album_index: album_idx + 1,
+                album_total: albums.len(),
+                image_index: job.index + 1,
+                image_total,
+                filename: filename.clone(),
+                status,
+                error_type,
+                paused: false,
+                result: None,
+                job_id: None,
+            });
+            if let Some((status, code)) = upload_failure {
+                reporter.report(&DownloadProgress {
                     album_title: album.title.clone(),
                     album_index: album_idx + 1,
                     album_total: albums.len(),
-                    image_index: img_idx + 1,
-                    image_total: limited.len(),
-                    filename: String::new(),
-                    status: "skipped: duplicate".to_string(),
-                    error_type: None,
+                    image_index: job.index + 1,
+                    image_total,
+                    filename,
+                    status,
+                    error_type: Some(code),
+                    paused: false,
+                    result: None,
+                    job_id: None,
                 });
-                continue;
             }
-            seen_urls.insert(image_url.clone());
-
-            // Build filename
-            let image_id = extract_image_id(image_url);
-            let filename = if let Some(ref id) = image_id {
-                // ID already contains extension (e.g., "abc123.jpeg"), so just use it as-is
-                if id.contains('.') {
-                    format!("id-{}", id)
-                } else {
-                    // Fallback: add extension if ID doesn't have one
-                    format!("id-{}{}", id, get_image_extension(image_url))
+            if upload_cancelled {
+                stopped_at = Some(album_idx + 1);
+                for path in &planned[job.index + 1..] {
+                    remove_partial(path);
+                }
+                break;
+            }
+        }
+        if let Some(library) = library.as_ref().filter(|_| !album_uploads.is_empty()) {
+            let created = album_uploads
+                .iter()
+                .filter(|u| matches!(u, Uploaded::Created(_)))
+                .count();
+            let added = cancel
+                .run_until_cancelled(library.add_to_album(&album.title, &album_uploads))
+                .await;
+            let (status, error_type) = match added {
+                Some(Ok(())) => (
+                    format!(
+                        "uploaded: {} new, {} already in {}",
+                        created,
+                        album_uploads.len() - created,
+                        library.describe()
+                    ),
+                    None,
+                ),
+                Some(Err(e)) => (
+                    format!("failed: adding photos to the album in {} - {}", library.describe(), e),
+                    Some(e.code()),
+                ),
+                // The album's own manifest is still written below
+                None => {
+                    stopped_at = Some(album_idx + 1);
+                    (format!("cancelled: adding photos to the album in {}", library.describe()), None)
                 }
-            } else {
-                let digest = format!("{:x}", md5_hash(image_url));
-                format!("image-{:04}-{}.jpg", img_idx + 1, &digest[..10])
             };
+            reporter.report(&DownloadProgress {
+                album_title: album.title.clone(),
+                album_index: album_idx + 1,
+                album_total: albums.len(),
+                image_index: 0,
+                image_total,
+                filename: String::new(),
+                status,
+                error_type,
+                paused: false,
+                result: None,
+                job_id: None,
+            });
+        }
+        let written = manifest
+            .lock()
+            .unwrap()
+            .write_album_manifest(album, kindergarten.as_deref())?;
+        if let Some(path) = written {
+            eprintln!("[DEBUG] Wrote album manifest {:?}", path);
+            // The local copy stays with the library's bookkeeping
+            if !settings.storage.is_local() {
+                let bytes = std::fs::read(&path).map_err(|e| Error::fs(&path, e))?;
+                let rel_path = manifest.lock().unwrap().relative(&path);
+                let Some(put) = cancel.run_until_cancelled(storage.put(&rel_path, bytes)).await else {
+                    stopped_at = Some(album_idx + 1);
+                    break;
+                };
+                put?;
+            }
+        }
+    }
 
-            let dest_path = album_dir.join(&filename);
+    let mut manifest = manifest.into_inner().unwrap();
+    manifest.compact()?;
+    hashes.save()?;
+    let mut export_error = None;
+    if let Some(zip) = settings.zip.clone().filter(|_| stopped_at.is_none()) {
+        if settings.storage.is_local() {
+            // The images are downloaded either way, so the counts are kept
+            let zipped = export::zip_albums_in_background(
+                manifest,
+                albums.clone(),
+                out_dir.clone(),
+                zip,
+                reporter,
+                cancel.clone(),
+            )
+            .await;
+            if let Err(e) = zipped {
+                eprintln!("[DEBUG] Could not zip the albums: {}", e);
+                if matches!(e, Error::Cancelled) {
+                    stopped_at = Some(albums.len());
+                }
+                export_error = Some(e.to_string());
+            }
+        } else {
+            eprintln!("[DEBUG] Images are in {}, not zipping them", storage.describe());
+        }
+    }
 
-            // Skip if exists
-            if dest_path.exists() {
-                total_skipped += 1;
-                let _ = app.emit("download-progress", DownloadProgress {
-                    album_title: album.title.clone(),
-                    album_index: album_idx + 1,
-                    album_total: albums.len(),
-                    image_index: img_idx + 1,
-                    image_total: limited.len(),
-                    filename: filename.clone(),
-                    status: "skipped: file exists".to_string(),
-                    error_type: None,
-                });
-                continue;
+    let result = DownloadResult {
+        total_albums: stopped_at.unwrap_or(albums.len()),
+        total_images: total_downloaded,
+        skipped: total_skipped,
+        failed: total_failed,
+        duplicates: total_duplicates,
+        uploaded: total_uploaded,
+        cancelled: stopped_at.is_some(),
+        export_error,
+    };
+    if let Some(reached) = stopped_at {
+        eprintln!("[DEBUG] Download cancelled after {} of {} albums", reached, albums.len());
+        reporter.report(&DownloadProgress {
+            album_title: reached
+                .checked_sub(1)
+                .map(|i| albums[i].title.clone())
+                .unwrap_or_default(),
+            album_index: reached,
+            album_total: albums.len(),
+            image_index: 0,
+            image_total: 0,
+            filename: String::new(),
+            status: "cancelled".to_string(),
+            error_type: None,
+            paused: false,
+            result: Some(result.clone()),
+            job_id: None,
+        });
+    }
+    Ok(result)
+}
+
+/// Fetch an album's page, logging in again once if the session has expired.
+/// `None` means Kidplan kept answering with its login page.
+async fn fetch_album_page(
+    state: &AppState,
+    client: &reqwest::Client,
+    album: &Album,
+    relogin: &Relogin,
+) -> Result<Option<String>> {
+    let mut retried = false;
+    loop {
+        let seen = relogin.generation();
+        let resp = client.get(&album.url).send().await?;
+        let status = resp.status();
+        let final_url = resp.url().to_string();
+        let album_html = resp.text().await?;
+
+        eprintln!("[DEBUG] Album page response: status={}, final_url={}, html_len={}", status, final_url, album_html.len());
+
+        // Check if we got redirected to a login page
+        if !is_login_page(&album_html) {
+            return Ok(Some(album_html));
+        }
+        eprintln!("[DEBUG] WARNING: Album page looks like a login page! Session may have expired.");
+        if retried || !relogin.renew(state, client, seen).await {
+            return Ok(None);
+        }
+        retried = true;
+    }
+}
+
+/// Upper bound for `DownloadSettings::concurrency`.
+pub const MAX_CONCURRENCY: usize = 8;
+
+struct ImageJob<'a> {
+    index: usize,
+    url: &'a str,
+    /// Stable key for the image within its album, used by the manifest.
+    image_id: String,
+    filename: String,
+    dest_path: PathBuf,
+    /// `dest_path` relative to the library root, as the manifest and the
+    /// storage know it.
+    rel_path: String,
+}
+
+enum ImageOutcome {
+    Fetched { bytes: Vec<u8>, sha256: String },
+    /// Already stored but not in the manifest yet.
+    Existing,
+    Skipped(&'static str),
+    Failed(ImageFailure),
+    Cancelled,
+}
+
+struct ImageFailure {
+    status: String,
+    error_type: ErrorCode,
+}
+
+/// Most times one download logs in again after Kidplan dropped the session,
+/// so a login that keeps expiring cannot loop forever.
+const MAX_RELOGINS: usize = 3;
+
+/// Logs in again with the stored credentials when the session expires part
+/// way through a download.
+struct Relogin {
+    /// Bumped on every successful login, so workers that failed on the same
+    /// old session share one login instead of each starting their own.
+    generation: AtomicUsize,
+    attempts: tokio::sync::Mutex<usize>,
+}
+
+impl Relogin {
+    fn new() -> Self {
+        Self {
+            generation: AtomicUsize::new(0),
+            attempts: tokio::sync::Mutex::new(0),
+        }
+    }
+
+    fn generation(&self) -> usize {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Log in again, unless that already happened since the caller's request
+    /// started (`seen`). Returns whether retrying the request is worthwhile.
+    async fn renew(&self, state: &AppState, client: &reqwest::Client, seen: usize) -> bool {
+        let mut attempts = self.attempts.lock().await;
+        if self.generation() != seen {
+            return true;
+        }
+        if *attempts >= MAX_RELOGINS {
+            return false;
+        }
+        let credentials = state.credentials.lock().await.clone();
+        let kid_id = state.kindergarten.lock().await.as_ref().map(|k| k.id);
+        let (Some(credentials), Some(kid_id)) = (credentials, kid_id) else {
+            return false;
+        };
+        *attempts += 1;
+        eprintln!("[DEBUG] Session expired, logging in again ({}/{})", *attempts, MAX_RELOGINS);
+        match login(client, &state.endpoints, &credentials, kid_id).await {
+            Ok(()) => {
+                self.generation.fetch_add(1, Ordering::SeqCst);
+                true
             }
+            Err(e) => {
+                eprintln!("[DEBUG] Logging in again failed: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Holds album pages and image requests back while the download is paused,
+/// so it continues exactly where it stopped.
+#[derive(Default)]
+struct PauseGate {
+    /// Whether the current pause has been reported, so the workers waiting
+    /// at it report it only once.
+    announced: AtomicBool,
+}
+
+impl PauseGate {
+    /// Wait until the download is no longer paused. `position` describes
+    /// where it stopped, for the one `paused` update per pause.
+    async fn wait(
+        &self,
+        state: &AppState,
+        reporter: &dyn ProgressReporter,
+        position: impl FnOnce() -> DownloadProgress,
+    ) {
+        let mut paused = state.controls.subscribe();
+        if !*paused.borrow_and_update() {
+            return;
+        }
+        if !self.announced.swap(true, Ordering::SeqCst) {
+            eprintln!("[DEBUG] Download paused");
+            reporter.report(&DownloadProgress {
+                status: "paused".to_string(),
+                ..position()
+            });
+        }
+        // The sender lives in `state`, which outlives the download
+        let _ = paused.wait_for(|paused| !*paused).await;
+        if self.announced.swap(false, Ordering::SeqCst) {
+            eprintln!("[DEBUG] Download resumed");
+        }
+    }
+}
+
+/// Whether Kidplan answered with its login form instead of the page asked for.
+fn is_login_page(html: &str) -> bool {
+    let lower = html.to_lowercase();
+    lower.contains("log in kidplan") || lower.contains("id=\"loginform\"")
+}
 
-            // Delay
-            if settings.delay_ms > 0 {
-                tokio::time::sleep(tokio::time::Duration::from_millis(settings.delay_ms)).await;
+/// Spaces out request starts so that, across all workers together, at most
+/// one image request begins per `interval`.
+struct RateLimiter {
+    interval: Duration,
+    next: tokio::sync::Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: tokio::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn wait(&self) {
+        if self.interval.is_zero() {
+            return;
+        }
+        // Reserve a slot, then sleep without holding the lock
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// The Kidplan image id without extension, and the extension, for templates.
+fn image_id_and_ext(image_url: &str, img_idx: usize, endpoints: &Endpoints) -> (String, String) {
+    match extract_image_id(image_url, endpoints) {
+        Some(id) => match id.rsplit_once('.') {
+            Some((stem, ext)) => (stem.to_string(), ext.to_lowercase()),
+            None => {
+                let ext = get_image_extension(image_url);
+                (id, ext.trim_start_matches('.').to_string())
             }
+        },
+        None => {
+            let digest = format!("{:x}", md5_hash(image_url));
+            (format!("image-{:04}-{}", img_idx + 1, &digest[..10]), "jpg".to_string())
+        }
+    }
+}
 
-            // Download with retry logic
-            eprintln!("[DEBUG] Downloading image {}/{} from album '{}': {}", img_idx + 1, limited.len(), album.title, image_url);
-            
-            let max_retries = 3;
-            let mut attempt = 0;
-            let mut download_succeeded = false;
-            
-            while attempt < max_retries && !download_succeeded {
-                if attempt > 0 {
-                    let backoff_ms = 1000 * (1 << (attempt - 1)); // 1s, 2s, 4s
-                    eprintln!("[DEBUG] Retry attempt {}/{} after {} ms backoff", attempt + 1, max_retries, backoff_ms);
-                    tokio::time::sleep(tokio::time::Duration::from_millis(backoff_ms)).await;
+/// `path`, or the first `name (n).ext` variant of it that no other image
+/// uses in this run or in the manifest.
+fn unique_path(
+    path: PathBuf,
+    album_id: &str,
+    image_id: &str,
+    claimed: &mut HashMap<PathBuf, (String, String)>,
+    manifest: &Manifest,
+) -> PathBuf {
+    let owner = (album_id.to_string(), image_id.to_string());
+    let mut n = 1;
+    loop {
+        let candidate = if n == 1 { path.clone() } else { numbered(&path, n) };
+        let taken = claimed.get(&candidate).is_some_and(|o| *o != owner)
+            || manifest.path_taken(&candidate, album_id, image_id);
+        if !taken {
+            if n > 1 {
+                eprintln!("[DEBUG] {:?} is used by another image, saving as {:?}", path, candidate);
+            }
+            claimed.insert(candidate.clone(), owner);
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+fn image_filename(image_url: &str, img_idx: usize, endpoints: &Endpoints) -> String {
+    match extract_image_id(image_url, endpoints) {
+        // ID already contains extension (e.g., "abc123.jpeg"), so just use it as-is
+        Some(id) if id.contains('.') => format!("id-{}", id),
+        // Fallback: add extension if ID doesn't have one
+        Some(id) => format!("id-{}{}", id, get_image_extension(image_url)),
+        None => {
+            let digest = format!("{:x}", md5_hash(image_url));
+            format!("image-{:04}-{}.jpg", img_idx + 1, &digest[..10])
+        }
+    }
+}
+
+/// Key identifying an image within its album: the Kidplan image id, or the
+/// URL itself when it carries none.
+fn image_key(image_url: &str, endpoints: &Endpoints) -> String {
+    extract_image_id(image_url, endpoints).unwrap_or_else(|| image_url.to_string())
+}
+
+/// Fetch one image and hash its content; storing it is left to the caller,
+/// which sees images in album order.
+async fn fetch_and_hash(
+    state: &AppState,
+    client: &reqwest::Client,
+    job: &ImageJob<'_>,
+    image_total: usize,
+    limiter: &RateLimiter,
+    relogin: &Relogin,
+) -> ImageOutcome {
+    eprintln!("[DEBUG] Downloading image {}/{}: {}", job.index + 1, image_total, job.url);
+    let seen = relogin.generation();
+    let mut fetched = fetch_image(client, job.url, limiter).await;
+    let expired = matches!(&fetched, Err(f) if f.error_type == ErrorCode::SessionExpired);
+    if expired && relogin.renew(state, client, seen).await {
+        fetched = fetch_image(client, job.url, limiter).await;
+    }
+    match fetched {
+        Ok(bytes) => {
+            let sha256 = sha256_hex(&bytes);
+            ImageOutcome::Fetched { bytes, sha256 }
+        }
+        Err(failure) => ImageOutcome::Failed(failure),
+    }
+}
+
+/// Size and SHA-256 are those of the file as stored, which differ from the
+/// downloaded bytes when EXIF is written.
+enum Stored {
+    Written { size: u64, sha256: String },
+    /// Same content as `original` (relative to the library root).
+    Duplicate { original: String, size: u64 },
+}
+
+/// Store a fetched image at its destination atomically, unless the library
+/// already holds the same content elsewhere, followed by its XMP sidecar when
+/// enabled. `sha256` is the hash of the downloaded bytes, which is what
+/// duplicates are detected by. `listing` is kept up to date with what is
+/// stored.
+#[allow(clippy::too_many_arguments)]
+async fn store_image(
+    storage: &dyn Storage,
+    listing: &mut Listing,
+    hashes: &HashIndex,
+    job: &ImageJob<'_>,
+    bytes: &[u8],
+    sha256: &str,
+    settings: &DownloadSettings,
+    meta: &ImageMetadata,
+) -> Result<Stored> {
+    let stored = store_image_bytes(storage, listing, hashes, job, bytes, sha256, settings, meta).await?;
+    // A skipped duplicate has no file of its own to describe
+    let has_file = match stored {
+        Stored::Written { .. } => true,
+        Stored::Duplicate { .. } => settings.duplicates != DuplicateMode::Skip,
+    };
+    if settings.write_xmp && has_file {
+        storage.put(&sidecar_path(&job.rel_path), xmp_sidecar(meta).into_bytes()).await?;
+    }
+    Ok(stored)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn store_image_bytes(
+    storage: &dyn Storage,
+    listing: &mut Listing,
+    hashes: &HashIndex,
+    job: &ImageJob<'_>,
+    bytes: &[u8],
+    sha256: &str,
+    settings: &DownloadSettings,
+    meta: &ImageMetadata,
+) -> Result<Stored> {
+    let mode = settings.duplicates;
+    let dest = &job.rel_path;
+    if let Some(original) = hashes.find(sha256, listing).filter(|f| f.path != *dest).cloned() {
+        eprintln!("[DEBUG] {} has the same content as {}", dest, original.path);
+        let linked = match mode {
+            DuplicateMode::Skip => Ok(()),
+            _ => storage.link(&original.path, dest, mode).await,
+        };
+        match linked {
+            Ok(()) => {
+                if mode != DuplicateMode::Skip {
+                    listing.insert(dest.clone(), original.size);
                 }
-                
-                attempt += 1;
-                
-                match client.get(image_url).send().await {
-                    Ok(resp) => {
-                        let status = resp.status();
-                        if status.is_success() {
-                            match resp.bytes().await {
-                                Ok(bytes) => {
-                                    eprintln!("[DEBUG] Got {} bytes, writing to {:?}", bytes.len(), dest_path);
-                                    if let Err(e) = std::fs::write(&dest_path, &bytes) {
-                                        total_failed += 1;
-                                        let _ = app.emit("download-progress", DownloadProgress {
-                                            album_title: album.title.clone(),
-                                            album_index: album_idx + 1,
-                                            album_total: albums.len(),
-                                            image_index: img_idx + 1,
-                                            image_total: limited.len(),
-                                            filename: filename.clone(),
-                                            status: format!("failed: file write error - {}", e),
-                                            error_type: Some("write_error".to_string()),
-                                        });
-                                        break; // Don't retry write errors
-                                    }
-                                    total_downloaded += 1;
-                                    download_succeeded = true;
-                                    let _ = app.emit("download-progress", DownloadProgress {
-                                        album_title: album.title.clone(),
-                                        album_index: album_idx + 1,
-                                        album_total: albums.len(),
-                                        image_index: img_idx + 1,
-                                        image_total: limited.len(),
-                                        filename: filename.clone(),
-                                        status: "downloaded".to_string(),
-                                        error_type: None,
-                                    });
-                                }
-                                Err(e) => {
-                                    eprintln!("[DEBUG] Failed to read response bytes: {}", e);
-                                    if attempt >= max_retries {
-                                        total_failed += 1;
-                                        let _ = app.emit("download-progress", DownloadProgress {
-                                            album_title: album.title.clone(),
-                                            album_index: album_idx + 1,
-                                            album_total: albums.len(),
-                                            image_index: img_idx + 1,
-                                            image_total: limited.len(),
-                                            filename: filename.clone(),
-                                            status: format!("failed: network error reading response - {}", e),
-                                            error_type: Some("network_error".to_string()),
-                                        });
-                                    }
-                                }
-                            }
-                        } else if status.as_u16() == 429 {
-                            // Rate limited - increase backoff
-                            eprintln!("[DEBUG] Rate limited (429), backing off");
-                            if attempt >= max_retries {
-                                total_failed += 1;
-                                let _ = app.emit("download-progress", DownloadProgress {
-                                    album_title: album.title.clone(),
-                                    album_index: album_idx + 1,
-                                    album_total: albums.len(),
-                                    image_index: img_idx + 1,
-                                    image_total: limited.len(),
-                                    filename: filename.clone(),
-                                    status: "failed: rate limited - try again later".to_string(),
-                                    error_type: Some("rate_limit".to_string()),
-                                });
-                            }
-                        } else if status.is_server_error() {
-                            // 5xx errors - retry
-                            eprintln!("[DEBUG] Server error: HTTP {}", status);
-                            if attempt >= max_retries {
-                                total_failed += 1;
-                                let _ = app.emit("download-progress", DownloadProgress {
-                                    album_title: album.title.clone(),
-                                    album_index: album_idx + 1,
-                                    album_total: albums.len(),
-                                    image_index: img_idx + 1,
-                                    image_total: limited.len(),
-                                    filename: filename.clone(),
-                                    status: format!("failed: server error (HTTP {}) - server is down", status),
-                                    error_type: Some("http_5xx".to_string()),
-                                });
+                return Ok(Stored::Duplicate {
+                    original: original.path,
+                    size: original.size,
+                });
+            }
+            // e.g. a filesystem or remote storage without link support;
+            // keep a plain copy
+            Err(e) => eprintln!("[DEBUG] Could not {} duplicate, writing a copy: {}", mode, e),
+        }
+    }
+
+    let tagged = match settings.write_exif {
+        true if bytes.starts_with(&[0xFF, 0xD8]) => match embed_exif(bytes, meta) {
+            Ok(tagged) => Some(tagged),
+            Err(e) => {
+                // Keep the photo as Kidplan sent it rather than lose it
+                eprintln!("[DEBUG] Could not write EXIF to {}: {}", dest, e);
+                None
+            }
+        },
+        _ => None,
+    };
+    let bytes = tagged.as_deref().unwrap_or(bytes);
+    eprintln!("[DEBUG] Got {} bytes, writing to {}", bytes.len(), dest);
+    storage.put(dest, bytes.to_vec()).await?;
+    listing.insert(dest.clone(), bytes.len() as u64);
+    Ok(Stored::Written {
+        size: bytes.len() as u64,
+        sha256: if tagged.is_some() {
+            sha256_hex(bytes)
+        } else {
+            sha256.to_string()
+        },
+    })
+}
+
+/// Fetch one image, retrying network errors, rate limiting and server errors
+/// with exponential backoff. Auth and other client errors fail immediately.
+async fn fetch_image(
+    client: &reqwest::Client,
+    image_url: &str,
+    limiter: &RateLimiter,
+) -> std::result::Result<Vec<u8>, ImageFailure> {
+    let max_retries = 3;
+    let mut attempt = 0;
+
+    loop {
+        if attempt > 0 {
+            let backoff_ms = 1000 * (1 << (attempt - 1)); // 1s, 2s, 4s
+            eprintln!("[DEBUG] Retry attempt {}/{} after {} ms backoff", attempt + 1, max_retries, backoff_ms);
+            tokio::time::sleep(Duration::from_millis(backoff_ms)).await;
+        }
+        attempt += 1;
+        limiter.wait().await;
+
+        let failure = match client.get(image_url).send().await {
+            Ok(resp) => {
+                let status = resp.status();
+                if status.is_success() {
+                    match resp.bytes().await {
+                        Ok(bytes) => return Ok(bytes.to_vec()),
+                        Err(e) => {
+                            eprintln!("[DEBUG] Failed to read response bytes: {}", e);
+                            ImageFailure {
+                                status: format!("failed: network error reading response - {}", e),
+                                error_type: ErrorCode::NetworkError,
                             }
-                        } else if status.as_u16() == 403 || status.as_u16() == 401 {
-                            // Auth errors - don't retry
-                            total_failed += 1;
-                            let _ = app.emit("download-progress", DownloadProgress {
-                                album_title: album.title.clone(),
-                                album_index: album_idx + 1,
-                                album_total: albums.len(),
-                                image_index: img_idx + 1,
-                                image_total: limited.len(),
-                                filename: filename.clone(),
-                                status: format!("failed: authentication error (HTTP {}) - session expired?", status),
-                                error_type: Some("auth_error".to_string()),
-                            });
-                            break; // Don't retry auth errors
-                        } else {
-                            // Other 4xx errors - don't retry
-                            total_failed += 1;
-                            let _ = app.emit("download-progress", DownloadProgress {
-                                album_title: album.title.clone(),
-                                album_index: album_idx + 1,
-                                album_total: albums.len(),
-                                image_index: img_idx + 1,
-                                image_total: limited.len(),
-                                filename: filename.clone(),
-                                status: format!("failed: HTTP {} - invalid request", status),
-                                error_type: Some("http_4xx".to_string()),
-                            });
-                            break; // Don't retry client errors
                         }
                     }
-                    Err(e) => {
-                        eprintln!("[DEBUG] Network request failed: {}", e);
-                        if attempt >= max_retries {
-                            total_failed += 1;
-                            let _ = app.emit("download-progress", DownloadProgress {
-                                album_title: album.title.clone(),
-                                album_index: album_idx + 1,
-                                album_total: albums.len(),
-                                image_index: img_idx + 1,
-                                image_total: limited.len(),
-                                filename: filename.clone(),
-                                status: format!("failed: network error - check internet connection - {}", e),
-                                error_type: Some("network_error".to_string()),
-                            });
-                        }
+                } else if status.as_u16() == 429 {
+                    // Rate limited - increase backoff
+                    eprintln!("[DEBUG] Rate limited (429), backing off");
+                    ImageFailure {
+                        status: "failed: rate limited - try again later".to_string(),
+                        error_type: ErrorCode::RateLimited,
+                    }
+                } else if status.is_server_error() {
+                    // 5xx errors - retry
+                    eprintln!("[DEBUG] Server error: HTTP {}", status);
+                    ImageFailure {
+                        status: format!("failed: server error (HTTP {}) - server is down", status),
+                        error_type: ErrorCode::HttpStatus,
                     }
+                } else if status.as_u16() == 403 || status.as_u16() == 401 {
+                    // Auth errors - don't retry
+                    return Err(ImageFailure {
+                        status: format!("failed: authentication error (HTTP {}) - session expired?", status),
+                        error_type: ErrorCode::SessionExpired,
+                    });
+                } else {
+                    // Other 4xx errors - don't retry
+                    return Err(ImageFailure {
+                        status: format!("failed: HTTP {} - invalid request", status),
+                        error_type: ErrorCode::HttpStatus,
+                    });
                 }
             }
+            Err(e) => {
+                eprintln!("[DEBUG] Network request failed: {}", e);
+                ImageFailure {
+                    status: format!("failed: network error - check internet connection - {}", e),
+                    error_type: ErrorCode::NetworkError,
+                }
+            }
+        };
+        if attempt >= max_retries {
+            return Err(failure);
         }
     }
-
-    Ok(DownloadResult {
-        total_albums: albums.len(),
-        total_images: total_downloaded,
-        skipped: total_skipped,
-        failed: total_failed,
-    })
 }
 
 fn md5_hash(input: &str) -> u128 {
@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_BASE_URL: &str = "https://app.kidplan.com";
+const DEFAULT_IMAGE_HOST: &str = "img.kidplan.com";
+
+/// Where the Kidplan service lives.
+///
+/// Defaults to the production service; point it at a local mock server to run
+/// the whole login → list → download flow without touching Kidplan.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Endpoints {
+    /// Base URL of the Kidplan web app, without a trailing slash.
+    pub base_url: String,
+    /// Hosts allowed to serve album images. Subdomains match as well.
+    pub image_hosts: Vec<String>,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            image_hosts: vec![DEFAULT_IMAGE_HOST.to_string()],
+        }
+    }
+}
+
+impl Endpoints {
+    pub fn new(base_url: &str, image_hosts: Vec<String>) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            image_hosts,
+        }
+    }
+
+    /// Production endpoints, overridden by `KIDPLAN_BASE_URL` and a
+    /// comma-separated `KIDPLAN_IMAGE_HOSTS` when set.
+    pub fn from_env() -> Self {
+        let mut endpoints = Self::default();
+        if let Ok(base_url) = std::env::var("KIDPLAN_BASE_URL") {
+            endpoints.base_url = base_url.trim_end_matches('/').to_string();
+        }
+        if let Ok(hosts) = std::env::var("KIDPLAN_IMAGE_HOSTS") {
+            endpoints.image_hosts = hosts
+                .split(',')
+                .map(|h| h.trim().to_string())
+                .filter(|h| !h.is_empty())
+                .collect();
+        }
+        endpoints
+    }
+
+    pub fn login_url(&self) -> String {
+        format!("{}/LogOn", self.base_url)
+    }
+
+    pub fn kindergarten_ids_url(&self) -> String {
+        format!("{}/Account/GetKinderGartenIds", self.base_url)
+    }
+
+    pub fn albums_json_url(&self) -> String {
+        format!("{}/bilder/GetAlbumsAsJson", self.base_url)
+    }
+
+    /// Base that relative album URLs from the album JSON are resolved against.
+    pub fn album_base_url(&self) -> String {
+        format!("{}/bilder/album", self.base_url)
+    }
+
+    pub fn is_image_host(&self, host: &str) -> bool {
+        self.image_hosts
+            .iter()
+            .any(|allowed| host == allowed || host.ends_with(&format!(".{}", allowed)))
+    }
+}
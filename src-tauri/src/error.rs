@@ -0,0 +1,153 @@
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::path::Path;
+
+/// Stable, machine-readable error codes shared by command errors and the
+/// `error_type` of failed [`crate::DownloadProgress`] updates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidCredentials,
+    NotLoggedIn,
+    SessionExpired,
+    NetworkError,
+    HttpStatus,
+    RateLimited,
+    ParseError,
+    FilesystemError,
+    InvalidTemplate,
+    Cancelled,
+    JobNotFound,
+    UnsupportedImage,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// Kidplan rejected the e-mail/password combination.
+    InvalidCredentials,
+    /// A command needing a session ran before `login`.
+    NotLoggedIn,
+    /// The session was valid once but Kidplan no longer accepts it.
+    SessionExpired,
+    Network(String),
+    HttpStatus {
+        status: u16,
+        context: String,
+    },
+    RateLimited,
+    Parse(String),
+    Filesystem {
+        path: String,
+        message: String,
+    },
+    /// A file name template that cannot be used.
+    InvalidTemplate(String),
+    Cancelled,
+    /// No queued download has this id.
+    JobNotFound(u64),
+    /// A file that could not be decoded as a JPEG, PNG or WebP image.
+    UnsupportedImage {
+        path: String,
+        message: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::InvalidCredentials => ErrorCode::InvalidCredentials,
+            Error::NotLoggedIn => ErrorCode::NotLoggedIn,
+            Error::SessionExpired => ErrorCode::SessionExpired,
+            Error::Network(_) => ErrorCode::NetworkError,
+            Error::HttpStatus { .. } => ErrorCode::HttpStatus,
+            Error::RateLimited => ErrorCode::RateLimited,
+            Error::Parse(_) => ErrorCode::ParseError,
+            Error::Filesystem { .. } => ErrorCode::FilesystemError,
+            Error::InvalidTemplate(_) => ErrorCode::InvalidTemplate,
+            Error::Cancelled => ErrorCode::Cancelled,
+            Error::JobNotFound(_) => ErrorCode::JobNotFound,
+            Error::UnsupportedImage { .. } => ErrorCode::UnsupportedImage,
+        }
+    }
+
+    pub fn http(status: reqwest::StatusCode, context: &str) -> Self {
+        Error::HttpStatus {
+            status: status.as_u16(),
+            context: context.to_string(),
+        }
+    }
+
+    pub fn fs(path: impl AsRef<Path>, err: impl fmt::Display) -> Self {
+        Error::Filesystem {
+            path: path.as_ref().display().to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidCredentials => {
+                write!(f, "Login failed: invalid e-mail or password")
+            }
+            Error::NotLoggedIn => write!(f, "Not logged in"),
+            Error::SessionExpired => write!(f, "Session expired, please log in again"),
+            Error::Network(msg) => write!(f, "Network error: {}", msg),
+            Error::HttpStatus { status, context } => {
+                write!(f, "{} failed: HTTP {}", context, status)
+            }
+            Error::RateLimited => write!(f, "Rate limited by Kidplan, try again later"),
+            Error::Parse(msg) => write!(f, "Unexpected response from Kidplan: {}", msg),
+            Error::Filesystem { path, message } => write!(f, "{}: {}", path, message),
+            Error::InvalidTemplate(msg) => write!(f, "Invalid file name template, {}", msg),
+            Error::Cancelled => write!(f, "Cancelled"),
+            Error::JobNotFound(id) => write!(f, "No download job with id {}", id),
+            Error::UnsupportedImage { path, message } => {
+                write!(
+                    f,
+                    "{}: not a readable JPEG, PNG or WebP image ({})",
+                    path, message
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_decode() {
+            Error::Parse(err.to_string())
+        } else {
+            Error::Network(err.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Parse(err.to_string())
+    }
+}
+
+/// Sent to the frontend as `{ code, message, status? }`.
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let status = match self {
+            Error::HttpStatus { status, .. } => Some(*status),
+            _ => None,
+        };
+        let mut s = serializer.serialize_struct("Error", 2 + status.is_some() as usize)?;
+        s.serialize_field("code", &self.code())?;
+        s.serialize_field("message", &self.to_string())?;
+        if let Some(status) = status {
+            s.serialize_field("status", &status)?;
+        }
+        s.end()
+    }
+}
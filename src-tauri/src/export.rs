@@ -0,0 +1,404 @@
+use crate::error::{Error, Result};
+use crate::manifest::{part_path, EntryStatus, Manifest, STATE_DIR};
+use crate::naming;
+use crate::progress::ProgressReporter;
+use crate::{Album, DownloadProgress};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio_util::sync::CancellationToken;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// How albums are split into archives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZipLayout {
+    /// One archive per album, named after the album.
+    #[default]
+    PerAlbum,
+    /// One archive with a folder per album.
+    Combined,
+}
+
+impl FromStr for ZipLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "per-album" | "per_album" => Ok(ZipLayout::PerAlbum),
+            "combined" => Ok(ZipLayout::Combined),
+            other => Err(format!(
+                "unknown zip layout '{}' (expected per-album or combined)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ZipLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ZipLayout::PerAlbum => "per-album",
+            ZipLayout::Combined => "combined",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ZipSettings {
+    /// 0 stores files as they are, which is the fastest and loses little
+    /// since photos hardly compress; 1–9 deflate, 9 being the smallest.
+    #[serde(default)]
+    pub level: u8,
+    #[serde(default)]
+    pub layout: ZipLayout,
+}
+
+impl ZipSettings {
+    fn options(&self) -> SimpleFileOptions {
+        match self.level.min(9) {
+            0 => SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+            level => SimpleFileOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(Some(level as i64)),
+        }
+    }
+}
+
+/// A file going into an archive, under `name`.
+struct ZipItem {
+    source: PathBuf,
+    name: String,
+}
+
+/// Pack the downloaded images of `albums` from the library at
+/// `manifest.root()` into archives in `dest_dir`, and return their paths.
+///
+/// Files are read from the library as they are added, so nothing but the
+/// archive itself is written. Albums with nothing downloaded are left out.
+/// Each file is reported with status "zipping", each finished archive with
+/// "zipped" and its path as the file name.
+pub fn zip_albums(
+    manifest: &Manifest,
+    albums: &[Album],
+    dest_dir: &Path,
+    settings: &ZipSettings,
+    reporter: &dyn ProgressReporter,
+    cancel: &CancellationToken,
+) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dest_dir).map_err(|e| Error::fs(dest_dir, e))?;
+    let folders = album_folders(albums);
+    let groups: Vec<(usize, &Album, &String, Vec<ZipItem>)> = albums
+        .iter()
+        .zip(&folders)
+        .enumerate()
+        .map(|(i, (album, folder))| (i, album, folder, album_items(manifest, album)))
+        .filter(|(_, album, _, items)| {
+            if items.is_empty() {
+                eprintln!(
+                    "[DEBUG] Nothing downloaded of album {}, not zipping it",
+                    album.id
+                );
+            }
+            !items.is_empty()
+        })
+        .collect();
+
+    let position = |index: usize, album: &Album| DownloadProgress {
+        album_title: album.title.clone(),
+        album_index: index + 1,
+        album_total: albums.len(),
+        image_index: 0,
+        image_total: 0,
+        filename: String::new(),
+        status: String::new(),
+        error_type: None,
+        paused: false,
+        result: None,
+        job_id: None,
+    };
+
+    let mut archives = Vec::new();
+    match settings.layout {
+        ZipLayout::PerAlbum => {
+            for (index, album, folder, items) in &groups {
+                let archive = dest_dir.join(format!("{}.zip", folder));
+                write_archive(&archive, settings, cancel, items.len(), |writer| {
+                    for (i, item) in items.iter().enumerate() {
+                        writer.add(item, cancel)?;
+                        reporter.report(&DownloadProgress {
+                            image_index: i + 1,
+                            image_total: items.len(),
+                            filename: item.name.clone(),
+                            status: "zipping".to_string(),
+                            ..position(*index, album)
+                        });
+                    }
+                    Ok(())
+                })?;
+                reporter.report(&DownloadProgress {
+                    filename: archive.display().to_string(),
+                    status: "zipped".to_string(),
+                    ..position(*index, album)
+                });
+                archives.push(archive);
+            }
+        }
+        ZipLayout::Combined => {
+            let Some((last, last_album, _, _)) = groups.last() else {
+                return Ok(archives);
+            };
+            let name = format!("kidplan-{}.zip", chrono::Local::now().format("%Y-%m-%d"));
+            let archive = dest_dir.join(name);
+            let total = groups.iter().map(|g| g.3.len()).sum();
+            write_archive(&archive, settings, cancel, total, |writer| {
+                for (index, album, folder, items) in &groups {
+                    for (i, item) in items.iter().enumerate() {
+                        let item = ZipItem {
+                            source: item.source.clone(),
+                            name: format!("{}/{}", folder, item.name),
+                        };
+                        writer.add(&item, cancel)?;
+                        reporter.report(&DownloadProgress {
+                            image_index: i + 1,
+                            image_total: items.len(),
+                            filename: item.name,
+                            status: "zipping".to_string(),
+                            ..position(*index, album)
+                        });
+                    }
+                }
+                Ok(())
+            })?;
+            reporter.report(&DownloadProgress {
+                filename: archive.display().to_string(),
+                status: "zipped".to_string(),
+                ..position(*last, last_album)
+            });
+            archives.push(archive);
+        }
+    }
+    Ok(archives)
+}
+
+/// [`zip_albums`] on a blocking thread, so packing a large library holds up
+/// neither the async runtime nor the reports, which reach `reporter` from
+/// the calling task.
+pub async fn zip_albums_in_background(
+    manifest: Manifest,
+    albums: Vec<Album>,
+    dest_dir: PathBuf,
+    settings: ZipSettings,
+    reporter: &dyn ProgressReporter,
+    cancel: CancellationToken,
+) -> Result<Vec<PathBuf>> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let dir = dest_dir.clone();
+    let zipping = tokio::task::spawn_blocking(move || {
+        let reporter = ChannelReporter(tx);
+        zip_albums(&manifest, &albums, &dest_dir, &settings, &reporter, &cancel)
+    });
+    // Ends once the blocking side is done and drops its sender
+    while let Some(progress) = rx.recv().await {
+        reporter.report(&progress);
+    }
+    zipping.await.map_err(|e| Error::fs(&dir, e))?
+}
+
+/// Hands reports from a blocking thread over to an async task.
+struct ChannelReporter(tokio::sync::mpsc::UnboundedSender<DownloadProgress>);
+
+impl ProgressReporter for ChannelReporter {
+    fn report(&self, progress: &DownloadProgress) {
+        let _ = self.0.send(progress.clone());
+    }
+}
+
+/// Pack every file under `dir` into `archive`, e.g. an album folder. The
+/// downloader's own bookkeeping and unfinished `.part` files are left out.
+pub fn zip_directory(
+    dir: &Path,
+    archive: &Path,
+    settings: &ZipSettings,
+    reporter: &dyn ProgressReporter,
+    cancel: &CancellationToken,
+) -> Result<PathBuf> {
+    let mut items = Vec::new();
+    collect_files(dir, dir, archive, &mut items)?;
+    items.sort_by(|a, b| a.name.cmp(&b.name));
+    if let Some(parent) = archive.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| Error::fs(parent, e))?;
+    }
+    let title = dir
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let position = DownloadProgress {
+        album_title: title,
+        album_index: 1,
+        album_total: 1,
+        image_index: 0,
+        image_total: 0,
+        filename: String::new(),
+        status: String::new(),
+        error_type: None,
+        paused: false,
+        result: None,
+        job_id: None,
+    };
+    write_archive(archive, settings, cancel, items.len(), |writer| {
+        for (i, item) in items.iter().enumerate() {
+            writer.add(item, cancel)?;
+            reporter.report(&DownloadProgress {
+                image_index: i + 1,
+                image_total: items.len(),
+                filename: item.name.clone(),
+                status: "zipping".to_string(),
+                ..position.clone()
+            });
+        }
+        Ok(())
+    })?;
+    reporter.report(&DownloadProgress {
+        filename: archive.display().to_string(),
+        status: "zipped".to_string(),
+        ..position
+    });
+    Ok(archive.to_path_buf())
+}
+
+/// The album's complete images, named by file name and numbered where two
+/// share one (e.g. a duplicate kept in another album's folder).
+fn album_items(manifest: &Manifest, album: &Album) -> Vec<ZipItem> {
+    let mut entries: Vec<_> = manifest
+        .entries()
+        .filter(|e| e.album_id == album.id && e.status == EntryStatus::Complete)
+        .collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut taken = HashSet::new();
+    entries
+        .into_iter()
+        .map(|entry| {
+            let file = entry.path.rsplit('/').next().unwrap_or(&entry.path);
+            let mut name = file.to_string();
+            let mut n = 2;
+            while !taken.insert(name.to_lowercase()) {
+                name = naming::numbered(Path::new(file), n)
+                    .to_string_lossy()
+                    .to_string();
+                n += 1;
+            }
+            ZipItem {
+                source: manifest.root().join(&entry.path),
+                name,
+            }
+        })
+        .collect()
+}
+
+/// A folder (and archive) name per album, with the id added where two
+/// share a title.
+fn album_folders(albums: &[Album]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let clashing: HashSet<String> = albums
+        .iter()
+        .map(|a| naming::folder_name(&a.title).to_lowercase())
+        .filter(|name| !seen.insert(name.clone()))
+        .collect();
+    albums
+        .iter()
+        .map(|a| {
+            let name = naming::folder_name(&a.title);
+            if clashing.contains(&name.to_lowercase()) {
+                format!("{} ({})", name, naming::folder_name(&a.id))
+            } else {
+                name
+            }
+        })
+        .collect()
+}
+
+fn collect_files(root: &Path, dir: &Path, skip: &Path, items: &mut Vec<ZipItem>) -> Result<()> {
+    let entries = std::fs::read_dir(dir).map_err(|e| Error::fs(dir, e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| Error::fs(dir, e))?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == STATE_DIR || name.ends_with(".part") || path == skip {
+            continue;
+        }
+        // Follows symlinks, so linked duplicates are packed as files
+        let metadata = std::fs::metadata(&path).map_err(|e| Error::fs(&path, e))?;
+        if metadata.is_dir() {
+            collect_files(root, &path, skip, items)?;
+        } else {
+            let rel = path.strip_prefix(root).unwrap_or(&path);
+            items.push(ZipItem {
+                name: rel
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+                source: path,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Streams files into an archive being written.
+struct ArchiveWriter<'a> {
+    zip: ZipWriter<File>,
+    options: SimpleFileOptions,
+    archive: &'a Path,
+}
+
+impl ArchiveWriter<'_> {
+    fn add(&mut self, item: &ZipItem, cancel: &CancellationToken) -> Result<()> {
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let mut source = File::open(&item.source).map_err(|e| Error::fs(&item.source, e))?;
+        self.zip
+            .start_file(item.name.as_str(), self.options)
+            .map_err(|e| Error::fs(self.archive, e))?;
+        std::io::copy(&mut source, &mut self.zip).map_err(|e| Error::fs(&item.source, e))?;
+        Ok(())
+    }
+}
+
+/// Write `archive` through `fill` into a `.part` file and move it into
+/// place once complete, so an interrupted export leaves no broken archive.
+fn write_archive(
+    archive: &Path,
+    settings: &ZipSettings,
+    cancel: &CancellationToken,
+    files: usize,
+    fill: impl FnOnce(&mut ArchiveWriter<'_>) -> Result<()>,
+) -> Result<()> {
+    eprintln!("[DEBUG] Zipping {} files into {:?}", files, archive);
+    let tmp = part_path(archive);
+    let result = (|| {
+        let file = File::create(&tmp).map_err(|e| Error::fs(&tmp, e))?;
+        let mut writer = ArchiveWriter {
+            zip: ZipWriter::new(file),
+            options: settings.options(),
+            archive,
+        };
+        fill(&mut writer)?;
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let file = writer.zip.finish().map_err(|e| Error::fs(archive, e))?;
+        file.sync_all().map_err(|e| Error::fs(&tmp, e))?;
+        std::fs::rename(&tmp, archive).map_err(|e| Error::fs(archive, e))
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
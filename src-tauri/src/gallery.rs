@@ -0,0 +1,548 @@
+//! A static HTML gallery of a downloaded library, for browsing it without
+//! the app: `index.html` at the library root with a card per album, and a
+//! page per album in [`GALLERY_DIR`] with a thumbnail grid and a lightbox.
+//!
+//! Pages link to the images, and to their thumbnails in `.kidplan`, by
+//! relative paths and carry their own styles and script, so the folder can
+//! be copied to a USB stick or another machine and opened in any browser,
+//! without network access.
+
+use crate::downloader::slugify;
+use crate::error::{Error, Result};
+use crate::manifest::{write_atomic, AlbumManifest, ALBUM_MANIFEST_FILE, STATE_DIR};
+use crate::thumbnails::{self, ThumbnailCache};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Folder at the library root holding the album pages.
+pub const GALLERY_DIR: &str = "gallery";
+/// The gallery's start page, at the library root.
+pub const INDEX_FILE: &str = "index.html";
+
+/// Files shown in the gallery; anything else in an album is left out.
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "bmp", "avif"];
+
+/// Language of the gallery's own texts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GalleryLanguage {
+    #[default]
+    En,
+    No,
+}
+
+impl FromStr for GalleryLanguage {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "en" => Ok(GalleryLanguage::En),
+            "no" | "nb" => Ok(GalleryLanguage::No),
+            other => Err(format!("unknown language '{}' (expected en or no)", other)),
+        }
+    }
+}
+
+impl fmt::Display for GalleryLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl GalleryLanguage {
+    fn code(self) -> &'static str {
+        match self {
+            GalleryLanguage::En => "en",
+            GalleryLanguage::No => "no",
+        }
+    }
+
+    fn photos(self, n: usize) -> String {
+        match (self, n) {
+            (GalleryLanguage::En, 1) => "1 photo".to_string(),
+            (GalleryLanguage::En, n) => format!("{} photos", n),
+            (GalleryLanguage::No, 1) => "1 bilde".to_string(),
+            (GalleryLanguage::No, n) => format!("{} bilder", n),
+        }
+    }
+
+    fn albums(self, n: usize) -> String {
+        match (self, n) {
+            (GalleryLanguage::En, 1) => "1 album".to_string(),
+            (GalleryLanguage::En, n) => format!("{} albums", n),
+            (GalleryLanguage::No, n) => format!("{} album", n),
+        }
+    }
+
+    fn date(self, date: NaiveDateTime) -> String {
+        match self {
+            GalleryLanguage::En => date.format("%Y-%m-%d").to_string(),
+            GalleryLanguage::No => date.format("%d.%m.%Y").to_string(),
+        }
+    }
+
+    fn text(self, key: Text) -> &'static str {
+        use GalleryLanguage::{En, No};
+        match (key, self) {
+            (Text::Photos, En) => "Photos",
+            (Text::Photos, No) => "Bilder",
+            (Text::AllAlbums, En) => "All albums",
+            (Text::AllAlbums, No) => "Alle album",
+            (Text::Close, En) => "Close",
+            (Text::Close, No) => "Lukk",
+            (Text::Previous, En) => "Previous",
+            (Text::Previous, No) => "Forrige",
+            (Text::Next, En) => "Next",
+            (Text::Next, No) => "Neste",
+            (Text::Original, En) => "Open original",
+            (Text::Original, No) => "Åpne original",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Text {
+    Photos,
+    AllAlbums,
+    Close,
+    Previous,
+    Next,
+    Original,
+}
+
+/// What [`write_gallery`] wrote.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Gallery {
+    /// The gallery's `index.html`.
+    pub index: PathBuf,
+    pub albums: usize,
+    pub images: usize,
+}
+
+/// An album found in the library, with the images present on disk.
+struct GalleryAlbum {
+    manifest: AlbumManifest,
+    images: Vec<GalleryImage>,
+    /// File name of the album's page in [`GALLERY_DIR`].
+    page: String,
+}
+
+/// Links relative to the gallery root, percent-encoded.
+struct GalleryImage {
+    href: String,
+    /// The image's thumbnail, or the image itself when it has none.
+    thumbnail: String,
+    name: String,
+}
+
+/// Write a gallery of every album below `root` that has an `album.json`,
+/// replacing the one written before.
+///
+/// Images listed in `album.json` but not on disk, e.g. kept in remote
+/// storage, are left out, and so are albums with none left. Several
+/// libraries below `root`, as downloaded for several kindergartens, make up
+/// one gallery with a section per kindergarten.
+///
+/// The grids show thumbnails from each library's thumbnail cache, made now
+/// for images that have none yet.
+pub fn write_gallery(root: &Path, language: GalleryLanguage) -> Result<Gallery> {
+    let mut found = Vec::new();
+    find_album_manifests(root, root, &mut found)?;
+    found.sort();
+
+    let mut seen = HashSet::new();
+    let mut caches: HashMap<PathBuf, ThumbnailCache> = HashMap::new();
+    let mut albums = Vec::new();
+    for path in found {
+        let manifest = match std::fs::read(&path)
+            .map_err(|e| Error::fs(&path, e))
+            .and_then(|bytes| Ok(serde_json::from_slice::<AlbumManifest>(&bytes)?))
+        {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                eprintln!("[DEBUG] Skipping {:?} in the gallery: {}", path, e);
+                continue;
+            }
+        };
+        let library = library_root(root, path.parent().unwrap_or(root));
+        if !seen.insert((library.clone(), manifest.album_id.clone())) {
+            continue;
+        }
+        let cache = caches
+            .entry(library.clone())
+            .or_insert_with(|| ThumbnailCache::open(&library));
+        let images = present_images(root, &library, &manifest, cache);
+        if images.is_empty() {
+            eprintln!(
+                "[DEBUG] No images of album {} on disk, leaving it out of the gallery",
+                manifest.album_id
+            );
+            continue;
+        }
+        albums.push(GalleryAlbum {
+            manifest,
+            images,
+            page: String::new(),
+        });
+    }
+
+    // By kindergarten, then newest first
+    albums.sort_by(|a, b| {
+        a.manifest
+            .kindergarten
+            .cmp(&b.manifest.kindergarten)
+            .then_with(|| b.manifest.date.cmp(&a.manifest.date))
+            .then_with(|| a.manifest.title.cmp(&b.manifest.title))
+    });
+    let mut pages = HashSet::new();
+    for cache in caches.values_mut() {
+        cache.save()?;
+    }
+    for album in &mut albums {
+        let base = slugify(&album.manifest.album_id);
+        let mut page = format!("{}.html", base);
+        let mut n = 2;
+        while !pages.insert(page.clone()) {
+            page = format!("{}-{}.html", base, n);
+            n += 1;
+        }
+        album.page = page;
+    }
+
+    let dir = root.join(GALLERY_DIR);
+    std::fs::create_dir_all(&dir).map_err(|e| Error::fs(&dir, e))?;
+    for album in &albums {
+        write_atomic(
+            &dir.join(&album.page),
+            album_page(album, language).as_bytes(),
+        )?;
+    }
+    remove_stale_pages(&dir, &pages)?;
+    let index = root.join(INDEX_FILE);
+    write_atomic(&index, index_page(&albums, language).as_bytes())?;
+
+    let gallery = Gallery {
+        index,
+        albums: albums.len(),
+        images: albums.iter().map(|a| a.images.len()).sum(),
+    };
+    eprintln!(
+        "[DEBUG] Wrote gallery {:?}: {} albums, {} images",
+        gallery.index, gallery.albums, gallery.images
+    );
+    Ok(gallery)
+}
+
+fn find_album_manifests(root: &Path, dir: &Path, found: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = std::fs::read_dir(dir).map_err(|e| Error::fs(dir, e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| Error::fs(dir, e))?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        // Symlinked folders are not followed, so links can't loop
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if name != STATE_DIR && path != root.join(GALLERY_DIR) {
+                find_album_manifests(root, &path, found)?;
+            }
+        } else if name == ALBUM_MANIFEST_FILE
+            || (name.starts_with("album-") && name.ends_with(".json"))
+        {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The library an album folder belongs to, which image paths in its
+/// `album.json` are relative to: the closest folder holding [`STATE_DIR`].
+fn library_root(root: &Path, dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .take_while(|d| d.starts_with(root))
+        .find(|d| d.join(STATE_DIR).is_dir())
+        .unwrap_or(root)
+        .to_path_buf()
+}
+
+fn present_images(
+    root: &Path,
+    library: &Path,
+    manifest: &AlbumManifest,
+    cache: &mut ThumbnailCache,
+) -> Vec<GalleryImage> {
+    let mut images = Vec::new();
+    for image in manifest.images.iter().filter(|i| is_image(&i.file)) {
+        let file = image
+            .path
+            .split('/')
+            .fold(library.to_path_buf(), |path, part| path.join(part));
+        if !file.is_file() {
+            continue;
+        }
+        let href = link(root, &file);
+        let thumbnail = match cache.thumbnail(&image.path, thumbnails::DEFAULT_SIZE) {
+            Ok(thumbnail) => link(root, &thumbnail),
+            Err(e) => {
+                eprintln!("[DEBUG] Showing {} as it is: {}", image.path, e);
+                href.clone()
+            }
+        };
+        images.push(GalleryImage {
+            href,
+            thumbnail,
+            name: image.file.clone(),
+        });
+    }
+    images
+}
+
+/// `file` as a link from the gallery root.
+fn link(root: &Path, file: &Path) -> String {
+    file.strip_prefix(root)
+        .unwrap_or(file)
+        .components()
+        .map(|c| urlencoding::encode(&c.as_os_str().to_string_lossy()).into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_image(file: &str) -> bool {
+    file.rsplit_once('.')
+        .is_some_and(|(_, ext)| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Remove album pages of an earlier gallery that no longer have an album.
+fn remove_stale_pages(dir: &Path, pages: &HashSet<String>) -> Result<()> {
+    let entries = std::fs::read_dir(dir).map_err(|e| Error::fs(dir, e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| Error::fs(dir, e))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.ends_with(".html") && !pages.contains(&name) {
+            let path = entry.path();
+            std::fs::remove_file(&path).map_err(|e| Error::fs(&path, e))?;
+        }
+    }
+    Ok(())
+}
+
+fn escape_text(value: &str) -> String {
+    html_escape::encode_text(value).to_string()
+}
+
+fn escape_attr(value: &str) -> String {
+    html_escape::encode_double_quoted_attribute(value).to_string()
+}
+
+fn album_meta(album: &GalleryAlbum, language: GalleryLanguage) -> String {
+    let manifest = &album.manifest;
+    manifest
+        .date
+        .map(|d| language.date(d))
+        .into_iter()
+        .chain(manifest.department.clone())
+        .chain(manifest.author.clone())
+        .chain(Some(language.photos(album.images.len())))
+        .collect::<Vec<_>>()
+        .join(" · ")
+}
+
+fn index_page(albums: &[GalleryAlbum], language: GalleryLanguage) -> String {
+    let kindergartens: Vec<Option<&str>> = albums
+        .iter()
+        .map(|a| a.manifest.kindergarten.as_deref())
+        .fold(Vec::new(), |mut names, name| {
+            if names.last() != Some(&name) {
+                names.push(name);
+            }
+            names
+        });
+    let title = match kindergartens.as_slice() {
+        [Some(name)] => name.to_string(),
+        _ => language.text(Text::Photos).to_string(),
+    };
+    let images: usize = albums.iter().map(|a| a.images.len()).sum();
+
+    let mut body = String::new();
+    let _ = writeln!(
+        body,
+        "<header><h1>{}</h1><p class=\"meta\">{} · {}</p></header>",
+        escape_text(&title),
+        language.albums(albums.len()),
+        language.photos(images)
+    );
+    for kindergarten in &kindergartens {
+        if kindergartens.len() > 1 {
+            let _ = writeln!(body, "<h2>{}</h2>", escape_text(kindergarten.unwrap_or("")));
+        }
+        body.push_str("<main class=\"albums\">\n");
+        for album in albums
+            .iter()
+            .filter(|a| a.manifest.kindergarten.as_deref() == *kindergarten)
+        {
+            let _ = writeln!(
+                body,
+                "<a class=\"card\" href=\"{}/{}\"><img src=\"{}\" alt=\"\" loading=\"lazy\"><span class=\"title\">{}</span><span class=\"meta\">{}</span></a>",
+                GALLERY_DIR,
+                escape_attr(&album.page),
+                escape_attr(&album.images[0].thumbnail),
+                escape_text(&album.manifest.title),
+                escape_text(&album_meta(album, language))
+            );
+        }
+        body.push_str("</main>\n");
+    }
+    document(language, &title, &body)
+}
+
+fn album_page(album: &GalleryAlbum, language: GalleryLanguage) -> String {
+    let manifest = &album.manifest;
+    let mut body = String::new();
+    let _ = writeln!(
+        body,
+        "<header><a class=\"back\" href=\"../{}\">← {}</a><h1>{}</h1><p class=\"meta\">{}</p>",
+        INDEX_FILE,
+        language.text(Text::AllAlbums),
+        escape_text(&manifest.title),
+        escape_text(&album_meta(album, language))
+    );
+    if let Some(description) = &manifest.description {
+        let _ = writeln!(
+            body,
+            "<p class=\"description\">{}</p>",
+            escape_text(description)
+        );
+    }
+    body.push_str("</header>\n<main class=\"grid\">\n");
+    for image in &album.images {
+        let _ = writeln!(
+            body,
+            "<a href=\"../{}\"><img src=\"../{}\" alt=\"{}\" loading=\"lazy\"></a>",
+            escape_attr(&image.href),
+            escape_attr(&image.thumbnail),
+            escape_attr(&image.name)
+        );
+    }
+    body.push_str("</main>\n");
+    let _ = writeln!(
+        body,
+        "<div class=\"lightbox\" id=\"lightbox\" hidden>\
+<img alt=\"\">\
+<button type=\"button\" class=\"close\" aria-label=\"{close}\">×</button>\
+<button type=\"button\" class=\"prev\" aria-label=\"{previous}\">‹</button>\
+<button type=\"button\" class=\"next\" aria-label=\"{next}\">›</button>\
+<div class=\"bar\"><span class=\"counter\"></span><a class=\"original\" target=\"_blank\">{original}</a></div>\
+</div>\n<script>{script}</script>",
+        close = language.text(Text::Close),
+        previous = language.text(Text::Previous),
+        next = language.text(Text::Next),
+        original = language.text(Text::Original),
+        script = LIGHTBOX_SCRIPT
+    );
+    document(language, &manifest.title, &body)
+}
+
+fn document(language: GalleryLanguage, title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n\
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        language.code(),
+        escape_text(title),
+        STYLE,
+        body
+    )
+}
+
+const STYLE: &str = r#"
+*{box-sizing:border-box}
+[hidden]{display:none!important}
+body{margin:0;padding:24px;font-family:system-ui,-apple-system,"Segoe UI",Roboto,sans-serif;background:#f9fafb;color:#111827}
+header{margin-bottom:24px}
+h1{margin:8px 0 4px;font-size:1.6rem}
+h2{margin:32px 0 12px;font-size:1.2rem}
+.meta{margin:0;color:#6b7280;font-size:.9rem}
+.description{max-width:60em;white-space:pre-line}
+.back{color:#2563eb;text-decoration:none;font-size:.9rem}
+.albums{display:grid;grid-template-columns:repeat(auto-fill,minmax(220px,1fr));gap:16px}
+.card{display:flex;flex-direction:column;background:#fff;border:1px solid #e5e7eb;border-radius:8px;overflow:hidden;color:inherit;text-decoration:none}
+.card:hover{border-color:#93c5fd}
+.card img{width:100%;aspect-ratio:4/3;object-fit:cover;background:#e5e7eb}
+.card .title{padding:10px 12px 2px;font-weight:600}
+.card .meta{padding:0 12px 12px;font-size:.8rem}
+.grid{display:grid;grid-template-columns:repeat(auto-fill,minmax(160px,1fr));gap:8px}
+.grid a{display:block;border-radius:6px;overflow:hidden;background:#e5e7eb}
+.grid img{display:block;width:100%;aspect-ratio:1;object-fit:cover}
+.lightbox{position:fixed;inset:0;background:rgba(0,0,0,.92);display:flex;align-items:center;justify-content:center;z-index:10}
+.lightbox img{max-width:100vw;max-height:calc(100vh - 56px)}
+.lightbox button{position:absolute;border:0;background:none;color:#fff;font-size:2.5rem;line-height:1;padding:16px;cursor:pointer;opacity:.8}
+.lightbox button:hover{opacity:1}
+.lightbox .close{top:0;right:0}
+.lightbox .prev{left:0;top:50%;transform:translateY(-50%)}
+.lightbox .next{right:0;top:50%;transform:translateY(-50%)}
+.lightbox .bar{position:absolute;bottom:0;left:0;right:0;display:flex;justify-content:space-between;padding:16px 24px;color:#d1d5db;font-size:.9rem}
+.lightbox .original{color:#93c5fd}
+@media (prefers-color-scheme:dark){
+body{background:#030712;color:#f3f4f6}
+.card{background:#111827;border-color:#1f2937}
+.card img,.grid a{background:#1f2937}
+.meta{color:#9ca3af}
+.back{color:#60a5fa}
+}
+"#;
+
+/// Opens the linked images in an overlay, browsed with the arrow keys or by
+/// swiping. Without script the links still open each image on its own.
+const LIGHTBOX_SCRIPT: &str = r#"
+(function () {
+  var links = Array.prototype.slice.call(document.querySelectorAll(".grid a"));
+  var box = document.getElementById("lightbox");
+  var image = box.querySelector("img");
+  var counter = box.querySelector(".counter");
+  var original = box.querySelector(".original");
+  var current = 0;
+  var touchX = null;
+  function show(i) {
+    current = (i + links.length) % links.length;
+    var href = links[current].getAttribute("href");
+    image.src = href;
+    original.href = href;
+    counter.textContent = current + 1 + " / " + links.length;
+    box.hidden = false;
+  }
+  function close() {
+    box.hidden = true;
+    image.removeAttribute("src");
+  }
+  links.forEach(function (link, i) {
+    link.addEventListener("click", function (e) {
+      e.preventDefault();
+      show(i);
+    });
+  });
+  box.querySelector(".prev").addEventListener("click", function () { show(current - 1); });
+  box.querySelector(".next").addEventListener("click", function () { show(current + 1); });
+  box.querySelector(".close").addEventListener("click", close);
+  box.addEventListener("click", function (e) {
+    if (e.target === box) close();
+  });
+  box.addEventListener("touchstart", function (e) { touchX = e.touches[0].clientX; });
+  box.addEventListener("touchend", function (e) {
+    if (touchX === null) return;
+    var dx = e.changedTouches[0].clientX - touchX;
+    touchX = null;
+    if (Math.abs(dx) > 50) show(current + (dx < 0 ? 1 : -1));
+  });
+  document.addEventListener("keydown", function (e) {
+    if (box.hidden) return;
+    if (e.key === "Escape") close();
+    else if (e.key === "ArrowLeft") show(current - 1);
+    else if (e.key === "ArrowRight") show(current + 1);
+  });
+})();
+"#;
@@ -0,0 +1,222 @@
+use crate::downloader::{self, resolve_out_dir};
+use crate::error::{Error, Result};
+use crate::naming;
+use crate::progress::ProgressReporter;
+use crate::{
+    AppState, Credentials, DownloadProgress, DownloadResult, DownloadSettings, Kindergarten,
+    KindergartenAlbums,
+};
+use std::collections::HashSet;
+
+/// Log in to each of `kid_ids` in turn and fetch its album list.
+///
+/// A Kidplan session only covers one kindergarten at a time, so the session
+/// is switched back to the kindergarten it was on before returning.
+pub async fn fetch_albums(
+    state: &AppState,
+    credentials: &Credentials,
+    kid_ids: &[i64],
+) -> Result<Vec<KindergartenAlbums>> {
+    let client = client(state).await?;
+    let previous = state.kindergarten.lock().await.clone();
+    let mut groups = Vec::new();
+    let mut failure = None;
+    for &kid_id in kid_ids {
+        let group: Result<KindergartenAlbums> = async {
+            let kindergarten = switch_to(state, &client, credentials, kid_id).await?;
+            let albums = downloader::fetch_albums(&client, &state.endpoints).await?;
+            Ok(KindergartenAlbums { kindergarten, albums })
+        }
+        .await;
+        match group {
+            Ok(group) => groups.push(group),
+            Err(e) => {
+                failure = Some(e);
+                break;
+            }
+        }
+    }
+    restore(state, &client, credentials, previous).await;
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(groups),
+    }
+}
+
+/// Download the chosen albums of several kindergartens as one job.
+///
+/// Each kindergarten gets its own folder (and library bookkeeping) under
+/// `settings.out_dir`, named after the kindergarten. Progress is numbered
+/// across all albums of the job, and the results are added up.
+pub async fn download_kindergartens(
+    state: &AppState,
+    credentials: &Credentials,
+    selections: Vec<KindergartenAlbums>,
+    settings: DownloadSettings,
+    reporter: &dyn ProgressReporter,
+) -> Result<DownloadResult> {
+    let client = client(state).await?;
+    let previous = state.kindergarten.lock().await.clone();
+    let root = resolve_out_dir(&settings.out_dir);
+    let album_total = selections.iter().map(|s| s.albums.len()).sum();
+    let folders = folder_names(selections.iter().map(|s| &s.kindergarten));
+
+    let mut result = DownloadResult::default();
+    let mut albums_before = 0;
+    let mut failure = None;
+    for (selection, folder) in selections.into_iter().zip(folders) {
+        if selection.albums.is_empty() {
+            continue;
+        }
+        // A download that was cancelled part way has reported it already
+        if result.cancelled {
+            break;
+        }
+        if state.controls.is_cancelled() {
+            result.cancelled = true;
+            reporter.report(&DownloadProgress {
+                album_title: String::new(),
+                album_index: albums_before,
+                album_total,
+                image_index: 0,
+                image_total: 0,
+                filename: String::new(),
+                status: "cancelled".to_string(),
+                error_type: None,
+                paused: false,
+                result: Some(result.clone()),
+                job_id: None,
+            });
+            break;
+        }
+        let kindergarten = selection.kindergarten;
+        eprintln!(
+            "[DEBUG] Downloading {} albums from kindergarten {} ({})",
+            selection.albums.len(),
+            kindergarten.name,
+            kindergarten.id
+        );
+        if let Err(e) = switch_to(state, &client, credentials, kindergarten.id).await {
+            failure = Some(e);
+            break;
+        }
+        let offset = Offset {
+            inner: reporter,
+            kindergarten: &kindergarten.name,
+            albums_before,
+            album_total,
+            result_before: result.clone(),
+        };
+        albums_before += selection.albums.len();
+        let kid_settings = DownloadSettings {
+            out_dir: root.join(&folder).to_string_lossy().to_string(),
+            storage: settings.storage.within(&folder),
+            ..settings.clone()
+        };
+        match downloader::download_albums(state, selection.albums, kid_settings, &offset).await {
+            Ok(kid_result) => result.merge(&kid_result),
+            Err(e) => {
+                failure = Some(e);
+                break;
+            }
+        }
+    }
+    restore(state, &client, credentials, previous).await;
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(result),
+    }
+}
+
+async fn client(state: &AppState) -> Result<reqwest::Client> {
+    state
+        .client
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or(Error::NotLoggedIn)
+}
+
+pub(crate) async fn switch_to(
+    state: &AppState,
+    client: &reqwest::Client,
+    credentials: &Credentials,
+    kid_id: i64,
+) -> Result<Kindergarten> {
+    downloader::login(client, &state.endpoints, credentials, kid_id).await?;
+    state.select_kindergarten(kid_id).await;
+    *state.credentials.lock().await = Some(credentials.clone());
+    Ok(state
+        .kindergarten
+        .lock()
+        .await
+        .clone()
+        .expect("kindergarten was just selected"))
+}
+
+/// Log back in to the kindergarten the session was on before a
+/// multi-kindergarten operation, so the album list stays the user's choice.
+async fn restore(
+    state: &AppState,
+    client: &reqwest::Client,
+    credentials: &Credentials,
+    previous: Option<Kindergarten>,
+) {
+    let current = state.kindergarten.lock().await.as_ref().map(|k| k.id);
+    if let Some(previous) = previous.filter(|p| Some(p.id) != current) {
+        if let Err(e) = switch_to(state, client, credentials, previous.id).await {
+            eprintln!(
+                "[DEBUG] Could not switch back to kindergarten {}: {}",
+                previous.id, e
+            );
+        }
+    }
+}
+
+/// A folder name per kindergarten, with the id added where two share a name.
+pub(crate) fn folder_names<'a>(kids: impl Iterator<Item = &'a Kindergarten>) -> Vec<String> {
+    let kids: Vec<_> = kids.collect();
+    let mut seen = HashSet::new();
+    let clashing: HashSet<String> = kids
+        .iter()
+        .map(|k| naming::folder_name(&k.name).to_lowercase())
+        .filter(|name| !seen.insert(name.clone()))
+        .collect();
+    kids.iter()
+        .map(|k| {
+            let name = naming::folder_name(&k.name);
+            if clashing.contains(&name.to_lowercase()) {
+                format!("{} ({})", name, k.id)
+            } else {
+                name
+            }
+        })
+        .collect()
+}
+
+/// Numbers albums across the whole job and prefixes their titles with the
+/// kindergarten, so progress reads as one download.
+struct Offset<'a> {
+    inner: &'a dyn ProgressReporter,
+    kindergarten: &'a str,
+    albums_before: usize,
+    album_total: usize,
+    /// What the kindergartens before this one added up to.
+    result_before: DownloadResult,
+}
+
+impl ProgressReporter for Offset<'_> {
+    fn report(&self, progress: &DownloadProgress) {
+        let mut progress = progress.clone();
+        progress.album_index += self.albums_before;
+        progress.album_total = self.album_total;
+        progress.album_title = format!("{}: {}", self.kindergarten, progress.album_title);
+        // A cancel ends the whole job, so it reports the whole job's counts
+        if let Some(result) = &mut progress.result {
+            let partial = std::mem::replace(result, self.result_before.clone());
+            result.merge(&partial);
+        }
+        self.inner.report(&progress);
+    }
+}
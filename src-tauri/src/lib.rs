@@ -1,6 +1,23 @@
 use serde::{Deserialize, Serialize};
 
+pub mod dedupe;
 pub mod downloader;
+pub mod endpoints;
+pub mod error;
+pub mod export;
+pub mod gallery;
+pub mod kindergartens;
+pub mod manifest;
+pub mod metadata;
+pub mod naming;
+pub mod progress;
+pub mod queue;
+pub mod schedule;
+pub mod session;
+pub mod storage;
+pub mod sync;
+pub mod thumbnails;
+pub mod upload;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Credentials {
@@ -16,12 +33,36 @@ pub struct Kindergarten {
     pub name: String,
 }
 
+/// Albums chosen from one kindergarten, for jobs spanning several.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KindergartenAlbums {
+    pub kindergarten: Kindergarten,
+    pub albums: Vec<Album>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Album {
     pub id: String,
     pub title: String,
     pub url: String,
     pub image_count: Option<usize>,
+    /// The album's date, when Kidplan reports one.
+    #[serde(default)]
+    pub date: Option<chrono::NaiveDateTime>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Absolute URL of the album's cover image.
+    #[serde(default)]
+    pub cover_url: Option<String>,
+    /// Who posted the album.
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Department (group) of the kindergarten the album belongs to.
+    #[serde(default)]
+    pub department: Option<String>,
+    /// Every other field `GetAlbumsAsJson` sent, as received.
+    #[serde(default)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +70,40 @@ pub struct DownloadSettings {
     pub out_dir: String,
     pub delay_ms: u64,
     pub limit_per_album: usize,
+    /// Number of images fetched in parallel (1–8). `delay_ms` then spaces
+    /// out request starts across all of them rather than per request.
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// How to store an image whose content is already in the library.
+    #[serde(default)]
+    pub duplicates: dedupe::DuplicateMode,
+    /// Where to save each image relative to `out_dir`, see
+    /// [`naming::PathTemplate`]. `None` keeps the classic
+    /// `{album}/id-{image id}` layout.
+    #[serde(default)]
+    pub path_template: Option<String>,
+    /// Write the album date, title and kindergarten into each JPEG's EXIF.
+    #[serde(default)]
+    pub write_exif: bool,
+    /// Write an `.xmp` sidecar with album and kindergarten details next to
+    /// each image, leaving the image bytes untouched.
+    #[serde(default)]
+    pub write_xmp: bool,
+    /// Pack the albums into ZIP archives in `out_dir` once downloaded.
+    #[serde(default)]
+    pub zip: Option<export::ZipSettings>,
+    /// Where the images go; the manifest and other bookkeeping always stay
+    /// in `out_dir`.
+    #[serde(default)]
+    pub storage: storage::StorageConfig,
+    /// Push each saved image into this photo library, in an album named
+    /// after the Kidplan album.
+    #[serde(default)]
+    pub upload: Option<upload::UploadConfig>,
+}
+
+fn default_concurrency() -> usize {
+    1
 }
 
 impl Default for DownloadSettings {
@@ -37,10 +112,54 @@ impl Default for DownloadSettings {
             out_dir: "kidplan-albums".to_string(),
             delay_ms: 200,
             limit_per_album: 0,
+            concurrency: default_concurrency(),
+            duplicates: dedupe::DuplicateMode::default(),
+            path_template: None,
+            write_exif: false,
+            write_xmp: false,
+            zip: None,
+            storage: storage::StorageConfig::default(),
+            upload: None,
         }
     }
 }
 
+/// The storage and photo library of [`DownloadSettings`] with their
+/// passwords and keys, kept apart wherever the settings are saved.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SettingsSecrets {
+    storage: storage::StorageConfig,
+    upload: Option<upload::UploadConfig>,
+}
+
+impl DownloadSettings {
+    /// The settings without storage passwords and photo library keys, and
+    /// those, if there are any.
+    pub(crate) fn split_secrets(&self) -> (DownloadSettings, Option<SettingsSecrets>) {
+        let public = DownloadSettings {
+            storage: self.storage.without_secrets(),
+            upload: self
+                .upload
+                .as_ref()
+                .map(upload::UploadConfig::without_secrets),
+            ..self.clone()
+        };
+        let secrets = (public.storage != self.storage || public.upload != self.upload).then(|| {
+            SettingsSecrets {
+                storage: self.storage.clone(),
+                upload: self.upload.clone(),
+            }
+        });
+        (public, secrets)
+    }
+
+    /// Put back what [`DownloadSettings::split_secrets`] took out.
+    pub(crate) fn restore_secrets(&mut self, secrets: SettingsSecrets) {
+        self.storage = secrets.storage;
+        self.upload = secrets.upload;
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DownloadProgress {
     pub album_title: String,
@@ -51,31 +170,158 @@ pub struct DownloadProgress {
     pub filename: String,
     pub status: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub error_type: Option<String>,
+    pub error_type: Option<error::ErrorCode>,
+    /// Set on the update sent when the download stops at a pause; the
+    /// indexes tell where it will continue.
+    #[serde(default)]
+    pub paused: bool,
+    /// What was done before a cancel, on the final `cancelled` update.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<DownloadResult>,
+    /// The queued job the update belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<queue::JobId>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DownloadResult {
     pub total_albums: usize,
     pub total_images: usize,
     pub skipped: usize,
     pub failed: usize,
+    /// Images whose content was already in the library (included in `skipped`).
+    #[serde(default)]
+    pub duplicates: usize,
+    /// Images sent to the photo library (not counting those it already had).
+    #[serde(default)]
+    pub uploaded: usize,
+    /// The download was cancelled, so the counts cover only part of it.
+    #[serde(default)]
+    pub cancelled: bool,
+    /// Why packing the albums as ZIP failed after the download, if it did.
+    #[serde(default)]
+    pub export_error: Option<String>,
+}
+
+impl DownloadResult {
+    /// Add the counts of `other`, e.g. another kindergarten's part of a job.
+    pub fn merge(&mut self, other: &DownloadResult) {
+        self.total_albums += other.total_albums;
+        self.total_images += other.total_images;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+        self.duplicates += other.duplicates;
+        self.uploaded += other.uploaded;
+        self.cancelled |= other.cancelled;
+        if self.export_error.is_none() {
+            self.export_error = other.export_error.clone();
+        }
+    }
+}
+
+/// Pause and cancel of the downloads run with one session. Queued jobs
+/// each get their own, so stopping one leaves the next as it is.
+pub struct Controls {
+    cancel: tokio_util::sync::CancellationToken,
+    /// Holds a running download where it is while `true`.
+    paused: tokio::sync::watch::Sender<bool>,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self {
+            cancel: tokio_util::sync::CancellationToken::new(),
+            paused: tokio::sync::watch::channel(false).0,
+        }
+    }
+}
+
+impl Controls {
+    /// Stop starting new album pages and images until [`Controls::resume`].
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    /// Stop the download, interrupting its requests and backoff waits, and
+    /// release it from a pause first.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+        self.resume();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// The token the download stops on.
+    pub fn cancellation(&self) -> tokio_util::sync::CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Changes whenever the download is paused or resumed.
+    pub fn subscribe(&self) -> tokio::sync::watch::Receiver<bool> {
+        self.paused.subscribe()
+    }
 }
 
 /// Shared app state holding the authenticated HTTP client session.
 pub struct AppState {
     pub client: tokio::sync::Mutex<Option<reqwest::Client>>,
     pub cookie_jar: std::sync::Arc<reqwest::cookie::Jar>,
-    pub cancel_flag: tokio::sync::Mutex<bool>,
+    /// Pause and cancel of the downloads run with this state.
+    pub controls: std::sync::Arc<Controls>,
+    pub endpoints: endpoints::Endpoints,
+    /// Kindergartens offered for the current credentials.
+    pub kindergartens: tokio::sync::Mutex<Vec<Kindergarten>>,
+    /// The kindergarten the session is logged in to.
+    pub kindergarten: tokio::sync::Mutex<Option<Kindergarten>>,
+    /// Credentials of the last login, kept so a download can log in again
+    /// when the session expires part way.
+    pub credentials: tokio::sync::Mutex<Option<Credentials>>,
 }
 
 impl AppState {
     pub fn new() -> Self {
+        Self::with_endpoints(endpoints::Endpoints::default())
+    }
+
+    pub fn with_endpoints(endpoints: endpoints::Endpoints) -> Self {
         let jar = std::sync::Arc::new(reqwest::cookie::Jar::default());
         Self {
             client: tokio::sync::Mutex::new(None),
             cookie_jar: jar,
-            cancel_flag: tokio::sync::Mutex::new(false),
+            controls: std::sync::Arc::new(Controls::default()),
+            endpoints,
+            kindergartens: tokio::sync::Mutex::new(Vec::new()),
+            kindergarten: tokio::sync::Mutex::new(None),
+            credentials: tokio::sync::Mutex::new(None),
         }
     }
+
+    /// Remember `kid_id` as the logged-in kindergarten, looking its name up
+    /// in the list fetched earlier.
+    pub async fn select_kindergarten(&self, kid_id: i64) {
+        let kid = self
+            .kindergartens
+            .lock()
+            .await
+            .iter()
+            .find(|k| k.id == kid_id)
+            .cloned()
+            .unwrap_or_else(|| Kindergarten {
+                id: kid_id,
+                name: kid_id.to_string(),
+            });
+        *self.kindergarten.lock().await = Some(kid);
+    }
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
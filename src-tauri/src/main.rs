@@ -1,42 +1,78 @@
 use kidplan_downloader_lib::{
-    downloader, Album, AppState, Credentials, DownloadResult, DownloadSettings, Kindergarten,
+    downloader, kindergartens, Album, AppState, Credentials, DownloadResult, DownloadSettings,
+    Kindergarten, KindergartenAlbums,
 };
-use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use kidplan_downloader_lib::endpoints::Endpoints;
+use kidplan_downloader_lib::error::{Error, Result};
+use kidplan_downloader_lib::export::{self, ZipSettings};
+use kidplan_downloader_lib::gallery::{self, Gallery, GalleryLanguage};
+use kidplan_downloader_lib::manifest::Manifest;
+use kidplan_downloader_lib::naming;
+use kidplan_downloader_lib::progress::TauriReporter;
+use kidplan_downloader_lib::queue::{Job, JobId, JobQueue, JOBS_EVENT};
+use kidplan_downloader_lib::schedule::{ScheduleConfig, ScheduledRun, Scheduler, RUN_EVENT};
+use kidplan_downloader_lib::session::{self, RestoredSession, SessionStore};
+use kidplan_downloader_lib::sync::SyncSummary;
+use kidplan_downloader_lib::thumbnails;
+use std::path::PathBuf;
 
-const UA: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 13_6) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/122.0 Safari/537.36";
+/// The encrypted session cookies live in the app data directory.
+fn session_store(app: &tauri::AppHandle) -> Result<SessionStore> {
+    use tauri::Manager;
+    let dir = app.path().app_data_dir().map_err(|e| Error::Filesystem {
+        path: "app data directory".to_string(),
+        message: e.to_string(),
+    })?;
+    Ok(SessionStore::new(dir))
+}
 
-fn build_client(jar: std::sync::Arc<reqwest::cookie::Jar>) -> reqwest::Client {
-    let mut headers = HeaderMap::new();
-    headers.insert(USER_AGENT, HeaderValue::from_static(UA));
-    reqwest::Client::builder()
-        .default_headers(headers)
-        .cookie_provider(jar)
-        .redirect(reqwest::redirect::Policy::limited(10))
-        .build()
-        .expect("Failed to build HTTP client")
+/// Persist the current session, logging rather than failing the command
+/// when that is not possible.
+async fn save_session(app: &tauri::AppHandle, state: &AppState) {
+    let saved = match session_store(app) {
+        Ok(store) => store.save(state).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = saved {
+        eprintln!("[DEBUG] Could not save session: {}", e);
+    }
+}
+
+/// Re-save the session after its cookies changed, unless the user chose not
+/// to be remembered.
+async fn refresh_session(app: &tauri::AppHandle, state: &AppState) {
+    if session_store(app).is_ok_and(|store| store.is_saved()) {
+        save_session(app, state).await;
+    }
 }
 
 #[tauri::command]
 async fn get_kindergartens(
     state: tauri::State<'_, AppState>,
     credentials: Credentials,
-) -> Result<Vec<Kindergarten>, String> {
+) -> Result<Vec<Kindergarten>> {
     eprintln!("[DEBUG] get_kindergartens called for user: {}", credentials.email);
     let jar = state.cookie_jar.clone();
-    let client = build_client(jar);
-    let kids = downloader::fetch_kindergarten_ids(&client, &credentials).await?;
+    let client = downloader::build_client(jar);
+    let kids = downloader::fetch_kindergarten_ids(&client, &state.endpoints, &credentials).await?;
     eprintln!("[DEBUG] Got {} kindergartens", kids.len());
     // Store client for later use
     *state.client.lock().await = Some(client);
+    *state.kindergartens.lock().await = kids.clone();
     Ok(kids)
 }
 
+/// Log in to `kid_id`. With `remember` (the default) the session is saved
+/// so the next launch can skip the login.
 #[tauri::command]
 async fn login(
+    app: tauri::AppHandle,
     state: tauri::State<'_, AppState>,
+    queue: tauri::State<'_, JobQueue>,
     credentials: Credentials,
     kid_id: i64,
-) -> Result<(), String> {
+    remember: Option<bool>,
+) -> Result<()> {
     eprintln!("[DEBUG] login called for kid_id={}", kid_id);
     let jar = state.cookie_jar.clone();
     let needs_client = {
@@ -44,55 +80,326 @@ async fn login(
         guard.is_none()
     };
     if needs_client {
-        let c = build_client(jar);
+        let c = downloader::build_client(jar);
         *state.client.lock().await = Some(c);
     }
     let client = {
         let guard = state.client.lock().await;
         guard.as_ref().unwrap().clone()
     };
-    let result = downloader::login(&client, &credentials, kid_id).await;
+    let result = downloader::login(&client, &state.endpoints, &credentials, kid_id).await;
     eprintln!("[DEBUG] login result: {:?}", result);
+    if result.is_ok() {
+        queue.wake();
+        state.select_kindergarten(kid_id).await;
+        *state.credentials.lock().await = Some(credentials);
+        if remember.unwrap_or(true) {
+            save_session(&app, &state).await;
+        } else {
+            session_store(&app)?.clear()?;
+        }
+    }
     result
 }
 
+/// Pick up the session saved by an earlier launch, if Kidplan still accepts it.
 #[tauri::command]
-async fn fetch_albums(state: tauri::State<'_, AppState>) -> Result<Vec<Album>, String> {
+async fn restore_session(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    queue: tauri::State<'_, JobQueue>,
+) -> Result<Option<RestoredSession>> {
+    let restored = session::restore(&state, &session_store(&app)?).await;
+    if matches!(restored, Ok(Some(_))) {
+        // Jobs left from the last launch can run now
+        queue.wake();
+    }
+    eprintln!(
+        "[DEBUG] restore_session: {}",
+        match &restored {
+            Ok(Some(_)) => "restored".to_string(),
+            Ok(None) => "no valid session".to_string(),
+            Err(e) => e.to_string(),
+        }
+    );
+    restored
+}
+
+#[tauri::command]
+async fn logout(app: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<()> {
+    eprintln!("[DEBUG] logout called");
+    session::logout(&state, &session_store(&app)?).await
+}
+
+#[tauri::command]
+async fn fetch_albums(state: tauri::State<'_, AppState>) -> Result<Vec<Album>> {
     eprintln!("[DEBUG] fetch_albums called");
     let client = {
         let guard = state.client.lock().await;
-        guard.as_ref().ok_or("Not logged in")?.clone()
+        guard.as_ref().ok_or(Error::NotLoggedIn)?.clone()
     };
-    let albums = downloader::fetch_albums(&client).await;
+    let albums = downloader::fetch_albums(&client, &state.endpoints).await;
     eprintln!("[DEBUG] fetch_albums result: {} albums", albums.as_ref().map(|a| a.len()).unwrap_or(0));
     albums
 }
 
+/// Fetch the album lists of several kindergartens, logging in to each in turn.
 #[tauri::command]
-async fn start_download(
+async fn fetch_kindergarten_albums(
     app: tauri::AppHandle,
     state: tauri::State<'_, AppState>,
+    credentials: Credentials,
+    kid_ids: Vec<i64>,
+) -> Result<Vec<KindergartenAlbums>> {
+    eprintln!("[DEBUG] fetch_kindergarten_albums called for {:?}", kid_ids);
+    let groups = kindergartens::fetch_albums(&state, &credentials, &kid_ids).await;
+    // Switching kindergartens renews the session cookies
+    refresh_session(&app, &state).await;
+    groups
+}
+
+/// Download albums through the job queue, returning once the job has run.
+#[tauri::command]
+async fn start_download(
+    state: tauri::State<'_, AppState>,
+    queue: tauri::State<'_, JobQueue>,
     albums: Vec<Album>,
     settings: DownloadSettings,
-) -> Result<DownloadResult, String> {
+) -> Result<DownloadResult> {
     eprintln!("[DEBUG] start_download command invoked: {} albums", albums.len());
     for a in &albums {
         eprintln!("[DEBUG]   album: id={}, title={}, url={}", a.id, a.title, a.url);
     }
     eprintln!("[DEBUG]   settings: out_dir={}, delay_ms={}, limit={}", settings.out_dir, settings.delay_ms, settings.limit_per_album);
-    // Reset cancel flag
-    *state.cancel_flag.lock().await = false;
-    let result = downloader::download_albums(&app, &state, albums, settings).await;
+    let result = queue.enqueue_and_wait(&state, albums, settings).await;
     eprintln!("[DEBUG] start_download result: {:?}", result);
     result
 }
 
+/// Add a download job to the queue and return its id without waiting.
+#[tauri::command]
+async fn enqueue_download(
+    state: tauri::State<'_, AppState>,
+    queue: tauri::State<'_, JobQueue>,
+    albums: Vec<Album>,
+    settings: DownloadSettings,
+) -> Result<JobId> {
+    eprintln!("[DEBUG] enqueue_download called: {} albums", albums.len());
+    queue.enqueue(&state, albums, settings).await
+}
+
+#[tauri::command]
+async fn list_jobs(queue: tauri::State<'_, JobQueue>) -> Result<Vec<Job>> {
+    Ok(queue.list())
+}
+
+#[tauri::command]
+async fn cancel_job(queue: tauri::State<'_, JobQueue>, job_id: JobId) -> Result<()> {
+    eprintln!("[DEBUG] cancel_job called for job {}", job_id);
+    queue.cancel(job_id)
+}
+
+/// Move a job to `position` in the queue; 0 runs it next.
+#[tauri::command]
+async fn move_job(queue: tauri::State<'_, JobQueue>, job_id: JobId, position: usize) -> Result<()> {
+    eprintln!("[DEBUG] move_job called: job {} to {}", job_id, position);
+    queue.move_job(job_id, position)
+}
+
+#[tauri::command]
+async fn clear_finished_jobs(queue: tauri::State<'_, JobQueue>) -> Result<()> {
+    queue.clear_finished()
+}
+
+/// Download albums from several kindergartens, each into its own folder,
+/// through the job queue.
+#[tauri::command]
+async fn start_multi_download(
+    state: tauri::State<'_, AppState>,
+    queue: tauri::State<'_, JobQueue>,
+    credentials: Credentials,
+    selections: Vec<KindergartenAlbums>,
+    settings: DownloadSettings,
+) -> Result<DownloadResult> {
+    eprintln!(
+        "[DEBUG] start_multi_download command invoked: {} kindergartens",
+        selections.len()
+    );
+    // The job logs in to each kindergarten with these
+    *state.credentials.lock().await = Some(credentials);
+    let result = queue.download_kindergartens(&state, selections, settings).await;
+    eprintln!("[DEBUG] start_multi_download result: {:?}", result);
+    result
+}
+
+/// Download only what is new since the last sync, through the job queue.
+#[tauri::command]
+async fn start_sync(
+    state: tauri::State<'_, AppState>,
+    queue: tauri::State<'_, JobQueue>,
+    settings: DownloadSettings,
+) -> Result<SyncSummary> {
+    eprintln!("[DEBUG] start_sync command invoked, out_dir={}", settings.out_dir);
+    let summary = queue.sync(&state, settings).await;
+    eprintln!("[DEBUG] start_sync result: {:?}", summary);
+    summary
+}
+
+/// Pack downloaded albums of the library in `out_dir` into ZIP archives in
+/// `dest_dir`, reporting progress like a download. The packing runs on a
+/// blocking thread, since it reads every image it packs.
+#[tauri::command]
+async fn export_zip(
+    app: tauri::AppHandle,
+    albums: Vec<Album>,
+    out_dir: String,
+    zip: ZipSettings,
+    dest_dir: String,
+) -> Result<Vec<String>> {
+    eprintln!("[DEBUG] export_zip called: {} albums to {}", albums.len(), dest_dir);
+    let manifest = Manifest::open(&downloader::resolve_out_dir(&out_dir))?;
+    // A token of its own, so a cancelled download job can't stop the export
+    let archives = export::zip_albums_in_background(
+        manifest,
+        albums,
+        PathBuf::from(dest_dir),
+        zip,
+        &TauriReporter::new(app),
+        tokio_util::sync::CancellationToken::new(),
+    )
+    .await?;
+    Ok(archives.iter().map(|p| p.display().to_string()).collect())
+}
+
+/// Write a static HTML gallery of the library in `out_dir` that opens in
+/// any browser, without the app. Runs away from the async runtime, since it
+/// makes thumbnails of new images.
+#[tauri::command]
+async fn generate_gallery(out_dir: String, language: GalleryLanguage) -> Result<Gallery> {
+    eprintln!("[DEBUG] generate_gallery called for {}", out_dir);
+    let root = downloader::resolve_out_dir(&out_dir);
+    tauri::async_runtime::spawn_blocking(move || gallery::write_gallery(&root, language))
+        .await
+        .map_err(|e| Error::fs(&out_dir, e))?
+}
+
+/// Find or make a thumbnail away from the async runtime, since making one
+/// decodes a full-size image.
+async fn thumbnail_of(
+    out_dir: String,
+    album_id: String,
+    image_id: Option<String>,
+    size: Option<u32>,
+) -> Result<Option<PathBuf>> {
+    let root = downloader::resolve_out_dir(&out_dir);
+    tauri::async_runtime::spawn_blocking(move || {
+        let size = size.unwrap_or(thumbnails::DEFAULT_SIZE);
+        thumbnails::find(&root, &album_id, image_id.as_deref(), size)
+    })
+    .await
+    .map_err(|e| Error::fs(&out_dir, e))?
+}
+
+/// Path of a thumbnail of image `image_id` of album `album_id`, or of the
+/// album's cover without `image_id`; `None` while nothing of it is
+/// downloaded.
+#[tauri::command]
+async fn get_thumbnail(
+    out_dir: String,
+    album_id: String,
+    image_id: Option<String>,
+    size: Option<u32>,
+) -> Result<Option<String>> {
+    let thumbnail = thumbnail_of(out_dir, album_id, image_id, size).await?;
+    Ok(thumbnail.map(|p| p.display().to_string()))
+}
+
+/// The JPEG bytes of the thumbnail `get_thumbnail` finds, sent without
+/// JSON encoding; empty while there is none.
+#[tauri::command]
+async fn read_thumbnail(
+    out_dir: String,
+    album_id: String,
+    image_id: Option<String>,
+    size: Option<u32>,
+) -> Result<tauri::ipc::Response> {
+    let bytes = match thumbnail_of(out_dir, album_id, image_id, size).await? {
+        Some(path) => std::fs::read(&path).map_err(|e| Error::fs(&path, e))?,
+        None => Vec::new(),
+    };
+    Ok(tauri::ipc::Response::new(bytes))
+}
+
+/// Render sample paths for `template` so the settings page can show its effect.
+#[tauri::command]
+async fn preview_paths(
+    state: tauri::State<'_, AppState>,
+    template: String,
+    albums: Vec<Album>,
+) -> Result<Vec<String>> {
+    let kindergarten = state.kindergarten.lock().await.clone();
+    naming::preview(&template, &albums, kindergarten.as_ref().map(|k| k.name.as_str()))
+}
+
 #[tauri::command]
-async fn cancel_download(state: tauri::State<'_, AppState>) -> Result<(), String> {
-    *state.cancel_flag.lock().await = true;
+async fn cancel_download(queue: tauri::State<'_, JobQueue>) -> Result<()> {
+    queue.cancel_running();
     Ok(())
 }
 
+/// Hold the running download after the images in flight; it keeps its place.
+#[tauri::command]
+async fn pause_download(queue: tauri::State<'_, JobQueue>) -> Result<()> {
+    eprintln!("[DEBUG] pause_download called");
+    queue.pause_running();
+    Ok(())
+}
+
+#[tauri::command]
+async fn resume_download(queue: tauri::State<'_, JobQueue>) -> Result<()> {
+    eprintln!("[DEBUG] resume_download called");
+    queue.resume_running();
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_schedule(scheduler: tauri::State<'_, Scheduler>) -> Result<Option<ScheduleConfig>> {
+    Ok(scheduler.config())
+}
+
+/// Set up (or with `null`, remove) the background sync schedule.
+#[tauri::command]
+async fn set_schedule(
+    scheduler: tauri::State<'_, Scheduler>,
+    config: Option<ScheduleConfig>,
+) -> Result<()> {
+    eprintln!("[DEBUG] set_schedule called: {:?}", config.as_ref().map(|c| &c.schedule));
+    scheduler.configure(config)
+}
+
+#[tauri::command]
+async fn list_scheduled_runs(scheduler: tauri::State<'_, Scheduler>) -> Result<Vec<ScheduledRun>> {
+    Ok(scheduler.runs())
+}
+
+/// Run the scheduled sync now instead of waiting for its time.
+#[tauri::command]
+async fn run_scheduled_sync(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    queue: tauri::State<'_, JobQueue>,
+    scheduler: tauri::State<'_, Scheduler>,
+) -> Result<Option<ScheduledRun>> {
+    use tauri::Emitter;
+    eprintln!("[DEBUG] run_scheduled_sync called");
+    let sessions = session_store(&app)?;
+    let run = scheduler.run_now(&state, &queue, Some(&sessions)).await;
+    if let Some(run) = &run {
+        let _ = app.emit(RUN_EVENT, run);
+    }
+    Ok(run)
+}
+
 fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_updater::Builder::new().build())
@@ -100,13 +407,71 @@ fn main() {
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
-        .manage(AppState::new())
+        .manage(AppState::with_endpoints(Endpoints::from_env()))
+        .setup(|app| {
+            use tauri::{Emitter, Manager};
+            let data_dir = app.path().app_data_dir()?;
+            app.manage(JobQueue::open(&data_dir)?);
+            app.manage(Scheduler::open(&data_dir)?);
+
+            // Queued jobs run in the background for as long as the app does
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let state = handle.state::<AppState>();
+                let queue = handle.state::<JobQueue>();
+                queue.run(&state, &TauriReporter::new(handle.clone())).await;
+            });
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let queue = handle.state::<JobQueue>();
+                let mut changed = queue.subscribe();
+                while changed.changed().await.is_ok() {
+                    let _ = handle.emit(JOBS_EVENT, queue.list());
+                }
+            });
+            // Scheduled syncs use the saved session, so they run without
+            // anyone logging in; failures reach the frontend with the run
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let state = handle.state::<AppState>();
+                let queue = handle.state::<JobQueue>();
+                let scheduler = handle.state::<Scheduler>();
+                let sessions = session_store(&handle).ok();
+                let emitter = handle.clone();
+                let on_run = move |run: &ScheduledRun| {
+                    let _ = emitter.emit(RUN_EVENT, run);
+                };
+                scheduler.run(&state, &queue, sessions.as_ref(), &on_run).await;
+            });
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             get_kindergartens,
             login,
+            restore_session,
+            logout,
             fetch_albums,
+            fetch_kindergarten_albums,
             start_download,
+            enqueue_download,
+            list_jobs,
+            cancel_job,
+            move_job,
+            clear_finished_jobs,
+            start_multi_download,
+            start_sync,
+            preview_paths,
             cancel_download,
+            pause_download,
+            resume_download,
+            export_zip,
+            generate_gallery,
+            get_thumbnail,
+            read_thumbnail,
+            get_schedule,
+            set_schedule,
+            list_scheduled_runs,
+            run_scheduled_sync,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
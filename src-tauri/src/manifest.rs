@@ -0,0 +1,461 @@
+use crate::downloader::slugify;
+use crate::error::{Error, Result};
+use crate::storage::Listing;
+use crate::Album;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Directory inside the library root holding the downloader's own bookkeeping.
+pub const STATE_DIR: &str = ".kidplan";
+const MANIFEST_FILE: &str = "manifest.jsonl";
+/// Per-album description written next to the album's images.
+pub const ALBUM_MANIFEST_FILE: &str = "album.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryStatus {
+    Complete,
+    Failed,
+}
+
+/// What is known about one image of one album.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub album_id: String,
+    pub image_id: String,
+    pub source_url: String,
+    /// Path relative to the library root, with `/` separators.
+    pub path: String,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// SHA-256 of the image as Kidplan sent it, when the stored file differs
+    /// from that, e.g. with EXIF written. Duplicates are found by this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_sha256: Option<String>,
+    pub status: EntryStatus,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ManifestEntry {
+    /// SHA-256 of the image as downloaded, before anything was written to it.
+    pub fn content_sha256(&self) -> Option<&str> {
+        self.source_sha256.as_deref().or(self.sha256.as_deref())
+    }
+}
+
+/// Contents of an album's `album.json`, for tools reading the library.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlbumManifest {
+    pub album_id: String,
+    pub title: String,
+    pub url: String,
+    pub kindergarten: Option<String>,
+    #[serde(default)]
+    pub date: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub department: Option<String>,
+    /// Number of images Kidplan reports for the album.
+    pub image_count: Option<usize>,
+    pub downloaded_at: DateTime<Utc>,
+    pub images: Vec<AlbumImage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlbumImage {
+    pub image_id: String,
+    pub source_url: String,
+    pub file: String,
+    /// Path relative to the library root, with `/` separators. Differs from
+    /// the album folder for duplicates stored in another album.
+    pub path: String,
+    pub size: u64,
+    pub sha256: Option<String>,
+}
+
+/// How a stored image compares with its manifest entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Recorded as complete and the file is stored with the recorded size.
+    Complete,
+    /// Recorded as complete, but the file is missing or truncated.
+    Stale,
+    /// Recorded as failed; try again.
+    Failed,
+    /// No entry yet.
+    Untracked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Ok,
+    Missing,
+    SizeMismatch,
+    ChecksumMismatch,
+}
+
+/// Per-library record of every attempted image, used to resume interrupted
+/// runs and to verify the library afterwards.
+///
+/// Entries are appended to a JSON Lines journal as they happen, so a crash
+/// loses at most the line being written; [`Manifest::compact`] rewrites the
+/// journal with one line per image.
+pub struct Manifest {
+    root: PathBuf,
+    entries: HashMap<(String, String), ManifestEntry>,
+    /// Which images each recorded path belongs to.
+    paths: HashMap<String, HashSet<(String, String)>>,
+    journal: File,
+}
+
+impl Manifest {
+    pub fn open(root: &Path) -> Result<Self> {
+        let dir = root.join(STATE_DIR);
+        std::fs::create_dir_all(&dir).map_err(|e| Error::fs(&dir, e))?;
+        let path = dir.join(MANIFEST_FILE);
+
+        let mut entries = HashMap::new();
+        if let Ok(file) = File::open(&path) {
+            for line in BufReader::new(file).lines() {
+                let Ok(line) = line else { break };
+                // A torn final line from a crash is simply ignored
+                if let Ok(entry) = serde_json::from_str::<ManifestEntry>(&line) {
+                    entries.insert((entry.album_id.clone(), entry.image_id.clone()), entry);
+                }
+            }
+        }
+
+        let mut paths: HashMap<String, HashSet<(String, String)>> = HashMap::new();
+        for (key, entry) in &entries {
+            paths.entry(entry.path.clone()).or_default().insert(key.clone());
+        }
+
+        let journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| Error::fs(&path, e))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            entries,
+            paths,
+            journal,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn get(&self, album_id: &str, image_id: &str) -> Option<&ManifestEntry> {
+        self.entries
+            .get(&(album_id.to_string(), image_id.to_string()))
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &ManifestEntry> {
+        self.entries.values()
+    }
+
+    /// Where the image stands, given `stored`, the listing of the storage
+    /// the library's images are kept in.
+    pub fn resume_state(&self, album_id: &str, image_id: &str, stored: &Listing) -> Resume {
+        match self.get(album_id, image_id) {
+            None => Resume::Untracked,
+            Some(entry) if entry.status == EntryStatus::Failed => Resume::Failed,
+            Some(entry) if stored.get(&entry.path) == Some(&entry.size) => Resume::Complete,
+            Some(_) => Resume::Stale,
+        }
+    }
+
+    /// Whether `path` is recorded for some image other than this one.
+    pub fn path_taken(&self, path: &Path, album_id: &str, image_id: &str) -> bool {
+        self.paths.get(&self.relative(path)).is_some_and(|owners| {
+            !owners.contains(&(album_id.to_string(), image_id.to_string()))
+                && !owners.is_empty()
+        })
+    }
+
+    /// A complete entry stored at `path` (relative, as in entries).
+    pub fn entry_at(&self, path: &str) -> Option<&ManifestEntry> {
+        self.paths
+            .get(path)?
+            .iter()
+            .filter_map(|key| self.entries.get(key))
+            .find(|e| e.status == EntryStatus::Complete && e.path == path)
+    }
+
+    pub fn record(&mut self, entry: ManifestEntry) -> Result<()> {
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        self.journal
+            .write_all(line.as_bytes())
+            .and_then(|_| self.journal.flush())
+            .map_err(|e| Error::fs(self.journal_path(), e))?;
+        let key = (entry.album_id.clone(), entry.image_id.clone());
+        if let Some(old) = self.entries.get(&key) {
+            if let Some(owners) = self.paths.get_mut(&old.path) {
+                owners.remove(&key);
+            }
+        }
+        self.paths
+            .entry(entry.path.clone())
+            .or_default()
+            .insert(key.clone());
+        self.entries.insert(key, entry);
+        Ok(())
+    }
+
+    /// Record a completed download stored at `path`. `sha256` is the hash of
+    /// the stored file and `source_sha256` that of the downloaded image.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_complete(
+        &mut self,
+        album_id: &str,
+        image_id: &str,
+        source_url: &str,
+        path: &Path,
+        size: u64,
+        sha256: String,
+        source_sha256: String,
+    ) -> Result<()> {
+        self.record(ManifestEntry {
+            album_id: album_id.to_string(),
+            image_id: image_id.to_string(),
+            source_url: source_url.to_string(),
+            path: self.relative(path),
+            size,
+            source_sha256: (source_sha256 != sha256).then_some(source_sha256),
+            sha256: Some(sha256),
+            status: EntryStatus::Complete,
+            updated_at: Utc::now(),
+        })
+    }
+
+    /// Take over a file that was downloaded before the manifest existed.
+    pub fn adopt(
+        &mut self,
+        album_id: &str,
+        image_id: &str,
+        source_url: &str,
+        path: &Path,
+    ) -> Result<()> {
+        let bytes = std::fs::read(path).map_err(|e| Error::fs(path, e))?;
+        let sha256 = sha256_hex(&bytes);
+        self.record_complete(
+            album_id,
+            image_id,
+            source_url,
+            path,
+            bytes.len() as u64,
+            sha256.clone(),
+            sha256,
+        )
+    }
+
+    /// Take over a file found in remote storage before the manifest knew
+    /// it. Its content is not fetched, so no checksum is recorded.
+    pub fn adopt_stored(
+        &mut self,
+        album_id: &str,
+        image_id: &str,
+        source_url: &str,
+        path: &Path,
+        size: u64,
+    ) -> Result<()> {
+        self.record(ManifestEntry {
+            album_id: album_id.to_string(),
+            image_id: image_id.to_string(),
+            source_url: source_url.to_string(),
+            path: self.relative(path),
+            size,
+            sha256: None,
+            source_sha256: None,
+            status: EntryStatus::Complete,
+            updated_at: Utc::now(),
+        })
+    }
+
+    pub fn record_failed(
+        &mut self,
+        album_id: &str,
+        image_id: &str,
+        source_url: &str,
+        path: &Path,
+    ) -> Result<()> {
+        self.record(ManifestEntry {
+            album_id: album_id.to_string(),
+            image_id: image_id.to_string(),
+            source_url: source_url.to_string(),
+            path: self.relative(path),
+            size: 0,
+            sha256: None,
+            source_sha256: None,
+            status: EntryStatus::Failed,
+            updated_at: Utc::now(),
+        })
+    }
+
+    /// Check a complete entry against the file on disk, including its checksum.
+    pub fn verify(&self, entry: &ManifestEntry) -> Verification {
+        let path = self.root.join(&entry.path);
+        let Ok(bytes) = std::fs::read(&path) else {
+            return Verification::Missing;
+        };
+        if bytes.len() as u64 != entry.size {
+            return Verification::SizeMismatch;
+        }
+        match &entry.sha256 {
+            Some(expected) if *expected != sha256_hex(&bytes) => Verification::ChecksumMismatch,
+            _ => Verification::Ok,
+        }
+    }
+
+    /// Rewrite the journal with only the latest entry per image.
+    pub fn compact(&mut self) -> Result<()> {
+        let mut entries: Vec<&ManifestEntry> = self.entries.values().collect();
+        entries.sort_by(|a, b| (&a.album_id, &a.path).cmp(&(&b.album_id, &b.path)));
+        let mut out = String::new();
+        for entry in entries {
+            out.push_str(&serde_json::to_string(entry)?);
+            out.push('\n');
+        }
+        let path = self.journal_path();
+        write_atomic(&path, out.as_bytes())?;
+        self.journal = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(|e| Error::fs(&path, e))?;
+        Ok(())
+    }
+
+    /// Write `album.json` into the folder holding the album's images,
+    /// listing every image of the album recorded as complete. Returns where
+    /// it was written, or `None` when nothing of the album is stored yet.
+    ///
+    /// When file name templates put several albums in one folder, each gets
+    /// an `album-<id>.json` there instead.
+    pub fn write_album_manifest(
+        &self,
+        album: &Album,
+        kindergarten: Option<&str>,
+    ) -> Result<Option<PathBuf>> {
+        let mut entries: Vec<&ManifestEntry> = self
+            .entries
+            .values()
+            .filter(|e| e.album_id == album.id && e.status == EntryStatus::Complete)
+            .collect();
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let album_json = AlbumManifest {
+            album_id: album.id.clone(),
+            title: album.title.clone(),
+            url: album.url.clone(),
+            kindergarten: kindergarten.map(str::to_string),
+            date: album.date,
+            description: album.description.clone(),
+            author: album.author.clone(),
+            department: album.department.clone(),
+            image_count: album.image_count,
+            downloaded_at: Utc::now(),
+            images: entries
+                .iter()
+                .map(|e| AlbumImage {
+                    image_id: e.image_id.clone(),
+                    source_url: e.source_url.clone(),
+                    file: e.path.rsplit('/').next().unwrap_or(&e.path).to_string(),
+                    path: e.path.clone(),
+                    size: e.size,
+                    sha256: e.sha256.clone(),
+                })
+                .collect(),
+        };
+
+        let dir = self.root.join(common_dir(&entries));
+        let mut path = dir.join(ALBUM_MANIFEST_FILE);
+        let shared = dir == self.root
+            || std::fs::read(&path)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<AlbumManifest>(&bytes).ok())
+                .is_some_and(|other| other.album_id != album.id);
+        if shared {
+            path = dir.join(format!("album-{}.json", slugify(&album.id)));
+        }
+        // With remote storage, the folder holds only this local copy
+        std::fs::create_dir_all(&dir).map_err(|e| Error::fs(&dir, e))?;
+        write_atomic(&path, &serde_json::to_vec_pretty(&album_json)?)?;
+        Ok(Some(path))
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.root.join(STATE_DIR).join(MANIFEST_FILE)
+    }
+
+    /// `path` relative to the library root, in the form stored in entries.
+    pub fn relative(&self, path: &Path) -> String {
+        let rel = path.strip_prefix(&self.root).unwrap_or(path);
+        rel.components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// The folder holding most of `entries`, so that duplicates kept in another
+/// album's folder don't decide where the album is.
+fn common_dir(entries: &[&ManifestEntry]) -> String {
+    let dir_of = |path: &str| path.rsplit_once('/').map_or("", |(dir, _)| dir).to_string();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for entry in entries {
+        *counts.entry(dir_of(&entry.path)).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+        .map(|(dir, _)| dir)
+        .unwrap_or_default()
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Write `bytes` to a `.part` file next to `path` and rename it into place,
+/// so readers never observe a half-written file.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp = part_path(path);
+    let result = File::create(&tmp)
+        .and_then(|mut f| f.write_all(bytes).and_then(|_| f.sync_all()))
+        .and_then(|_| std::fs::rename(&tmp, path));
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(Error::fs(path, e));
+    }
+    Ok(())
+}
+
+/// Where [`write_atomic`] puts `path`'s content until it is complete.
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".part");
+    PathBuf::from(tmp)
+}
+
+/// Remove what an interrupted [`write_atomic`] of `path` left behind.
+pub fn remove_partial(path: &Path) {
+    let tmp = part_path(path);
+    if std::fs::remove_file(&tmp).is_ok() {
+        eprintln!("[DEBUG] Removed partial file {:?}", tmp);
+    }
+}
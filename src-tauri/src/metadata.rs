@@ -0,0 +1,296 @@
+use crate::error::{Error, Result};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use exif::{Field, In, Tag, Value};
+use std::io::Cursor;
+
+/// What we know about a downloaded image beyond its bytes.
+#[derive(Debug, Clone, Default)]
+pub struct ImageMetadata {
+    /// When the photo was taken or posted, as reported by Kidplan. Never the
+    /// download time or a file timestamp.
+    pub date: Option<NaiveDateTime>,
+    pub album_id: String,
+    pub album_title: String,
+    pub kindergarten: Option<String>,
+    pub image_id: String,
+    pub source_url: String,
+    /// Caption shown with the image on the album page.
+    pub caption: Option<String>,
+}
+
+/// Parse a date as Kidplan sends it: ASP.NET `/Date(ms)/`, epoch
+/// milliseconds, ISO 8601 with or without time and offset, or `dd.mm.yyyy`.
+pub fn parse_date(value: &serde_json::Value) -> Option<NaiveDateTime> {
+    let from_millis = |ms: i64| {
+        DateTime::from_timestamp_millis(ms).map(|d| d.with_timezone(&Local).naive_local())
+    };
+    let text = match value {
+        serde_json::Value::Number(n) => return from_millis(n.as_i64()?),
+        serde_json::Value::String(s) => s.trim(),
+        _ => return None,
+    };
+    if let Some(inner) = text.strip_prefix("/Date(").and_then(|s| s.strip_suffix(")/")) {
+        // The optional "+0200" suffix only describes the sender's zone
+        let digits = inner
+            .char_indices()
+            .find(|(i, c)| *i > 0 && (*c == '+' || *c == '-'))
+            .map_or(inner, |(i, _)| &inner[..i]);
+        return from_millis(digits.parse().ok()?);
+    }
+    if let Ok(d) = DateTime::parse_from_rfc3339(text) {
+        return Some(d.with_timezone(&Local).naive_local());
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+        if let Ok(d) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(d);
+        }
+    }
+    for format in ["%Y-%m-%d", "%d.%m.%Y"] {
+        if let Ok(d) = NaiveDate::parse_from_str(text, format) {
+            return d.and_hms_opt(0, 0, 0);
+        }
+    }
+    None
+}
+
+/// A local time that may fall in a DST gap, moved past the gap if so.
+pub(crate) fn local_at(time: NaiveDateTime) -> DateTime<Local> {
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .unwrap_or_else(|| local_at(time + Duration::hours(1)))
+}
+
+const APP1: u8 = 0xE1;
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+/// Return `jpeg` with `DateTimeOriginal`, `ImageDescription` and `Artist`
+/// set from `meta`.
+///
+/// Values already present in the photo's own EXIF are kept, since a camera
+/// knows better than Kidplan when a picture was taken; the rest of the
+/// existing EXIF is carried over, apart from its embedded thumbnail.
+pub fn embed_exif(jpeg: &[u8], meta: &ImageMetadata) -> Result<Vec<u8>> {
+    let segments = jpeg_segments(jpeg).ok_or_else(|| Error::Parse("not a valid JPEG".to_string()))?;
+
+    let existing = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(jpeg))
+        .ok();
+    let mut fields: Vec<Field> = existing
+        .iter()
+        .flat_map(|e| e.fields())
+        .filter(|f| f.ifd_num == In::PRIMARY)
+        .cloned()
+        .collect();
+    let has = |fields: &[Field], tag: Tag| fields.iter().any(|f| f.tag == tag);
+
+    let mut added = false;
+    if let Some(date) = meta.date {
+        if !has(&fields, Tag::DateTimeOriginal) {
+            fields.push(ascii(Tag::DateTimeOriginal, &date.format("%Y:%m:%d %H:%M:%S").to_string()));
+            added = true;
+        }
+    }
+    if !meta.album_title.is_empty() && !has(&fields, Tag::ImageDescription) {
+        fields.push(ascii(Tag::ImageDescription, &meta.album_title));
+        added = true;
+    }
+    if let Some(kindergarten) = meta.kindergarten.as_deref().filter(|k| !k.is_empty()) {
+        if !has(&fields, Tag::Artist) {
+            fields.push(ascii(Tag::Artist, kindergarten));
+            added = true;
+        }
+    }
+    if !added {
+        return Ok(jpeg.to_vec());
+    }
+
+    let mut writer = exif::experimental::Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut tiff = Cursor::new(Vec::new());
+    let little_endian = existing.as_ref().is_some_and(|e| e.little_endian());
+    writer
+        .write(&mut tiff, little_endian)
+        .map_err(|e| Error::Parse(format!("could not encode EXIF: {}", e)))?;
+
+    let mut payload = EXIF_HEADER.to_vec();
+    payload.extend_from_slice(&tiff.into_inner());
+    let length = u16::try_from(payload.len() + 2)
+        .map_err(|_| Error::Parse("EXIF data too large for a JPEG segment".to_string()))?;
+
+    let mut out = Vec::with_capacity(jpeg.len() + payload.len() + 4);
+    out.extend_from_slice(&jpeg[..2]);
+    let mut inserted = false;
+    for segment in &segments {
+        let is_exif = segment.marker == APP1 && segment.payload(jpeg).starts_with(EXIF_HEADER);
+        // EXIF goes first, after a JFIF header if there is one
+        if !inserted && segment.marker != 0xE0 {
+            out.extend_from_slice(&[0xFF, APP1]);
+            out.extend_from_slice(&length.to_be_bytes());
+            out.extend_from_slice(&payload);
+            inserted = true;
+        }
+        if !is_exif {
+            out.extend_from_slice(&jpeg[segment.start..segment.end]);
+        }
+    }
+    Ok(out)
+}
+
+fn ascii(tag: Tag, text: &str) -> Field {
+    // EXIF ASCII is 7-bit in theory, but every reader accepts UTF-8 in practice
+    Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![text.as_bytes().to_vec()]),
+    }
+}
+
+struct Segment {
+    marker: u8,
+    start: usize,
+    end: usize,
+}
+
+impl Segment {
+    fn payload<'a>(&self, jpeg: &'a [u8]) -> &'a [u8] {
+        jpeg.get(self.start + 4..self.end).unwrap_or(&[])
+    }
+}
+
+/// Split a JPEG into its header segments, with everything from the first
+/// start-of-scan onwards as one final segment.
+fn jpeg_segments(jpeg: &[u8]) -> Option<Vec<Segment>> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    loop {
+        if *jpeg.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *jpeg.get(pos + 1)?;
+        // Start of scan or end of image: the rest is image data
+        if marker == 0xDA || marker == 0xD9 {
+            segments.push(Segment {
+                marker,
+                start: pos,
+                end: jpeg.len(),
+            });
+            return Some(segments);
+        }
+        let length = u16::from_be_bytes([*jpeg.get(pos + 2)?, *jpeg.get(pos + 3)?]) as usize;
+        let end = pos + 2 + length;
+        if length < 2 || end > jpeg.len() {
+            return None;
+        }
+        segments.push(Segment {
+            marker,
+            start: pos,
+            end,
+        });
+        pos = end;
+    }
+}
+
+/// Namespace for the Kidplan-specific properties in XMP sidecars.
+const KIDPLAN_NS: &str = "https://github.com/SjoenH/kidplan-downloader/ns/1.0/";
+
+/// Where the XMP sidecar of the image stored at `image` goes: `photo.jpg`
+/// gets `photo.jpg.xmp`, the name darktable and digiKam look for.
+pub fn sidecar_path(image: &str) -> String {
+    format!("{}.xmp", image)
+}
+
+/// An XMP sidecar describing one image.
+///
+/// The album is written both as `xmpDM:album` and as a hierarchical keyword
+/// (`Kidplan|kindergarten|album`), which is how digiKam and darktable show
+/// collections; the Kidplan ids and source URL go in a `kidplan:` namespace.
+pub fn xmp_sidecar(meta: &ImageMetadata) -> String {
+    let kindergarten = meta.kindergarten.as_deref().unwrap_or("");
+    let mut props = String::new();
+    let mut simple = |name: &str, value: &str| {
+        if !value.is_empty() {
+            props.push_str(&format!("   <{0}>{1}</{0}>\n", name, escape_xml(value)));
+        }
+    };
+    simple("xmpDM:album", &meta.album_title);
+    simple("photoshop:Credit", kindergarten);
+    if let Some(date) = meta.date {
+        simple("photoshop:DateCreated", &date.format("%Y-%m-%dT%H:%M:%S").to_string());
+    }
+    simple("dc:source", &meta.source_url);
+    simple("kidplan:AlbumId", &meta.album_id);
+    simple("kidplan:AlbumTitle", &meta.album_title);
+    simple("kidplan:ImageId", &meta.image_id);
+    simple("kidplan:Kindergarten", kindergarten);
+    simple("kidplan:SourceUrl", &meta.source_url);
+
+    if let Some(caption) = meta.caption.as_deref().filter(|c| !c.is_empty()) {
+        props.push_str(&format!(
+            "   <dc:description>\n    <rdf:Alt>\n     <rdf:li xml:lang=\"x-default\">{}</rdf:li>\n    </rdf:Alt>\n   </dc:description>\n",
+            escape_xml(caption)
+        ));
+    }
+
+    let keywords: Vec<&str> = [meta.album_title.as_str(), kindergarten]
+        .into_iter()
+        .filter(|k| !k.is_empty())
+        .collect();
+    if !keywords.is_empty() {
+        props.push_str(&bag("dc:subject", &keywords));
+        let mut path = vec!["Kidplan"];
+        path.extend(keywords.iter().rev());
+        props.push_str(&bag("lr:hierarchicalSubject", &[&path.join("|")]));
+    }
+
+    format!(
+        r#"<?xpacket begin="{bom}" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:xmpDM="http://ns.adobe.com/xmp/1.0/DynamicMedia/"
+    xmlns:photoshop="http://ns.adobe.com/photoshop/1.0/"
+    xmlns:lr="http://ns.adobe.com/lightroom/1.0/"
+    xmlns:kidplan="{ns}">
+{props}  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>
+"#,
+        bom = '\u{FEFF}',
+        ns = KIDPLAN_NS,
+        props = props,
+    )
+}
+
+fn bag(name: &str, items: &[&str]) -> String {
+    let mut out = format!("   <{}>\n    <rdf:Bag>\n", name);
+    for item in items {
+        out.push_str(&format!("     <rdf:li>{}</rdf:li>\n", escape_xml(item)));
+    }
+    out.push_str(&format!("    </rdf:Bag>\n   </{}>\n", name));
+    out
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Control characters other than whitespace are not allowed in XML
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
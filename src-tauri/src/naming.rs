@@ -0,0 +1,342 @@
+use crate::downloader::slugify;
+use crate::error::{Error, Result};
+use crate::Album;
+use chrono::{Datelike, NaiveDate};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Longest file or folder name a placeholder may expand to, in characters.
+const MAX_COMPONENT_CHARS: usize = 150;
+
+/// Placeholders available in a path template, e.g.
+/// `{year}/{year}-{month} {album_title}/{index:03} {image_id}.{ext}`.
+pub const PLACEHOLDERS: &[&str] = &[
+    "album_title",
+    "album_slug",
+    "album_id",
+    "kindergarten",
+    "department",
+    "author",
+    "image_id",
+    "index",
+    "ext",
+    "year",
+    "month",
+    "day",
+    "date",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    AlbumTitle,
+    AlbumSlug,
+    AlbumId,
+    Kindergarten,
+    Department,
+    Author,
+    ImageId,
+    Index,
+    Ext,
+    Year,
+    Month,
+    Day,
+    Date,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "album_title" => Field::AlbumTitle,
+            "album_slug" => Field::AlbumSlug,
+            "album_id" => Field::AlbumId,
+            "kindergarten" => Field::Kindergarten,
+            "department" => Field::Department,
+            "author" => Field::Author,
+            "image_id" => Field::ImageId,
+            "index" => Field::Index,
+            "ext" => Field::Ext,
+            "year" => Field::Year,
+            "month" => Field::Month,
+            "day" => Field::Day,
+            "date" => Field::Date,
+            _ => return None,
+        })
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Field::Index | Field::Year | Field::Month | Field::Day)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Field { field: Field, width: usize },
+}
+
+/// Everything a template can refer to for one image.
+#[derive(Debug, Clone)]
+pub struct PathContext<'a> {
+    pub album: &'a Album,
+    pub kindergarten: Option<&'a str>,
+    /// Kidplan image id without its extension.
+    pub image_id: &'a str,
+    /// 1-based position of the image within its album.
+    pub index: usize,
+    /// Lowercase extension without the dot.
+    pub ext: &'a str,
+    /// The album's date, or the download day when Kidplan gives none.
+    pub date: NaiveDate,
+}
+
+/// A parsed path template. `/` separates folders; `{name}` or `{name:03}`
+/// inserts a placeholder (the latter zero-padded); `{{` and `}}` are literal
+/// braces.
+///
+/// Placeholder values are escaped so they can never add folders, climb out of
+/// the output directory or produce names Windows refuses.
+#[derive(Debug, Clone)]
+pub struct PathTemplate {
+    components: Vec<Vec<Segment>>,
+}
+
+impl FromStr for PathTemplate {
+    type Err = Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        let invalid = |msg: &str| Error::InvalidTemplate(format!("{}: {}", msg, template));
+        let trimmed = template.trim();
+        if trimmed.is_empty() {
+            return Err(invalid("template is empty"));
+        }
+        if trimmed.starts_with('/') {
+            return Err(invalid("template must be a relative path"));
+        }
+
+        let mut components = Vec::new();
+        for part in trimmed.split('/') {
+            let segments = parse_component(part).map_err(|msg| invalid(&msg))?;
+            let literal_only: String = segments
+                .iter()
+                .map(|s| match s {
+                    Segment::Literal(text) => text.as_str(),
+                    Segment::Field { .. } => "\u{0}",
+                })
+                .collect();
+            if literal_only.trim().is_empty() {
+                return Err(invalid("template contains an empty folder name"));
+            }
+            if literal_only == "." || literal_only == ".." {
+                return Err(invalid("template may not contain '.' or '..' folders"));
+            }
+            components.push(segments);
+        }
+        Ok(Self { components })
+    }
+}
+
+fn parse_component(part: &str) -> std::result::Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = part.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => spec.push(c),
+                        None => return Err("unclosed '{'".to_string()),
+                    }
+                }
+                let (name, width) = match spec.split_once(':') {
+                    Some((name, width)) => (name.trim(), Some(width.trim())),
+                    None => (spec.trim(), None),
+                };
+                let field = Field::parse(name).ok_or_else(|| {
+                    format!(
+                        "unknown placeholder '{{{}}}' (available: {})",
+                        name,
+                        PLACEHOLDERS.join(", ")
+                    )
+                })?;
+                let width = match width {
+                    None => 0,
+                    Some(_) if !field.is_numeric() => {
+                        return Err(format!("'{{{}}}' does not take a width", name))
+                    }
+                    Some(w) => w
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|w| *w <= 10)
+                        .ok_or_else(|| format!("invalid width in '{{{}}}'", spec))?,
+                };
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Field { field, width });
+            }
+            '}' => return Err("unmatched '}'".to_string()),
+            '\\' => return Err("use '/' to separate folders".to_string()),
+            ':' | '*' | '?' | '"' | '<' | '>' | '|' => {
+                return Err(format!("'{}' is not allowed in file names", ch))
+            }
+            _ => literal.push(ch),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+impl PathTemplate {
+    /// The path for one image, relative to the output directory.
+    pub fn render(&self, ctx: &PathContext<'_>) -> PathBuf {
+        self.components
+            .iter()
+            .map(|segments| {
+                let mut name = String::new();
+                for segment in segments {
+                    match segment {
+                        Segment::Literal(text) => name.push_str(text),
+                        Segment::Field { field, width } => {
+                            let value = field_value(*field, ctx);
+                            let value = format!("{:0>width$}", value, width = *width);
+                            name.push_str(&escape_value(&value));
+                        }
+                    }
+                }
+                sanitize_component(&name)
+            })
+            .collect()
+    }
+}
+
+fn field_value(field: Field, ctx: &PathContext<'_>) -> String {
+    match field {
+        Field::AlbumTitle => ctx.album.title.clone(),
+        Field::AlbumSlug => slugify(&ctx.album.title),
+        Field::AlbumId => ctx.album.id.clone(),
+        Field::Kindergarten => ctx.kindergarten.unwrap_or("Kidplan").to_string(),
+        Field::Department => ctx.album.department.clone().unwrap_or_default(),
+        Field::Author => ctx.album.author.clone().unwrap_or_default(),
+        Field::ImageId => ctx.image_id.to_string(),
+        Field::Index => ctx.index.to_string(),
+        Field::Ext => ctx.ext.to_string(),
+        Field::Year => ctx.date.year().to_string(),
+        Field::Month => format!("{:02}", ctx.date.month()),
+        Field::Day => format!("{:02}", ctx.date.day()),
+        Field::Date => ctx.date.format("%Y-%m-%d").to_string(),
+    }
+}
+
+/// Replace anything in a placeholder value that a file system would read as
+/// structure: separators, characters Windows reserves and control characters.
+fn escape_value(value: &str) -> String {
+    let escaped: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    escaped.chars().take(MAX_COMPONENT_CHARS).collect()
+}
+
+/// Make a rendered file or folder name safe on every platform.
+fn sanitize_component(name: &str) -> String {
+    let name = name.trim().trim_end_matches('.').trim_end();
+    let name = if name.is_empty() || name == "." || name == ".." {
+        "_".to_string()
+    } else {
+        name.to_string()
+    };
+    let stem = name.split('.').next().unwrap_or("").to_ascii_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || (stem.len() == 4
+            && (stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.as_bytes()[3].is_ascii_digit());
+    if reserved {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+/// `name` made safe to use as a single folder name.
+pub fn folder_name(name: &str) -> String {
+    sanitize_component(&escape_value(name))
+}
+
+/// `path` with ` (n)` inserted before its extension, used when two images
+/// would otherwise get the same name.
+pub fn numbered(path: &std::path::Path, n: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+/// Sample paths for the first images of up to three `albums`, for showing a
+/// template's effect before downloading.
+pub fn preview(template: &str, albums: &[Album], kindergarten: Option<&str>) -> Result<Vec<String>> {
+    let template: PathTemplate = template.parse()?;
+    let sample = Album {
+        id: "12345".to_string(),
+        title: "Sommerfest 2024".to_string(),
+        url: String::new(),
+        image_count: Some(2),
+        date: None,
+        description: None,
+        cover_url: None,
+        author: None,
+        department: Some("Blåbær".to_string()),
+        extra: Default::default(),
+    };
+    let albums = if albums.is_empty() {
+        std::slice::from_ref(&sample)
+    } else {
+        &albums[..albums.len().min(3)]
+    };
+    let today = chrono::Local::now().date_naive();
+    let mut paths = Vec::new();
+    for album in albums {
+        let date = album.date.map_or(today, |d| d.date());
+        for index in 1..=2 {
+            let image_id = format!("{}{:03}", album.id, index);
+            let ctx = PathContext {
+                album,
+                kindergarten,
+                image_id: &image_id,
+                index,
+                ext: "jpeg",
+                date,
+            };
+            let rendered = template.render(&ctx);
+            paths.push(
+                rendered
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            );
+        }
+    }
+    Ok(paths)
+}
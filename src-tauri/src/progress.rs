@@ -0,0 +1,113 @@
+use crate::DownloadProgress;
+use std::sync::Mutex;
+
+/// Name of the Tauri event carrying [`DownloadProgress`] payloads.
+pub const PROGRESS_EVENT: &str = "download-progress";
+
+/// Receives progress updates from the download engine.
+///
+/// The same engine runs from the GUI, the CLI and tests; each picks the
+/// reporter that fits where the updates should go.
+pub trait ProgressReporter: Send + Sync {
+    fn report(&self, progress: &DownloadProgress);
+}
+
+impl<F> ProgressReporter for F
+where
+    F: Fn(&DownloadProgress) + Send + Sync,
+{
+    fn report(&self, progress: &DownloadProgress) {
+        self(progress)
+    }
+}
+
+/// Forwards progress to the frontend as `download-progress` events.
+#[cfg(feature = "gui")]
+pub struct TauriReporter {
+    app: tauri::AppHandle,
+}
+
+#[cfg(feature = "gui")]
+impl TauriReporter {
+    pub fn new(app: tauri::AppHandle) -> Self {
+        Self { app }
+    }
+}
+
+#[cfg(feature = "gui")]
+impl ProgressReporter for TauriReporter {
+    fn report(&self, progress: &DownloadProgress) {
+        use tauri::Emitter;
+        let _ = self.app.emit(PROGRESS_EVENT, progress);
+    }
+}
+
+/// Sends progress over an unbounded channel, e.g. to a UI task or another thread.
+pub struct ChannelReporter {
+    tx: tokio::sync::mpsc::UnboundedSender<DownloadProgress>,
+}
+
+impl ChannelReporter {
+    pub fn new(tx: tokio::sync::mpsc::UnboundedSender<DownloadProgress>) -> Self {
+        Self { tx }
+    }
+}
+
+impl ProgressReporter for ChannelReporter {
+    fn report(&self, progress: &DownloadProgress) {
+        // A dropped receiver just means nobody is listening any more
+        let _ = self.tx.send(progress.clone());
+    }
+}
+
+/// Prints one line per update to stdout, for terminal use.
+pub struct LogReporter;
+
+impl ProgressReporter for LogReporter {
+    fn report(&self, p: &DownloadProgress) {
+        if p.filename.is_empty() {
+            println!(
+                "[{}/{}] {} - {}",
+                p.album_index, p.album_total, p.album_title, p.status
+            );
+        } else {
+            println!(
+                "[{}/{}] {} - [{}/{}] {}: {}",
+                p.album_index,
+                p.album_total,
+                p.album_title,
+                p.image_index,
+                p.image_total,
+                p.filename,
+                p.status
+            );
+        }
+    }
+}
+
+/// Keeps every update in memory so tests can inspect them afterwards.
+#[derive(Default)]
+pub struct CollectingReporter {
+    events: Mutex<Vec<DownloadProgress>>,
+}
+
+impl CollectingReporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot of the updates received so far.
+    pub fn events(&self) -> Vec<DownloadProgress> {
+        self.events.lock().unwrap().clone()
+    }
+
+    pub fn into_events(self) -> Vec<DownloadProgress> {
+        self.events.into_inner().unwrap()
+    }
+}
+
+impl ProgressReporter for CollectingReporter {
+    fn report(&self, progress: &DownloadProgress) {
+        self.events.lock().unwrap().push(progress.clone());
+    }
+}
@@ -0,0 +1,573 @@
+use crate::downloader;
+use crate::error::{Error, Result};
+use crate::kindergartens;
+use crate::manifest::write_atomic;
+use crate::progress::ProgressReporter;
+use crate::session::{self, SessionStore};
+use crate::sync::{self, SyncSummary};
+use crate::{
+    Album, AppState, Controls, DownloadProgress, DownloadResult, DownloadSettings, Kindergarten,
+    KindergartenAlbums, SettingsSecrets,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{oneshot, watch, Notify};
+
+const QUEUE_FILE: &str = "queue.json";
+const SECRETS_FILE: &str = "queue-secrets.bin";
+
+/// Name of the Tauri event carrying the job list whenever it changes.
+pub const JOBS_EVENT: &str = "download-jobs";
+
+pub type JobId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// What a job downloads.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobKind {
+    /// The job's albums.
+    #[default]
+    Download,
+    /// Chosen albums of several kindergartens, each into its own folder.
+    Kindergartens { selections: Vec<KindergartenAlbums> },
+    /// Whatever is new in the album list since the last sync.
+    Sync,
+}
+
+/// A download with the settings it was queued with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: JobId,
+    pub status: JobStatus,
+    /// Plain album downloads in queues saved before there were other kinds.
+    #[serde(default)]
+    pub kind: JobKind,
+    /// The kindergarten the albums belong to. The job logs in to it on a
+    /// session of its own, wherever the app's session has moved on to.
+    pub kindergarten: Option<Kindergarten>,
+    pub albums: Vec<Album>,
+    pub settings: DownloadSettings,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Counts of a completed or cancelled job.
+    #[serde(default)]
+    pub result: Option<DownloadResult>,
+    /// Why a failed job failed.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// What a finished job hands to whoever waits for it.
+enum Outcome {
+    Download(DownloadResult),
+    Sync(SyncSummary),
+}
+
+impl Outcome {
+    fn result(&self) -> &DownloadResult {
+        match self {
+            Outcome::Download(result) => result,
+            Outcome::Sync(summary) => &summary.result,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SavedQueue {
+    next_id: JobId,
+    /// In the order they run.
+    jobs: Vec<Job>,
+}
+
+/// Download jobs waiting to run, one at a time, in list order.
+///
+/// The queue is kept in `queue.json` in a directory (the app data dir in the
+/// GUI), so jobs that had not finished are picked up again on the next
+/// launch. A job that was running then starts over; the library's manifest
+/// makes it skip what was already downloaded. The storage passwords and
+/// photo library keys of those jobs are kept apart, encrypted with the key
+/// of the [`SessionStore`] in the same directory.
+pub struct JobQueue {
+    path: PathBuf,
+    secrets: SessionStore,
+    saved: Mutex<SavedQueue>,
+    /// Callers waiting for their job to run, by job.
+    waiters: Mutex<HashMap<JobId, oneshot::Sender<Result<Outcome>>>>,
+    /// The running job with its own pause and cancel.
+    running: Mutex<Option<(JobId, Arc<Controls>)>>,
+    /// Wakes [`JobQueue::run`] when there may be a job it can start.
+    wake: Notify,
+    changed: watch::Sender<()>,
+}
+
+impl JobQueue {
+    pub fn open(dir: &Path) -> Result<Self> {
+        let path = dir.join(QUEUE_FILE);
+        let mut saved: SavedQueue = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SavedQueue::default(),
+            Err(e) => return Err(Error::fs(&path, e)),
+        };
+        let secrets = SessionStore::new(dir);
+        let mut job_secrets: HashMap<JobId, SettingsSecrets> =
+            secrets.read_sealed(SECRETS_FILE).unwrap_or_default();
+        for job in &mut saved.jobs {
+            if job.status == JobStatus::Running {
+                job.status = JobStatus::Queued;
+            }
+            if let Some(secrets) = job_secrets.remove(&job.id) {
+                job.settings.restore_secrets(secrets);
+            }
+        }
+        Ok(Self {
+            path,
+            secrets,
+            saved: Mutex::new(saved),
+            waiters: Mutex::new(HashMap::new()),
+            running: Mutex::new(None),
+            wake: Notify::new(),
+            changed: watch::channel(()).0,
+        })
+    }
+
+    /// Queue `albums` of the kindergarten the session is on.
+    pub async fn enqueue(
+        &self,
+        state: &AppState,
+        albums: Vec<Album>,
+        settings: DownloadSettings,
+    ) -> Result<JobId> {
+        let kindergarten = state.kindergarten.lock().await.clone();
+        self.add(JobKind::Download, kindergarten, albums, settings, None)
+    }
+
+    /// Queue `albums` and wait for the job to run, as a plain download would.
+    pub async fn enqueue_and_wait(
+        &self,
+        state: &AppState,
+        albums: Vec<Album>,
+        settings: DownloadSettings,
+    ) -> Result<DownloadResult> {
+        let kindergarten = state.kindergarten.lock().await.clone();
+        let outcome = self
+            .add_and_wait(state, JobKind::Download, kindergarten, albums, settings)
+            .await?;
+        Ok(outcome.result().clone())
+    }
+
+    /// Queue albums of several kindergartens as one job and wait for it to
+    /// run. It switches kindergartens with the credentials of the session.
+    pub async fn download_kindergartens(
+        &self,
+        state: &AppState,
+        selections: Vec<KindergartenAlbums>,
+        settings: DownloadSettings,
+    ) -> Result<DownloadResult> {
+        let kind = JobKind::Kindergartens { selections };
+        let kindergarten = state.kindergarten.lock().await.clone();
+        let outcome = self
+            .add_and_wait(state, kind, kindergarten, Vec::new(), settings)
+            .await?;
+        Ok(outcome.result().clone())
+    }
+
+    /// Queue a sync of the kindergarten the session is on and wait for it
+    /// to run.
+    pub async fn sync(&self, state: &AppState, settings: DownloadSettings) -> Result<SyncSummary> {
+        let kindergarten = state.kindergarten.lock().await.clone();
+        self.sync_kindergarten(state, kindergarten, settings).await
+    }
+
+    /// Queue a sync of `kindergarten` (`None` for the one the job finds the
+    /// session on) and wait for it to run.
+    pub async fn sync_kindergarten(
+        &self,
+        state: &AppState,
+        kindergarten: Option<Kindergarten>,
+        settings: DownloadSettings,
+    ) -> Result<SyncSummary> {
+        match self
+            .add_and_wait(state, JobKind::Sync, kindergarten, Vec::new(), settings)
+            .await?
+        {
+            Outcome::Sync(summary) => Ok(summary),
+            Outcome::Download(_) => unreachable!("sync jobs end with a summary"),
+        }
+    }
+
+    async fn add_and_wait(
+        &self,
+        state: &AppState,
+        kind: JobKind,
+        kindergarten: Option<Kindergarten>,
+        albums: Vec<Album>,
+        settings: DownloadSettings,
+    ) -> Result<Outcome> {
+        // Queued jobs wait for a session, which would leave the caller hanging
+        if state.client.lock().await.is_none() {
+            return Err(Error::NotLoggedIn);
+        }
+        let (tx, rx) = oneshot::channel();
+        self.add(kind, kindergarten, albums, settings, Some(tx))?;
+        rx.await.unwrap_or(Err(Error::Cancelled))
+    }
+
+    fn add(
+        &self,
+        kind: JobKind,
+        kindergarten: Option<Kindergarten>,
+        albums: Vec<Album>,
+        settings: DownloadSettings,
+        waiter: Option<oneshot::Sender<Result<Outcome>>>,
+    ) -> Result<JobId> {
+        let id = {
+            let mut saved = self.saved.lock().unwrap();
+            let id = saved.next_id.max(1);
+            saved.next_id = id + 1;
+            if let Some(waiter) = waiter {
+                self.waiters.lock().unwrap().insert(id, waiter);
+            }
+            eprintln!("[DEBUG] Queued job {} with {} albums", id, albums.len());
+            saved.jobs.push(Job {
+                id,
+                status: JobStatus::Queued,
+                kind,
+                kindergarten,
+                albums,
+                settings,
+                created_at: chrono::Utc::now(),
+                result: None,
+                error: None,
+            });
+            id
+        };
+        self.save_and_notify()?;
+        self.wake();
+        Ok(id)
+    }
+
+    /// Every job, finished ones included, in the order they run.
+    pub fn list(&self) -> Vec<Job> {
+        self.saved.lock().unwrap().jobs.clone()
+    }
+
+    pub fn get(&self, id: JobId) -> Option<Job> {
+        self.saved
+            .lock()
+            .unwrap()
+            .jobs
+            .iter()
+            .find(|j| j.id == id)
+            .cloned()
+    }
+
+    /// Drop a queued job, or stop it if it is running. Finished jobs are
+    /// left as they are.
+    pub fn cancel(&self, id: JobId) -> Result<()> {
+        let status = {
+            let mut saved = self.saved.lock().unwrap();
+            let job = saved
+                .jobs
+                .iter_mut()
+                .find(|j| j.id == id)
+                .ok_or(Error::JobNotFound(id))?;
+            let status = job.status;
+            if status == JobStatus::Queued {
+                job.status = JobStatus::Cancelled;
+            }
+            status
+        };
+        match status {
+            // It ends as cancelled through the download's own result
+            JobStatus::Running => {
+                if let Some((_, controls)) = self
+                    .running
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .filter(|(running, _)| *running == id)
+                {
+                    controls.cancel();
+                }
+            }
+            JobStatus::Queued => {
+                eprintln!("[DEBUG] Cancelled queued job {}", id);
+                if let Some(waiter) = self.waiters.lock().unwrap().remove(&id) {
+                    let _ = waiter.send(Err(Error::Cancelled));
+                }
+                self.save_and_notify()?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Hold the running job after the images in flight, if a job is running.
+    pub fn pause_running(&self) {
+        if let Some((_, controls)) = self.running.lock().unwrap().as_ref() {
+            controls.pause();
+        }
+    }
+
+    pub fn resume_running(&self) {
+        if let Some((_, controls)) = self.running.lock().unwrap().as_ref() {
+            controls.resume();
+        }
+    }
+
+    /// Stop the running job, if there is one.
+    pub fn cancel_running(&self) {
+        if let Some((_, controls)) = self.running.lock().unwrap().as_ref() {
+            controls.cancel();
+        }
+    }
+
+    /// Move a job to `position` in the list, e.g. 0 to run it next.
+    pub fn move_job(&self, id: JobId, position: usize) -> Result<()> {
+        {
+            let mut saved = self.saved.lock().unwrap();
+            let from = saved
+                .jobs
+                .iter()
+                .position(|j| j.id == id)
+                .ok_or(Error::JobNotFound(id))?;
+            let job = saved.jobs.remove(from);
+            let to = position.min(saved.jobs.len());
+            saved.jobs.insert(to, job);
+        }
+        self.save_and_notify()
+    }
+
+    /// Forget completed, failed and cancelled jobs.
+    pub fn clear_finished(&self) -> Result<()> {
+        self.saved
+            .lock()
+            .unwrap()
+            .jobs
+            .retain(|j| !j.status.is_finished());
+        self.save_and_notify()
+    }
+
+    /// Changes whenever a job is added, moves or changes status.
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.changed.subscribe()
+    }
+
+    /// Let [`JobQueue::run`] look for a job again, e.g. after logging in.
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    /// Run queued jobs one after another, waiting for new ones (or for a
+    /// login) when there is nothing to run. Never returns.
+    pub async fn run(&self, state: &AppState, reporter: &dyn ProgressReporter) {
+        loop {
+            if self.run_next(state, reporter).await.is_none() {
+                self.wake.notified().await;
+            }
+        }
+    }
+
+    /// Run the first queued job, if there is one and a session to run it
+    /// with. Its progress updates carry its id.
+    pub async fn run_next(
+        &self,
+        state: &AppState,
+        reporter: &dyn ProgressReporter,
+    ) -> Option<JobId> {
+        if state.client.lock().await.is_none() {
+            return None;
+        }
+        let controls = Arc::new(Controls::default());
+        let job = {
+            let mut saved = self.saved.lock().unwrap();
+            let job = saved
+                .jobs
+                .iter_mut()
+                .find(|j| j.status == JobStatus::Queued)?;
+            job.status = JobStatus::Running;
+            // Set while the job is marked running, so a cancel always finds it
+            *self.running.lock().unwrap() = Some((job.id, controls.clone()));
+            job.clone()
+        };
+        if let Err(e) = self.save_and_notify() {
+            eprintln!("[DEBUG] Could not save the job queue: {}", e);
+        }
+        eprintln!("[DEBUG] Running job {}", job.id);
+
+        let reporter = JobReporter {
+            job_id: job.id,
+            inner: reporter,
+        };
+        let result = run_job(state, &job, controls, &reporter).await;
+        *self.running.lock().unwrap() = None;
+        eprintln!(
+            "[DEBUG] Job {} finished: {:?}",
+            job.id,
+            result.as_ref().map(Outcome::result)
+        );
+        self.finish(job.id, result);
+        Some(job.id)
+    }
+
+    fn finish(&self, id: JobId, result: Result<Outcome>) {
+        if let Some(job) = self
+            .saved
+            .lock()
+            .unwrap()
+            .jobs
+            .iter_mut()
+            .find(|j| j.id == id)
+        {
+            match &result {
+                Ok(outcome) => {
+                    let r = outcome.result();
+                    job.status = if r.cancelled {
+                        JobStatus::Cancelled
+                    } else {
+                        JobStatus::Completed
+                    };
+                    job.result = Some(r.clone());
+                }
+                Err(e) => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(e.to_string());
+                }
+            }
+        }
+        if let Err(e) = self.save_and_notify() {
+            eprintln!("[DEBUG] Could not save the job queue: {}", e);
+        }
+        if let Some(waiter) = self.waiters.lock().unwrap().remove(&id) {
+            let _ = waiter.send(result);
+        }
+    }
+
+    fn save_and_notify(&self) -> Result<()> {
+        let (queue, job_secrets) = {
+            let saved = self.saved.lock().unwrap();
+            let mut job_secrets = HashMap::new();
+            let mut jobs = Vec::with_capacity(saved.jobs.len());
+            for job in &saved.jobs {
+                let mut job = job.clone();
+                let (settings, secrets) = job.settings.split_secrets();
+                job.settings = settings;
+                // Finished jobs don't run again, so theirs are let go
+                if let Some(secrets) = secrets.filter(|_| !job.status.is_finished()) {
+                    job_secrets.insert(job.id, secrets);
+                }
+                jobs.push(job);
+            }
+            let queue = SavedQueue {
+                next_id: saved.next_id,
+                jobs,
+            };
+            (queue, job_secrets)
+        };
+        self.changed.send_replace(());
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| Error::fs(dir, e))?;
+        }
+        write_atomic(&self.path, &serde_json::to_vec_pretty(&queue)?)?;
+
+        let job_secrets = Some(&job_secrets).filter(|secrets| !secrets.is_empty());
+        self.secrets.write_sealed(SECRETS_FILE, job_secrets)
+    }
+}
+
+async fn run_job(
+    state: &AppState,
+    job: &Job,
+    controls: Arc<Controls>,
+    reporter: &dyn ProgressReporter,
+) -> Result<Outcome> {
+    // Whatever kindergarten the app has moved on to, the job downloads from
+    // its own, and its switches stay out of the app's session
+    let kid_id = job.kindergarten.as_ref().map(|k| k.id);
+    let mut session = session::fork(state, kid_id).await?;
+    session.controls = controls;
+    let state = &session;
+    let settings = job.settings.clone();
+    match &job.kind {
+        JobKind::Download => {
+            downloader::download_albums(state, job.albums.clone(), settings, reporter)
+                .await
+                .map(Outcome::Download)
+        }
+        JobKind::Kindergartens { selections } => {
+            let credentials = state
+                .credentials
+                .lock()
+                .await
+                .clone()
+                .ok_or(Error::NotLoggedIn)?;
+            kindergartens::download_kindergartens(
+                state,
+                &credentials,
+                selections.clone(),
+                settings,
+                reporter,
+            )
+            .await
+            .map(Outcome::Download)
+        }
+        JobKind::Sync => {
+            let client = state
+                .client
+                .lock()
+                .await
+                .clone()
+                .ok_or(Error::NotLoggedIn)?;
+            let cancel = state.controls.cancellation();
+            let listing = downloader::fetch_albums(&client, &state.endpoints);
+            let Some(albums) = cancel.run_until_cancelled(listing).await else {
+                // Nothing was synced yet, so there is nothing to count
+                return Ok(Outcome::Sync(SyncSummary {
+                    new_albums: 0,
+                    changed_albums: 0,
+                    unchanged_albums: 0,
+                    new_images: 0,
+                    result: DownloadResult {
+                        cancelled: true,
+                        ..DownloadResult::default()
+                    },
+                }));
+            };
+            sync::sync_albums(state, &albums?, settings, reporter)
+                .await
+                .map(Outcome::Sync)
+        }
+    }
+}
+
+/// Marks every update with the job it belongs to.
+struct JobReporter<'a> {
+    job_id: JobId,
+    inner: &'a dyn ProgressReporter,
+}
+
+impl ProgressReporter for JobReporter<'_> {
+    fn report(&self, progress: &DownloadProgress) {
+        self.inner.report(&DownloadProgress {
+            job_id: Some(self.job_id),
+            ..progress.clone()
+        });
+    }
+}
@@ -0,0 +1,369 @@
+use crate::downloader::resolve_out_dir;
+use crate::error::{Error, ErrorCode, Result};
+use crate::kindergartens;
+use crate::manifest::write_atomic;
+use crate::metadata::local_at;
+use crate::queue::JobQueue;
+use crate::session::{self, SessionStore};
+use crate::{AppState, DownloadResult, DownloadSettings, Kindergarten};
+use chrono::{DateTime, Duration, Local, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+const CONFIG_FILE: &str = "schedule.json";
+const RUNS_FILE: &str = "schedule-runs.json";
+const SECRETS_FILE: &str = "schedule-secrets.bin";
+/// Runs kept in the history; older ones are dropped.
+const MAX_RUNS: usize = 100;
+
+/// Name of the Tauri event carrying a [`ScheduledRun`] after each run.
+pub const RUN_EVENT: &str = "scheduled-sync";
+
+/// When scheduled syncs run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Schedule {
+    /// Every day at a local time, e.g. 02:00.
+    Daily { at: NaiveTime },
+    /// Repeatedly, this many minutes after the previous run started.
+    Every { minutes: u64 },
+}
+
+impl Schedule {
+    /// When the next run is due, given when the last one started.
+    ///
+    /// A daily run missed while the app was closed happens right away. A new
+    /// schedule waits for its first time slot instead of running at once.
+    pub fn next_run(&self, last: Option<DateTime<Local>>, now: DateTime<Local>) -> DateTime<Local> {
+        match self {
+            Schedule::Daily { at } => {
+                let today = local_at(now.date_naive().and_time(*at));
+                let due = if today <= now {
+                    today
+                } else {
+                    local_at((now.date_naive() - Duration::days(1)).and_time(*at))
+                };
+                match last {
+                    Some(last) if last < due => now,
+                    _ => local_at((due.date_naive() + Duration::days(1)).and_time(*at)),
+                }
+            }
+            Schedule::Every { minutes } => {
+                let every = Duration::minutes(*minutes as i64);
+                match last {
+                    Some(last) => (last + every).max(now),
+                    None => now + every,
+                }
+            }
+        }
+    }
+}
+
+/// `HH:MM` for a daily run, or an interval such as `90m` or `6h`.
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(at) = NaiveTime::parse_from_str(s, "%H:%M") {
+            return Ok(Schedule::Daily { at });
+        }
+        let (number, unit) = s.split_at(s.len().saturating_sub(1));
+        let minutes = match (number.parse::<u64>(), unit) {
+            (Ok(n), "m") => n,
+            (Ok(n), "h") => n * 60,
+            _ => {
+                return Err(format!(
+                    "expected HH:MM, or minutes/hours like 90m or 6h, not \"{}\"",
+                    s
+                ))
+            }
+        };
+        if minutes == 0 {
+            return Err("the interval must be at least a minute".to_string());
+        }
+        Ok(Schedule::Every { minutes })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Daily { at } => write!(f, "daily at {}", at.format("%H:%M")),
+            Schedule::Every { minutes } if minutes % 60 == 0 => {
+                write!(f, "every {}h", minutes / 60)
+            }
+            Schedule::Every { minutes } => write!(f, "every {}m", minutes),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    pub enabled: bool,
+    pub schedule: Schedule,
+    /// Kindergartens to sync, each into its own folder when there are
+    /// several. Empty means the one the session is logged in to.
+    #[serde(default)]
+    pub kid_ids: Vec<i64>,
+    pub settings: DownloadSettings,
+}
+
+/// Why a scheduled run failed, e.g. `session_expired` when Kidplan no
+/// longer accepts the saved session and no password is at hand.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl From<&Error> for RunError {
+    fn from(e: &Error) -> Self {
+        Self {
+            code: e.code(),
+            message: e.to_string(),
+        }
+    }
+}
+
+/// One scheduled sync, as recorded in the run history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledRun {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub new_albums: usize,
+    pub new_images: usize,
+    /// Counts of everything the run did, also when it failed part way.
+    pub result: DownloadResult,
+    #[serde(default)]
+    pub error: Option<RunError>,
+}
+
+/// Queues an incremental sync on a [`Schedule`] as a job of a [`JobQueue`],
+/// with the session in [`AppState`]: a saved session, or credentials from a
+/// login.
+///
+/// The configuration and the run history are kept in a directory (the app
+/// data dir in the GUI), so the schedule survives restarts. The storage
+/// passwords and photo library keys of the settings are kept apart,
+/// encrypted with the key of the [`SessionStore`] in the same directory.
+pub struct Scheduler {
+    dir: PathBuf,
+    secrets: SessionStore,
+    config: watch::Sender<Option<ScheduleConfig>>,
+    runs: Mutex<Vec<ScheduledRun>>,
+}
+
+impl Scheduler {
+    pub fn open(dir: &Path) -> Result<Self> {
+        let secrets = SessionStore::new(dir);
+        let mut config: Option<ScheduleConfig> = read_json(&dir.join(CONFIG_FILE))?.flatten();
+        if let Some(config) = &mut config {
+            if let Some(saved) = secrets.read_sealed(SECRETS_FILE) {
+                config.settings.restore_secrets(saved);
+            }
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            secrets,
+            config: watch::channel(config).0,
+            runs: Mutex::new(read_json(&dir.join(RUNS_FILE))?.unwrap_or_default()),
+        })
+    }
+
+    pub fn config(&self) -> Option<ScheduleConfig> {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the schedule (`None` removes it). A running
+    /// [`Scheduler::run`] picks up the change at once.
+    pub fn configure(&self, config: Option<ScheduleConfig>) -> Result<()> {
+        let (public, secrets) = match &config {
+            Some(config) => {
+                let (settings, secrets) = config.settings.split_secrets();
+                let public = ScheduleConfig {
+                    settings,
+                    ..config.clone()
+                };
+                (Some(public), secrets)
+            }
+            None => (None, None),
+        };
+        write_json(&self.dir, CONFIG_FILE, &public)?;
+        self.secrets.write_sealed(SECRETS_FILE, secrets.as_ref())?;
+        self.config.send_replace(config);
+        Ok(())
+    }
+
+    /// Past runs, oldest first.
+    pub fn runs(&self) -> Vec<ScheduledRun> {
+        self.runs.lock().unwrap().clone()
+    }
+
+    /// When the next run is due, if the schedule is enabled.
+    pub fn next_run(&self) -> Option<DateTime<Local>> {
+        let config = self.config().filter(|c| c.enabled)?;
+        let last = self
+            .runs
+            .lock()
+            .unwrap()
+            .last()
+            .map(|r| r.started_at.with_timezone(&Local));
+        Some(config.schedule.next_run(last, Local::now()))
+    }
+
+    /// Sync whenever the schedule says so, for as long as the app runs.
+    /// `on_run` sees each recorded run, failed ones included.
+    pub async fn run(
+        &self,
+        state: &AppState,
+        queue: &JobQueue,
+        sessions: Option<&SessionStore>,
+        on_run: &(dyn Fn(&ScheduledRun) + Send + Sync),
+    ) {
+        let mut config = self.config.subscribe();
+        loop {
+            config.mark_unchanged();
+            let Some(next) = self.next_run() else {
+                let _ = config.changed().await;
+                continue;
+            };
+            eprintln!("[DEBUG] Next scheduled sync at {}", next);
+            let wait = (next - Local::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {
+                    if let Some(run) = self.run_now(state, queue, sessions).await {
+                        on_run(&run);
+                    }
+                }
+                _ = config.changed() => {}
+            }
+        }
+    }
+
+    /// Sync the configured kindergartens now and record the run. Without a
+    /// schedule there is nothing to sync.
+    pub async fn run_now(
+        &self,
+        state: &AppState,
+        queue: &JobQueue,
+        sessions: Option<&SessionStore>,
+    ) -> Option<ScheduledRun> {
+        let config = self.config()?;
+        let started_at = Utc::now();
+        let mut run = ScheduledRun {
+            started_at,
+            finished_at: started_at,
+            new_albums: 0,
+            new_images: 0,
+            result: DownloadResult::default(),
+            error: None,
+        };
+        eprintln!("[DEBUG] Scheduled sync started");
+        if let Err(e) = sync_scheduled(state, queue, sessions, &config, &mut run).await {
+            eprintln!("[DEBUG] Scheduled sync failed: {}", e);
+            run.error = Some(RunError::from(&e));
+        }
+        run.finished_at = Utc::now();
+
+        let runs = {
+            let mut runs = self.runs.lock().unwrap();
+            runs.push(run.clone());
+            let excess = runs.len().saturating_sub(MAX_RUNS);
+            runs.drain(..excess);
+            runs.clone()
+        };
+        if let Err(e) = write_json(&self.dir, RUNS_FILE, &runs) {
+            eprintln!("[DEBUG] Could not save the sync history: {}", e);
+        }
+        Some(run)
+    }
+}
+
+/// Queue a sync of each configured kindergarten and add up the jobs into
+/// `run` as they finish.
+async fn sync_scheduled(
+    state: &AppState,
+    queue: &JobQueue,
+    sessions: Option<&SessionStore>,
+    config: &ScheduleConfig,
+    run: &mut ScheduledRun,
+) -> Result<()> {
+    let logged_in =
+        state.client.lock().await.is_some() && state.kindergarten.lock().await.is_some();
+    if !logged_in {
+        let restored = match sessions {
+            Some(store) => session::restore(state, store).await?,
+            None => None,
+        };
+        if restored.is_none() {
+            return Err(Error::NotLoggedIn);
+        }
+    }
+
+    let current = state
+        .kindergarten
+        .lock()
+        .await
+        .clone()
+        .ok_or(Error::NotLoggedIn)?;
+    let kid_ids = match config.kid_ids.as_slice() {
+        [] => vec![current.id],
+        ids => ids.to_vec(),
+    };
+    let known = state.kindergartens.lock().await.clone();
+    let kids: Vec<Kindergarten> = kid_ids
+        .iter()
+        .map(|&id| {
+            known
+                .iter()
+                .find(|k| k.id == id)
+                .cloned()
+                .unwrap_or(Kindergarten {
+                    id,
+                    name: id.to_string(),
+                })
+        })
+        .collect();
+    let root = resolve_out_dir(&config.settings.out_dir);
+    let folders = kindergartens::folder_names(kids.iter());
+
+    // Each job logs in to its kindergarten on a session of its own, so the
+    // app's session stays where it is
+    for (kid, folder) in kids.iter().zip(folders) {
+        let settings = if kids.len() > 1 {
+            DownloadSettings {
+                out_dir: root.join(&folder).to_string_lossy().to_string(),
+                storage: config.settings.storage.within(&folder),
+                ..config.settings.clone()
+            }
+        } else {
+            config.settings.clone()
+        };
+        let summary = queue
+            .sync_kindergarten(state, Some(kid.clone()), settings)
+            .await?;
+        eprintln!("[DEBUG] Scheduled sync of {}: {}", kid.name, summary);
+        run.new_albums += summary.new_albums;
+        run.new_images += summary.new_images;
+        run.result.merge(&summary.result);
+    }
+    Ok(())
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::fs(path, e)),
+    }
+}
+
+fn write_json<T: Serialize>(dir: &Path, file: &str, value: &T) -> Result<()> {
+    std::fs::create_dir_all(dir).map_err(|e| Error::fs(dir, e))?;
+    write_atomic(&dir.join(file), &serde_json::to_vec_pretty(value)?)
+}
@@ -0,0 +1,282 @@
+use crate::downloader::{self, build_client};
+use crate::error::{Error, Result};
+use crate::manifest::write_atomic;
+use crate::{AppState, Kindergarten};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use reqwest::cookie::{CookieStore, Jar};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const SESSION_FILE: &str = "session.bin";
+const KEY_FILE: &str = "session.key";
+const NONCE_LEN: usize = 12;
+
+/// What is remembered about a logged-in session between launches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedSession {
+    /// `name=value` pairs Kidplan set, per URL they were sent to.
+    cookies: Vec<(String, String)>,
+    kindergarten: Option<Kindergarten>,
+    kindergartens: Vec<Kindergarten>,
+}
+
+/// The kindergarten a restored session is logged in to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoredSession {
+    pub kindergarten: Option<Kindergarten>,
+    pub kindergartens: Vec<Kindergarten>,
+}
+
+/// Session cookies kept in a directory (the app data dir in the GUI),
+/// encrypted with AES-256-GCM under a random per-installation key.
+///
+/// The key lives in its own file, readable only by the user, so the session
+/// file alone (e.g. from a backup or a synced folder) is useless.
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Remember the cookies and kindergarten of the current session.
+    pub async fn save(&self, state: &AppState) -> Result<()> {
+        let session = SavedSession {
+            cookies: session_cookies(state),
+            kindergarten: state.kindergarten.lock().await.clone(),
+            kindergartens: state.kindergartens.lock().await.clone(),
+        };
+        if session.cookies.is_empty() {
+            return self.clear();
+        }
+        let bytes = self.seal(&serde_json::to_vec(&session)?)?;
+        write_atomic(&self.dir.join(SESSION_FILE), &bytes)
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.dir.join(SESSION_FILE).exists()
+    }
+
+    /// Forget the saved session. The key is kept for the next one.
+    pub fn clear(&self) -> Result<()> {
+        let path = self.dir.join(SESSION_FILE);
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::fs(&path, e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The saved session, or `None` if there is none or it can't be
+    /// decrypted (e.g. the key file was removed).
+    fn load(&self) -> Option<SavedSession> {
+        let bytes = std::fs::read(self.dir.join(SESSION_FILE)).ok()?;
+        serde_json::from_slice(&self.unseal(&bytes)?).ok()
+    }
+
+    /// Encrypt `plain` under the key.
+    fn seal(&self, plain: &[u8]) -> Result<Vec<u8>> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| Error::fs(&self.dir, e))?;
+        let cipher = Aes256Gcm::new(&self.key()?);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let sealed = cipher
+            .encrypt(&nonce, plain)
+            .map_err(|_| Error::Parse("could not encrypt data for the app data dir".to_string()))?;
+        let mut bytes = nonce.to_vec();
+        bytes.extend_from_slice(&sealed);
+        Ok(bytes)
+    }
+
+    /// What [`SessionStore::seal`] encrypted, or `None` if it can't be
+    /// decrypted.
+    fn unseal(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+        let key = std::fs::read(self.dir.join(KEY_FILE)).ok()?;
+        if bytes.len() < NONCE_LEN || key.len() != 32 {
+            return None;
+        }
+        let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .ok()
+    }
+
+    /// `value` sealed into `file` next to the session, or `file` removed
+    /// when there is nothing to keep.
+    pub(crate) fn write_sealed<T: Serialize>(&self, file: &str, value: Option<&T>) -> Result<()> {
+        let path = self.dir.join(file);
+        let Some(value) = value else {
+            return match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::fs(&path, e)),
+                _ => Ok(()),
+            };
+        };
+        let bytes = self.seal(&serde_json::to_vec(value)?)?;
+        write_atomic(&path, &bytes)
+    }
+
+    /// What [`SessionStore::write_sealed`] kept in `file`, or `None` if there
+    /// is nothing or it can't be decrypted.
+    pub(crate) fn read_sealed<T: serde::de::DeserializeOwned>(&self, file: &str) -> Option<T> {
+        let bytes = std::fs::read(self.dir.join(file)).ok()?;
+        serde_json::from_slice(&self.unseal(&bytes)?).ok()
+    }
+
+    fn key(&self) -> Result<Key<Aes256Gcm>> {
+        let path = self.dir.join(KEY_FILE);
+        match std::fs::read(&path) {
+            Ok(bytes) if bytes.len() == 32 => return Ok(*Key::<Aes256Gcm>::from_slice(&bytes)),
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(Error::fs(&path, e)),
+            _ => {}
+        }
+        let key = Aes256Gcm::generate_key(OsRng);
+        write_private(&path, &key)?;
+        Ok(key)
+    }
+}
+
+/// Restore the saved session into `state` if Kidplan still accepts it.
+///
+/// The cookies are tried on a throwaway client first, so an expired session
+/// never touches `state`; it is deleted instead.
+pub async fn restore(state: &AppState, store: &SessionStore) -> Result<Option<RestoredSession>> {
+    let Some(saved) = store.load() else {
+        return Ok(None);
+    };
+    let probe_jar = std::sync::Arc::new(Jar::default());
+    add_cookies(&probe_jar, &saved.cookies);
+    if !is_valid(&build_client(probe_jar), state).await? {
+        eprintln!("[DEBUG] Saved session has expired, removing it");
+        store.clear()?;
+        return Ok(None);
+    }
+
+    add_cookies(&state.cookie_jar, &saved.cookies);
+    *state.client.lock().await = Some(build_client(state.cookie_jar.clone()));
+    *state.kindergartens.lock().await = saved.kindergartens.clone();
+    *state.kindergarten.lock().await = saved.kindergarten.clone();
+    Ok(Some(RestoredSession {
+        kindergarten: saved.kindergarten,
+        kindergartens: saved.kindergartens,
+    }))
+}
+
+/// End the session: expire its cookies, drop the client and forget the
+/// saved copy.
+pub async fn logout(state: &AppState, store: &SessionStore) -> Result<()> {
+    for url in session_urls(state) {
+        let Some(header) = state.cookie_jar.cookies(&url) else {
+            continue;
+        };
+        let names: Vec<String> = header
+            .to_str()
+            .unwrap_or("")
+            .split(';')
+            .filter_map(|pair| pair.split_once('=').map(|(name, _)| name.trim().to_string()))
+            .collect();
+        for name in names {
+            state
+                .cookie_jar
+                .add_cookie_str(&format!("{}=; Max-Age=0; Path=/", name), &url);
+        }
+    }
+    *state.client.lock().await = None;
+    *state.kindergarten.lock().await = None;
+    *state.credentials.lock().await = None;
+    state.kindergartens.lock().await.clear();
+    store.clear()
+}
+
+/// A session of its own for a download job, so the job neither follows the
+/// app switching kindergartens nor switches the app's session itself.
+///
+/// It logs in to `kid_id` (the kindergarten `state` is on when `None`) with
+/// the credentials of `state`. A session restored without them is copied
+/// instead, which only works for the kindergarten it is on.
+pub async fn fork(state: &AppState, kid_id: Option<i64>) -> Result<AppState> {
+    if state.client.lock().await.is_none() {
+        return Err(Error::NotLoggedIn);
+    }
+    let forked = AppState::with_endpoints(state.endpoints.clone());
+    *forked.kindergartens.lock().await = state.kindergartens.lock().await.clone();
+    let current = state.kindergarten.lock().await.clone();
+    let kid_id = kid_id.or(current.as_ref().map(|k| k.id));
+    let client = build_client(forked.cookie_jar.clone());
+    match (state.credentials.lock().await.clone(), kid_id) {
+        (Some(credentials), Some(kid_id)) => {
+            downloader::login(&client, &forked.endpoints, &credentials, kid_id).await?;
+            forked.select_kindergarten(kid_id).await;
+            *forked.credentials.lock().await = Some(credentials);
+        }
+        _ if kid_id == current.as_ref().map(|k| k.id) => {
+            add_cookies(&forked.cookie_jar, &session_cookies(state));
+            *forked.kindergarten.lock().await = current;
+        }
+        // Another kindergarten needs a login, and so the password
+        _ => return Err(Error::NotLoggedIn),
+    }
+    *forked.client.lock().await = Some(client);
+    Ok(forked)
+}
+
+/// Whether a client's cookies still open Kidplan's album list. Kidplan
+/// answers an expired session with its login page rather than an error.
+async fn is_valid(client: &reqwest::Client, state: &AppState) -> Result<bool> {
+    let url = format!("{}?take=1&skip=0", state.endpoints.albums_json_url());
+    let resp = match client.get(&url).send().await {
+        Ok(resp) => resp,
+        Err(e) if e.is_connect() || e.is_timeout() => return Err(e.into()),
+        Err(_) => return Ok(false),
+    };
+    if !resp.status().is_success() {
+        return Ok(false);
+    }
+    Ok(resp.json::<Vec<serde_json::Value>>().await.is_ok())
+}
+
+/// URLs whose cookies make up the session.
+fn session_urls(state: &AppState) -> Vec<url::Url> {
+    [state.endpoints.base_url.clone(), state.endpoints.album_base_url()]
+        .iter()
+        .filter_map(|u| url::Url::parse(u).ok())
+        .collect()
+}
+
+/// The session's cookies as `name=value` pairs, per URL they are sent to.
+fn session_cookies(state: &AppState) -> Vec<(String, String)> {
+    session_urls(state)
+        .into_iter()
+        .filter_map(|url| {
+            let header = state.cookie_jar.cookies(&url)?;
+            Some((url.to_string(), header.to_str().ok()?.to_string()))
+        })
+        .collect()
+}
+
+fn add_cookies(jar: &Jar, cookies: &[(String, String)]) {
+    for (url, header) in cookies {
+        let Ok(url) = url::Url::parse(url) else {
+            continue;
+        };
+        for pair in header.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+            jar.add_cookie_str(&format!("{}; Path=/", pair), &url);
+        }
+    }
+}
+
+/// Write `bytes` to a new file only the current user can read.
+fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(bytes))
+        .map_err(|e| Error::fs(path, e))
+}
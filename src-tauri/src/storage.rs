@@ -0,0 +1,683 @@
+use crate::dedupe::{link_duplicate, DuplicateMode};
+use crate::error::{Error, Result};
+use crate::manifest::{write_atomic, STATE_DIR};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Every file in a storage, by path relative to its root (with `/`
+/// separators, as in the manifest), with its size.
+pub type Listing = HashMap<String, u64>;
+
+/// Where downloaded images are kept.
+///
+/// Paths are relative to the storage's root. The library's bookkeeping
+/// (manifest, hash index, sync state) always stays in the local `out_dir`,
+/// so only the images, sidecars and `album.json` files go through here.
+pub trait Storage: Send + Sync {
+    /// Where the files go, for logs.
+    fn describe(&self) -> String;
+
+    /// Store `bytes` at `path` so that readers see either the old file or
+    /// the complete new one, never a partial upload.
+    fn put<'a>(&'a self, path: &'a str, bytes: Vec<u8>) -> BoxFuture<'a, Result<()>>;
+
+    /// Every file stored, e.g. to tell which images are already there
+    /// without asking for each one.
+    fn list(&self) -> BoxFuture<'_, Result<Listing>>;
+
+    /// Make `dest` a link to `original`. Storages without links fail, and
+    /// the caller stores a copy instead.
+    fn link<'a>(
+        &'a self,
+        _original: &'a str,
+        dest: &'a str,
+        mode: DuplicateMode,
+    ) -> BoxFuture<'a, Result<()>> {
+        async move {
+            Err(Error::fs(
+                dest,
+                format!("{} does not support {} duplicates", self.describe(), mode),
+            ))
+        }
+        .boxed()
+    }
+}
+
+/// Which [`Storage`] a download writes to.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StorageConfig {
+    /// Files under `out_dir`.
+    #[default]
+    Local,
+    /// A WebDAV folder, e.g. on Nextcloud:
+    /// `https://cloud.example.com/remote.php/dav/files/<user>/Kidplan`.
+    #[serde(rename = "webdav")]
+    WebDav {
+        url: String,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+    },
+    /// A bucket on S3 or a compatible server such as MinIO, addressed
+    /// path-style (`<endpoint>/<bucket>/<key>`).
+    S3 {
+        endpoint: String,
+        bucket: String,
+        #[serde(default = "default_region")]
+        region: String,
+        access_key: String,
+        secret_key: String,
+        /// Folder inside the bucket to keep the library in.
+        #[serde(default)]
+        prefix: String,
+    },
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+// Settings are logged, so the secrets are left out
+impl fmt::Debug for StorageConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageConfig::Local => f.write_str("Local"),
+            StorageConfig::WebDav { url, username, .. } => f
+                .debug_struct("WebDav")
+                .field("url", url)
+                .field("username", username)
+                .finish_non_exhaustive(),
+            StorageConfig::S3 {
+                endpoint,
+                bucket,
+                region,
+                prefix,
+                ..
+            } => f
+                .debug_struct("S3")
+                .field("endpoint", endpoint)
+                .field("bucket", bucket)
+                .field("region", region)
+                .field("prefix", prefix)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl StorageConfig {
+    pub fn is_local(&self) -> bool {
+        matches!(self, StorageConfig::Local)
+    }
+
+    /// The same storage, but with its root in `folder`, e.g. for one of
+    /// several kindergartens. Local files follow `out_dir` instead.
+    pub fn within(&self, folder: &str) -> StorageConfig {
+        let mut config = self.clone();
+        match &mut config {
+            StorageConfig::Local => {}
+            StorageConfig::WebDav { url, .. } => {
+                *url = format!("{}/{}", url.trim_end_matches('/'), folder);
+            }
+            StorageConfig::S3 { prefix, .. } => {
+                *prefix = format!("{}/{}", prefix.trim_matches('/'), folder)
+                    .trim_start_matches('/')
+                    .to_string();
+            }
+        }
+        config
+    }
+
+    /// The same storage without its password or secret key, to save where
+    /// they could be read.
+    pub fn without_secrets(&self) -> StorageConfig {
+        let mut config = self.clone();
+        match &mut config {
+            StorageConfig::Local => {}
+            StorageConfig::WebDav { password, .. } => *password = None,
+            StorageConfig::S3 { secret_key, .. } => secret_key.clear(),
+        }
+        config
+    }
+
+    /// The configured storage; local files go under `out_dir`.
+    pub fn open(&self, out_dir: &Path) -> Result<Box<dyn Storage>> {
+        Ok(match self {
+            StorageConfig::Local => Box::new(LocalStorage::new(out_dir)),
+            StorageConfig::WebDav {
+                url,
+                username,
+                password,
+            } => Box::new(WebDavStorage::new(url, username.clone(), password.clone())?),
+            StorageConfig::S3 {
+                endpoint,
+                bucket,
+                region,
+                access_key,
+                secret_key,
+                prefix,
+            } => Box::new(S3Storage::new(
+                endpoint, bucket, region, access_key, secret_key, prefix,
+            )?),
+        })
+    }
+}
+
+/// Files in a local directory, written with [`write_atomic`].
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+}
+
+impl Storage for LocalStorage {
+    fn describe(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    fn put<'a>(&'a self, path: &'a str, bytes: Vec<u8>) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let path = self.path(path);
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|e| Error::fs(dir, e))?;
+            }
+            write_atomic(&path, &bytes)
+        }
+        .boxed()
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Listing>> {
+        async move {
+            let mut listing = Listing::new();
+            if self.root.exists() {
+                list_dir(&self.root, "", &mut listing)?;
+            }
+            Ok(listing)
+        }
+        .boxed()
+    }
+
+    fn link<'a>(
+        &'a self,
+        original: &'a str,
+        dest: &'a str,
+        mode: DuplicateMode,
+    ) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let dest = self.path(dest);
+            if let Some(dir) = dest.parent() {
+                std::fs::create_dir_all(dir).map_err(|e| Error::fs(dir, e))?;
+            }
+            link_duplicate(&self.root, original, &dest, mode)
+        }
+        .boxed()
+    }
+}
+
+fn list_dir(dir: &Path, prefix: &str, listing: &mut Listing) -> Result<()> {
+    for entry in std::fs::read_dir(dir).map_err(|e| Error::fs(dir, e))? {
+        let entry = entry.map_err(|e| Error::fs(dir, e))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == STATE_DIR || name.ends_with(".part") {
+            continue;
+        }
+        let path = entry.path();
+        // Follows symlinks, so a linked duplicate has its original's size;
+        // broken links count as missing
+        let Ok(metadata) = std::fs::metadata(&path) else {
+            continue;
+        };
+        let rel = format!("{}{}", prefix, name);
+        if metadata.is_dir() {
+            list_dir(&path, &format!("{}/", rel), listing)?;
+        } else {
+            listing.insert(rel, metadata.len());
+        }
+    }
+    Ok(())
+}
+
+/// Files in a WebDAV collection. Uploads go to a `.part` file that is then
+/// moved into place, since a plain PUT is visible while it is in progress.
+pub struct WebDavStorage {
+    client: reqwest::Client,
+    base: url::Url,
+    username: Option<String>,
+    password: Option<String>,
+    /// Collections known to exist, so each is created at most once.
+    collections: Mutex<HashSet<String>>,
+}
+
+impl WebDavStorage {
+    pub fn new(url: &str, username: Option<String>, password: Option<String>) -> Result<Self> {
+        let mut base = url::Url::parse(url)
+            .map_err(|e| Error::Parse(format!("invalid WebDAV URL {}: {}", url, e)))?;
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        Ok(Self {
+            client: reqwest::Client::new(),
+            base,
+            username,
+            password,
+            collections: Mutex::new(HashSet::new()),
+        })
+    }
+
+    fn url(&self, path: &str) -> url::Url {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .expect("WebDAV URL has a path")
+            .pop_if_empty()
+            .extend(path.split('/'));
+        url
+    }
+
+    fn request(&self, method: Method, url: url::Url) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.username {
+            Some(user) => request.basic_auth(user, self.password.as_deref()),
+            None => request,
+        }
+    }
+
+    /// Create the collections above `path`, outermost first.
+    async fn create_parents(&self, path: &str) -> Result<()> {
+        let mut dir = String::new();
+        let Some((parents, _)) = path.rsplit_once('/') else {
+            return Ok(());
+        };
+        for segment in parents.split('/') {
+            dir = if dir.is_empty() {
+                segment.to_string()
+            } else {
+                format!("{}/{}", dir, segment)
+            };
+            if self.collections.lock().unwrap().contains(&dir) {
+                continue;
+            }
+            let mut url = self.url(&dir);
+            url.path_segments_mut()
+                .expect("WebDAV URL has a path")
+                .push("");
+            let mkcol = Method::from_bytes(b"MKCOL").expect("valid method");
+            let resp = self.request(mkcol, url).send().await?;
+            // 405 means it exists already
+            if !resp.status().is_success() && resp.status() != StatusCode::METHOD_NOT_ALLOWED {
+                return Err(Error::http(resp.status(), "WebDAV folder creation"));
+            }
+            self.collections.lock().unwrap().insert(dir.clone());
+        }
+        Ok(())
+    }
+
+    /// The files and sub-collections directly in collection `dir`.
+    async fn propfind(&self, dir: &str) -> Result<Vec<(String, Option<u64>)>> {
+        let mut url = if dir.is_empty() {
+            self.base.clone()
+        } else {
+            self.url(dir)
+        };
+        if !url.path().ends_with('/') {
+            url.path_segments_mut()
+                .expect("WebDAV URL has a path")
+                .push("");
+        }
+        let propfind = Method::from_bytes(b"PROPFIND").expect("valid method");
+        let resp = self
+            .request(propfind, url.clone())
+            .header("Depth", "1")
+            .header("Content-Type", "application/xml")
+            .body(
+                r#"<?xml version="1.0"?><d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/><d:getcontentlength/></d:prop></d:propfind>"#,
+            )
+            .send()
+            .await?;
+        if resp.status() == StatusCode::NOT_FOUND && dir.is_empty() {
+            return Ok(Vec::new());
+        }
+        if !resp.status().is_success() {
+            return Err(Error::http(resp.status(), "WebDAV listing"));
+        }
+        let body = resp.text().await?;
+        let doc = roxmltree::Document::parse(&body)
+            .map_err(|e| Error::Parse(format!("WebDAV listing: {}", e)))?;
+
+        let base_path = decode_path(self.base.path());
+        let own_path = decode_path(url.path());
+        let mut items = Vec::new();
+        for response in doc.descendants().filter(|n| is_dav(n, "response")) {
+            let Some(href) = response
+                .descendants()
+                .find(|n| is_dav(n, "href"))
+                .and_then(|n| n.text())
+            else {
+                continue;
+            };
+            // Servers answer with a path or a full URL
+            let href_path = url::Url::parse(href)
+                .map(|u| u.path().to_string())
+                .unwrap_or_else(|_| href.to_string());
+            let path = decode_path(&href_path);
+            if path.trim_end_matches('/') == own_path.trim_end_matches('/') {
+                continue;
+            }
+            let Some(rel) = path.strip_prefix(&base_path) else {
+                continue;
+            };
+            let is_collection = response.descendants().any(|n| is_dav(&n, "collection"));
+            let size = response
+                .descendants()
+                .find(|n| is_dav(n, "getcontentlength"))
+                .and_then(|n| n.text())
+                .and_then(|t| t.trim().parse().ok());
+            let rel = rel.trim_end_matches('/').to_string();
+            items.push((
+                rel,
+                if is_collection {
+                    None
+                } else {
+                    Some(size.unwrap_or(0))
+                },
+            ));
+        }
+        Ok(items)
+    }
+}
+
+fn is_dav(node: &roxmltree::Node<'_, '_>, name: &str) -> bool {
+    node.is_element()
+        && node.tag_name().name() == name
+        && node.tag_name().namespace() == Some("DAV:")
+}
+
+fn decode_path(path: &str) -> String {
+    urlencoding::decode(path)
+        .map(|p| p.into_owned())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+impl Storage for WebDavStorage {
+    fn describe(&self) -> String {
+        self.base.to_string()
+    }
+
+    fn put<'a>(&'a self, path: &'a str, bytes: Vec<u8>) -> BoxFuture<'a, Result<()>> {
+        async move {
+            self.create_parents(path).await?;
+            let part = format!("{}.part", path);
+            let resp = self
+                .request(Method::PUT, self.url(&part))
+                .body(bytes)
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                return Err(Error::http(resp.status(), "WebDAV upload"));
+            }
+            let moved = Method::from_bytes(b"MOVE").expect("valid method");
+            let resp = self
+                .request(moved, self.url(&part))
+                .header("Destination", self.url(path).as_str())
+                .header("Overwrite", "T")
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                return Err(Error::http(resp.status(), "WebDAV upload"));
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Listing>> {
+        async move {
+            // Depth 1 at a time, as servers such as Nextcloud refuse
+            // `Depth: infinity`
+            let mut listing = Listing::new();
+            let mut pending = vec![String::new()];
+            while let Some(dir) = pending.pop() {
+                for (path, size) in self.propfind(&dir).await? {
+                    match size {
+                        None => {
+                            self.collections.lock().unwrap().insert(path.clone());
+                            pending.push(path);
+                        }
+                        Some(_) if path.ends_with(".part") => {}
+                        Some(size) => {
+                            listing.insert(path, size);
+                        }
+                    }
+                }
+            }
+            Ok(listing)
+        }
+        .boxed()
+    }
+}
+
+/// Objects in an S3 bucket, signed with AWS Signature Version 4. A PUT
+/// only becomes visible once complete, so uploads are atomic as they are.
+pub struct S3Storage {
+    client: reqwest::Client,
+    endpoint: url::Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    /// Empty, or a folder ending in `/`.
+    prefix: String,
+}
+
+/// What S3 leaves unencoded in a signed path or query.
+fn s3_encode(value: &str, keep_slash: bool) -> String {
+    let mut out = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            b'/' if keep_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+impl S3Storage {
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+        prefix: &str,
+    ) -> Result<Self> {
+        let endpoint = url::Url::parse(endpoint)
+            .map_err(|e| Error::Parse(format!("invalid S3 endpoint {}: {}", endpoint, e)))?;
+        let prefix = prefix.trim_matches('/');
+        Ok(Self {
+            client: reqwest::Client::new(),
+            endpoint,
+            bucket: bucket.to_string(),
+            region: region.to_string(),
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+            prefix: if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("{}/", prefix)
+            },
+        })
+    }
+
+    /// A signed request for `key` (`None` for the bucket itself).
+    fn request(
+        &self,
+        method: Method,
+        key: Option<&str>,
+        query: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<reqwest::RequestBuilder> {
+        let mut path = format!(
+            "{}/{}",
+            self.endpoint.path().trim_end_matches('/'),
+            s3_encode(&self.bucket, false)
+        );
+        if let Some(key) = key {
+            path.push('/');
+            path.push_str(&s3_encode(&format!("{}{}", self.prefix, key), true));
+        }
+        let mut pairs: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (s3_encode(k, false), s3_encode(v, false)))
+            .collect();
+        pairs.sort();
+        let query = pairs
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        url.set_query((!query.is_empty()).then_some(query.as_str()));
+
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{}", self.endpoint.host_str().unwrap_or(""), port),
+            None => self.endpoint.host_str().unwrap_or("").to_string(),
+        };
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = format!("{:x}", Sha256::digest(&body));
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, path, query, host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+            amz_date,
+            scope,
+            Sha256::digest(canonical_request.as_bytes())
+        );
+        let mut key = hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac_sha256(&key, part);
+        }
+        let signature: String = hmac_sha256(&key, &string_to_sign)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        Ok(self
+            .client
+            .request(method, url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header(
+                "Authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    self.access_key, scope, signed_headers, signature
+                ),
+            )
+            .body(body))
+    }
+}
+
+impl Storage for S3Storage {
+    fn describe(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+
+    fn put<'a>(&'a self, path: &'a str, bytes: Vec<u8>) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let resp = self
+                .request(Method::PUT, Some(path), &[], bytes)?
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                return Err(Error::http(resp.status(), "S3 upload"));
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Listing>> {
+        async move {
+            let mut listing = Listing::new();
+            let mut token: Option<String> = None;
+            loop {
+                let mut query = vec![("list-type", "2"), ("prefix", self.prefix.as_str())];
+                if let Some(token) = &token {
+                    query.push(("continuation-token", token.as_str()));
+                }
+                let resp = self
+                    .request(Method::GET, None, &query, Vec::new())?
+                    .send()
+                    .await?;
+                if !resp.status().is_success() {
+                    return Err(Error::http(resp.status(), "S3 listing"));
+                }
+                let body = resp.text().await?;
+                let doc = roxmltree::Document::parse(&body)
+                    .map_err(|e| Error::Parse(format!("S3 listing: {}", e)))?;
+                let child_text = |node: roxmltree::Node<'_, '_>, name: &str| {
+                    node.children()
+                        .find(|n| n.tag_name().name() == name)
+                        .and_then(|n| n.text())
+                        .map(str::to_string)
+                };
+                for object in doc
+                    .descendants()
+                    .filter(|n| n.tag_name().name() == "Contents")
+                {
+                    let Some(key) = child_text(object, "Key") else {
+                        continue;
+                    };
+                    let Some(path) = key.strip_prefix(&self.prefix) else {
+                        continue;
+                    };
+                    let size = child_text(object, "Size")
+                        .and_then(|s| s.parse().ok())
+                        .unwrap_or(0);
+                    listing.insert(path.to_string(), size);
+                }
+                let root = doc.root_element();
+                token = match child_text(root, "IsTruncated").as_deref() {
+                    Some("true") => child_text(root, "NextContinuationToken"),
+                    _ => None,
+                };
+                if token.is_none() {
+                    return Ok(listing);
+                }
+            }
+        }
+        .boxed()
+    }
+}
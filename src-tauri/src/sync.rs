@@ -0,0 +1,233 @@
+use crate::downloader::{download_albums, resolve_out_dir};
+use crate::error::{Error, Result};
+use crate::export;
+use crate::manifest::{write_atomic, Manifest, STATE_DIR};
+use crate::progress::ProgressReporter;
+use crate::{Album, AppState, DownloadProgress, DownloadResult, DownloadSettings};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const SYNC_FILE: &str = "sync.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncedAlbum {
+    pub title: String,
+    pub image_count: Option<usize>,
+    pub synced_at: DateTime<Utc>,
+}
+
+/// Albums seen by the last successful sync of a library, with their image
+/// counts as reported by `GetAlbumsAsJson`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    pub albums: HashMap<String, SyncedAlbum>,
+}
+
+/// Albums that need their pages fetched in this sync.
+#[derive(Debug, Clone, Default)]
+pub struct SyncPlan {
+    pub new_albums: Vec<Album>,
+    pub changed_albums: Vec<Album>,
+    pub unchanged_albums: usize,
+}
+
+impl SyncPlan {
+    pub fn albums(&self) -> Vec<Album> {
+        self.new_albums
+            .iter()
+            .chain(&self.changed_albums)
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncSummary {
+    pub new_albums: usize,
+    pub changed_albums: usize,
+    pub unchanged_albums: usize,
+    pub new_images: usize,
+    pub result: DownloadResult,
+}
+
+impl fmt::Display for SyncSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} new album{}, {} new photo{}",
+            self.new_albums,
+            if self.new_albums == 1 { "" } else { "s" },
+            self.new_images,
+            if self.new_images == 1 { "" } else { "s" },
+        )
+    }
+}
+
+impl SyncState {
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = sync_path(root);
+        match std::fs::read(&path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(Error::fs(&path, e)),
+        }
+    }
+
+    pub fn save(&self, root: &Path) -> Result<()> {
+        let dir = root.join(STATE_DIR);
+        std::fs::create_dir_all(&dir).map_err(|e| Error::fs(&dir, e))?;
+        write_atomic(&sync_path(root), &serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Split `albums` into new, changed and unchanged. Albums whose count
+    /// Kidplan does not report are always treated as changed.
+    pub fn plan(&self, albums: &[Album]) -> SyncPlan {
+        let mut plan = SyncPlan::default();
+        for album in albums {
+            match self.albums.get(&album.id) {
+                None => plan.new_albums.push(album.clone()),
+                Some(seen)
+                    if album.image_count.is_some() && seen.image_count == album.image_count =>
+                {
+                    plan.unchanged_albums += 1
+                }
+                Some(_) => plan.changed_albums.push(album.clone()),
+            }
+        }
+        plan
+    }
+
+    pub fn mark_synced(&mut self, album: &Album) {
+        self.albums.insert(
+            album.id.clone(),
+            SyncedAlbum {
+                title: album.title.clone(),
+                image_count: album.image_count,
+                synced_at: Utc::now(),
+            },
+        );
+    }
+}
+
+fn sync_path(root: &Path) -> PathBuf {
+    root.join(STATE_DIR).join(SYNC_FILE)
+}
+
+/// Download only the albums that are new or whose image count changed since
+/// the last sync of `settings.out_dir`.
+///
+/// An album is remembered as synced only once all of its images were handled
+/// without failures, so interrupted or failing albums are retried next time.
+/// With `settings.limit_per_album`, albums that may have more images than the
+/// limit let through are not remembered either.
+/// With `settings.zip`, only the albums fetched in this sync are packed.
+pub async fn sync_albums(
+    state: &AppState,
+    albums: &[Album],
+    mut settings: DownloadSettings,
+    reporter: &dyn ProgressReporter,
+) -> Result<SyncSummary> {
+    let root = resolve_out_dir(&settings.out_dir);
+    let mut sync_state = SyncState::load(&root)?;
+    let plan = sync_state.plan(albums);
+    let to_fetch = plan.albums();
+    eprintln!(
+        "[DEBUG] sync: {} new, {} changed, {} unchanged albums",
+        plan.new_albums.len(),
+        plan.changed_albums.len(),
+        plan.unchanged_albums
+    );
+
+    let tracker = AlbumTracker {
+        inner: reporter,
+        albums: Mutex::new(HashMap::new()),
+    };
+    // Zipped here, once the tracker has seen every image. Images in remote
+    // storage can't be zipped, which the downloader notes in the log
+    let zip = if settings.storage.is_local() {
+        settings.zip.take()
+    } else {
+        None
+    };
+    let limit = settings.limit_per_album;
+    let mut result = download_albums(state, to_fetch.clone(), settings, &tracker).await?;
+
+    let finished = tracker.albums.into_inner().unwrap();
+    for (idx, album) in to_fetch.iter().enumerate() {
+        // The tracker only sees the images the limit let through
+        let whole = limit == 0 || album.image_count.is_some_and(|count| count <= limit);
+        if whole
+            && finished
+                .get(&(idx + 1))
+                .is_some_and(AlbumStatus::is_complete)
+        {
+            sync_state.mark_synced(album);
+        }
+    }
+    sync_state.save(&root)?;
+    if let Some(zip) = zip.filter(|_| !result.cancelled) {
+        let manifest = Manifest::open(&root)?;
+        let cancel = state.controls.cancellation();
+        let zipped =
+            export::zip_albums_in_background(manifest, to_fetch, root, zip, reporter, cancel).await;
+        // The sync itself is done, so its counts are kept
+        if let Err(e) = zipped {
+            eprintln!("[DEBUG] Could not zip the synced albums: {}", e);
+            result.cancelled |= matches!(e, Error::Cancelled);
+            result.export_error = Some(e.to_string());
+        }
+    }
+
+    Ok(SyncSummary {
+        new_albums: plan.new_albums.len(),
+        changed_albums: plan.changed_albums.len(),
+        unchanged_albums: plan.unchanged_albums,
+        new_images: result.total_images,
+        result,
+    })
+}
+
+#[derive(Default)]
+struct AlbumStatus {
+    image_total: Option<usize>,
+    handled: usize,
+    failed: bool,
+}
+
+impl AlbumStatus {
+    fn is_complete(&self) -> bool {
+        !self.failed && self.image_total == Some(self.handled)
+    }
+}
+
+/// Passes progress through while noting which albums finished cleanly.
+struct AlbumTracker<'a> {
+    inner: &'a dyn ProgressReporter,
+    albums: Mutex<HashMap<usize, AlbumStatus>>,
+}
+
+impl ProgressReporter for AlbumTracker<'_> {
+    fn report(&self, progress: &DownloadProgress) {
+        // Pauses and the final cancel update are about the job, not an image
+        if progress.paused || progress.result.is_some() {
+            self.inner.report(progress);
+            return;
+        }
+        {
+            let mut albums = self.albums.lock().unwrap();
+            let status = albums.entry(progress.album_index).or_default();
+            status.image_total = Some(progress.image_total);
+            if progress.image_index > 0 {
+                status.handled += 1;
+            }
+            if progress.error_type.is_some() {
+                status.failed = true;
+            }
+        }
+        self.inner.report(progress);
+    }
+}
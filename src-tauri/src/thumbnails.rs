@@ -0,0 +1,226 @@
+//! Small previews of downloaded images, for showing albums without loading
+//! every photo at full resolution.
+//!
+//! Thumbnails are JPEGs in `.kidplan/thumbnails` of the library, named after
+//! the SHA-256 of the image they show and their size, so identical images
+//! share one and a changed image gets a new one. What each file held when
+//! last seen is kept in `sources.json` with its size and modification time,
+//! so unchanged files are not read and hashed again.
+
+use crate::error::{Error, ErrorCode, Result};
+use crate::manifest::{sha256_hex, write_atomic, EntryStatus, Manifest, STATE_DIR};
+use chrono::{DateTime, Utc};
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// Folder inside [`STATE_DIR`] holding the thumbnails.
+pub const THUMBNAIL_DIR: &str = "thumbnails";
+const SOURCES_FILE: &str = "sources.json";
+/// Longest side of a thumbnail in pixels, unless asked otherwise.
+pub const DEFAULT_SIZE: u32 = 320;
+const MIN_SIZE: u32 = 16;
+const MAX_SIZE: u32 = 1600;
+const JPEG_QUALITY: u8 = 80;
+
+/// An image file as last seen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Source {
+    size: u64,
+    modified: Option<DateTime<Utc>>,
+    sha256: String,
+}
+
+/// The thumbnails of one library.
+pub struct ThumbnailCache {
+    root: PathBuf,
+    dir: PathBuf,
+    /// By image path relative to the library root.
+    sources: HashMap<String, Source>,
+    changed: bool,
+}
+
+impl ThumbnailCache {
+    pub fn open(root: &Path) -> Self {
+        let dir = root.join(STATE_DIR).join(THUMBNAIL_DIR);
+        // Losing this only means hashing the images once more
+        let sources = std::fs::read(dir.join(SOURCES_FILE))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Self {
+            root: root.to_path_buf(),
+            dir,
+            sources,
+            changed: false,
+        }
+    }
+
+    /// Thumbnail of the image at `path`, relative to the library root with
+    /// `/` separators, whose longer side is at most `size` pixels. It is
+    /// made now when the image is new or has changed since.
+    pub fn thumbnail(&mut self, path: &str, size: u32) -> Result<PathBuf> {
+        let size = size.clamp(MIN_SIZE, MAX_SIZE);
+        let file = path
+            .split('/')
+            .fold(self.root.clone(), |file, part| file.join(part));
+        let metadata = std::fs::metadata(&file).map_err(|e| Error::fs(&file, e))?;
+        let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+
+        let mut bytes = None;
+        let sha256 = match self
+            .sources
+            .get(path)
+            .filter(|s| s.size == metadata.len() && s.modified == modified)
+        {
+            Some(source) => source.sha256.clone(),
+            None => {
+                let read = std::fs::read(&file).map_err(|e| Error::fs(&file, e))?;
+                let sha256 = sha256_hex(&read);
+                bytes = Some(read);
+                let source = Source {
+                    size: metadata.len(),
+                    modified,
+                    sha256: sha256.clone(),
+                };
+                if let Some(old) = self.sources.insert(path.to_string(), source) {
+                    self.forget(&old.sha256);
+                }
+                self.changed = true;
+                sha256
+            }
+        };
+
+        let thumbnail = self.dir.join(format!("{}-{}.jpg", sha256, size));
+        if thumbnail.is_file() {
+            return Ok(thumbnail);
+        }
+        let bytes = match bytes {
+            Some(bytes) => bytes,
+            None => std::fs::read(&file).map_err(|e| Error::fs(&file, e))?,
+        };
+        let jpeg = render(&file, &bytes, size)?;
+        std::fs::create_dir_all(&self.dir).map_err(|e| Error::fs(&self.dir, e))?;
+        if let Err(e) = write_atomic(&thumbnail, &jpeg) {
+            // Someone else may have just written the same thumbnail
+            if !thumbnail.is_file() {
+                return Err(e);
+            }
+        }
+        eprintln!("[DEBUG] Made thumbnail {:?} of {}", thumbnail, path);
+        Ok(thumbnail)
+    }
+
+    /// Thumbnail of image `image_id` of album `album_id`, or of the album's
+    /// first image that can be shown when `image_id` is `None`. `None` when
+    /// nothing of it is downloaded into the library, e.g. with remote
+    /// storage.
+    pub fn for_album(
+        &mut self,
+        manifest: &Manifest,
+        album_id: &str,
+        image_id: Option<&str>,
+        size: u32,
+    ) -> Result<Option<PathBuf>> {
+        let mut entries: Vec<_> = manifest
+            .entries()
+            .filter(|e| e.album_id == album_id && e.status == EntryStatus::Complete)
+            .filter(|e| image_id.is_none_or(|id| e.image_id == id))
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        for entry in entries {
+            if !self.root.join(&entry.path).is_file() {
+                continue;
+            }
+            match self.thumbnail(&entry.path, size) {
+                Ok(thumbnail) => return Ok(Some(thumbnail)),
+                Err(e) if image_id.is_none() && e.code() == ErrorCode::UnsupportedImage => {
+                    eprintln!("[DEBUG] No cover from {}: {}", entry.path, e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remember what the images looked like for next time.
+    pub fn save(&mut self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.dir).map_err(|e| Error::fs(&self.dir, e))?;
+        write_atomic(
+            &self.dir.join(SOURCES_FILE),
+            &serde_json::to_vec(&self.sources)?,
+        )?;
+        self.changed = false;
+        Ok(())
+    }
+
+    /// Remove the thumbnails of content no image has any more.
+    fn forget(&self, sha256: &str) {
+        if self.sources.values().any(|s| s.sha256 == sha256) {
+            return;
+        }
+        let prefix = format!("{}-", sha256);
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+/// Thumbnail of an album or one of its images in the library at `root`,
+/// see [`ThumbnailCache::for_album`].
+pub fn find(
+    root: &Path,
+    album_id: &str,
+    image_id: Option<&str>,
+    size: u32,
+) -> Result<Option<PathBuf>> {
+    // Asking must not turn a folder into a library
+    if !root.join(STATE_DIR).is_dir() {
+        return Ok(None);
+    }
+    let manifest = Manifest::open(root)?;
+    let mut cache = ThumbnailCache::open(root);
+    let thumbnail = cache.for_album(&manifest, album_id, image_id, size)?;
+    // Another request may be saving at the same time; the next one will
+    // catch up on what this one learned
+    if let Err(e) = cache.save() {
+        eprintln!("[DEBUG] Could not save thumbnail sources: {}", e);
+    }
+    Ok(thumbnail)
+}
+
+/// Decode a JPEG, PNG or WebP image, turn it upright and scale it down to
+/// fit `size`, as JPEG.
+fn render(file: &Path, bytes: &[u8], size: u32) -> Result<Vec<u8>> {
+    let unsupported = |e: image::ImageError| Error::UnsupportedImage {
+        path: file.display().to_string(),
+        message: e.to_string(),
+    };
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| Error::fs(file, e))?
+        .into_decoder()
+        .map_err(unsupported)?;
+    let orientation = decoder.orientation().map_err(unsupported)?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(unsupported)?;
+    image.apply_orientation(orientation);
+
+    let mut jpeg = Vec::new();
+    image
+        .thumbnail(size, size)
+        .to_rgb8()
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY))
+        .map_err(unsupported)?;
+    Ok(jpeg)
+}
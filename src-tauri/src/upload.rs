@@ -0,0 +1,497 @@
+use crate::error::{Error, Result};
+use crate::metadata::local_at;
+use chrono::{Local, NaiveDateTime, SecondsFormat};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use reqwest::header::ACCEPT;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Sent to Immich as the uploading device, so it can tell our assets apart.
+const DEVICE_ID: &str = "kidplan-downloader";
+
+/// A downloaded photo to push into the photo library.
+pub struct Asset<'a> {
+    pub album_title: &'a str,
+    /// Stable id of the photo (album and image id), so the library can tell
+    /// it was sent before.
+    pub id: &'a str,
+    pub filename: &'a str,
+    pub bytes: &'a [u8],
+    /// When the photo was taken, as far as known (the album date).
+    pub taken: Option<NaiveDateTime>,
+}
+
+/// The library's id of an uploaded photo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Uploaded {
+    Created(String),
+    /// The library already had the photo, so it was not sent again.
+    Existing(String),
+}
+
+impl Uploaded {
+    pub fn id(&self) -> &str {
+        match self {
+            Uploaded::Created(id) | Uploaded::Existing(id) => id,
+        }
+    }
+}
+
+/// A self-hosted photo library that downloaded photos are pushed into.
+pub trait PhotoLibrary: Send + Sync {
+    /// The library's name and address, for logs and progress.
+    fn describe(&self) -> String;
+
+    /// Send one photo, unless the library already has it.
+    fn upload<'a>(&'a self, asset: &'a Asset<'a>) -> BoxFuture<'a, Result<Uploaded>>;
+
+    /// Put photos uploaded with `album_title` into the library album of
+    /// that name, creating it when missing.
+    fn add_to_album<'a>(
+        &'a self,
+        album_title: &'a str,
+        photos: &'a [Uploaded],
+    ) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Which photo library downloaded photos are pushed into.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum UploadConfig {
+    /// An Immich server, with an API key from its account settings.
+    Immich { url: String, api_key: String },
+    /// A PhotoPrism server, with an app password.
+    #[serde(rename = "photoprism")]
+    PhotoPrism { url: String, token: String },
+}
+
+// Settings are logged, so the keys are left out
+impl fmt::Debug for UploadConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadConfig::Immich { url, .. } => f
+                .debug_struct("Immich")
+                .field("url", url)
+                .finish_non_exhaustive(),
+            UploadConfig::PhotoPrism { url, .. } => f
+                .debug_struct("PhotoPrism")
+                .field("url", url)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl UploadConfig {
+    /// The same library without its API key or app password, to save where
+    /// they could be read.
+    pub fn without_secrets(&self) -> UploadConfig {
+        let mut config = self.clone();
+        match &mut config {
+            UploadConfig::Immich { api_key, .. } => api_key.clear(),
+            UploadConfig::PhotoPrism { token, .. } => token.clear(),
+        }
+        config
+    }
+
+    pub fn open(&self) -> Box<dyn PhotoLibrary> {
+        match self {
+            UploadConfig::Immich { url, api_key } => Box::new(Immich::new(url, api_key)),
+            UploadConfig::PhotoPrism { url, token } => Box::new(PhotoPrism::new(url, token)),
+        }
+    }
+}
+
+fn sha1_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha1::digest(bytes))
+}
+
+/// Uploads through the Immich API: assets are matched by SHA-1 checksum
+/// before sending, and albums are found or created by name.
+pub struct Immich {
+    client: reqwest::Client,
+    url: String,
+    api_key: String,
+    /// Album ids by name, once listed.
+    albums: Mutex<Option<HashMap<String, String>>>,
+}
+
+#[derive(Deserialize)]
+struct BulkCheck {
+    results: Vec<BulkCheckResult>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkCheckResult {
+    action: String,
+    asset_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UploadResponse {
+    id: String,
+    status: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImmichAlbum {
+    id: String,
+    album_name: String,
+}
+
+impl Immich {
+    pub fn new(url: &str, api_key: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            albums: Mutex::new(None),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, format!("{}/api{}", self.url, path))
+            .header("x-api-key", &self.api_key)
+            .header(ACCEPT, "application/json")
+    }
+
+    async fn album_id(&self, title: &str) -> Result<String> {
+        if self.albums.lock().unwrap().is_none() {
+            let resp = self.request(Method::GET, "/albums").send().await?;
+            if !resp.status().is_success() {
+                return Err(Error::http(resp.status(), "Immich album list"));
+            }
+            let albums: Vec<ImmichAlbum> = resp.json().await?;
+            *self.albums.lock().unwrap() =
+                Some(albums.into_iter().map(|a| (a.album_name, a.id)).collect());
+        }
+        let known = self
+            .albums
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|albums| albums.get(title).cloned());
+        if let Some(id) = known {
+            return Ok(id);
+        }
+
+        eprintln!("[DEBUG] Creating Immich album {:?}", title);
+        let resp = self
+            .request(Method::POST, "/albums")
+            .json(&serde_json::json!({ "albumName": title }))
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(Error::http(resp.status(), "Immich album creation"));
+        }
+        let album: ImmichAlbum = resp.json().await?;
+        if let Some(albums) = self.albums.lock().unwrap().as_mut() {
+            albums.insert(album.album_name, album.id.clone());
+        }
+        Ok(album.id)
+    }
+}
+
+impl PhotoLibrary for Immich {
+    fn describe(&self) -> String {
+        format!("Immich at {}", self.url)
+    }
+
+    fn upload<'a>(&'a self, asset: &'a Asset<'a>) -> BoxFuture<'a, Result<Uploaded>> {
+        async move {
+            let resp = self
+                .request(Method::POST, "/assets/bulk-upload-check")
+                .json(&serde_json::json!({
+                    "assets": [{ "id": asset.id, "checksum": sha1_hex(asset.bytes) }]
+                }))
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                return Err(Error::http(resp.status(), "Immich duplicate check"));
+            }
+            let check: BulkCheck = resp.json().await?;
+            if let Some(BulkCheckResult {
+                asset_id: Some(id), ..
+            }) = check.results.into_iter().find(|r| r.action == "reject")
+            {
+                return Ok(Uploaded::Existing(id));
+            }
+
+            // Album dates are local times; Immich wants them with an offset
+            let taken = asset
+                .taken
+                .map(local_at)
+                .unwrap_or_else(Local::now)
+                .to_rfc3339_opts(SecondsFormat::Millis, false);
+            let form = Form::new()
+                .text("deviceAssetId", asset.id.to_string())
+                .text("deviceId", DEVICE_ID)
+                .text("fileCreatedAt", taken.clone())
+                .text("fileModifiedAt", taken)
+                .text("filename", asset.filename.to_string())
+                .part(
+                    "assetData",
+                    Part::bytes(asset.bytes.to_vec()).file_name(asset.filename.to_string()),
+                );
+            let resp = self
+                .request(Method::POST, "/assets")
+                .multipart(form)
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                return Err(Error::http(resp.status(), "Immich upload"));
+            }
+            let uploaded: UploadResponse = resp.json().await?;
+            Ok(match uploaded.status.as_str() {
+                "duplicate" => Uploaded::Existing(uploaded.id),
+                _ => Uploaded::Created(uploaded.id),
+            })
+        }
+        .boxed()
+    }
+
+    fn add_to_album<'a>(
+        &'a self,
+        album_title: &'a str,
+        photos: &'a [Uploaded],
+    ) -> BoxFuture<'a, Result<()>> {
+        async move {
+            if photos.is_empty() {
+                return Ok(());
+            }
+            let album = self.album_id(album_title).await?;
+            let ids: Vec<&str> = photos.iter().map(Uploaded::id).collect();
+            // Photos already in the album are reported per photo, not as
+            // a failure of the request
+            let resp = self
+                .request(Method::PUT, &format!("/albums/{}/assets", album))
+                .json(&serde_json::json!({ "ids": ids }))
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                return Err(Error::http(
+                    resp.status(),
+                    "Adding photos to the Immich album",
+                ));
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Uploads through the PhotoPrism API. Photos whose SHA-1 PhotoPrism
+/// already indexed are skipped; new ones are uploaded per album and then
+/// imported into the album of that name. Skipped photos are added to that
+/// album by their photo UID.
+pub struct PhotoPrism {
+    client: reqwest::Client,
+    url: String,
+    token: String,
+    user: tokio::sync::OnceCell<String>,
+    /// Upload batch per album title, imported by `add_to_album`.
+    batches: Mutex<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+struct PhotoPrismSession {
+    user: PhotoPrismUser,
+}
+
+#[derive(Deserialize)]
+struct PhotoPrismUser {
+    #[serde(rename = "UID")]
+    uid: String,
+}
+
+#[derive(Deserialize)]
+struct PhotoPrismFile {
+    #[serde(rename = "PhotoUID")]
+    photo_uid: String,
+}
+
+#[derive(Deserialize)]
+struct PhotoPrismAlbum {
+    #[serde(rename = "UID")]
+    uid: String,
+    #[serde(rename = "Title")]
+    title: String,
+}
+
+impl PhotoPrism {
+    pub fn new(url: &str, token: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            user: tokio::sync::OnceCell::new(),
+            batches: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, format!("{}/api/v1{}", self.url, path))
+            .header("X-Auth-Token", &self.token)
+            .header(ACCEPT, "application/json")
+    }
+
+    /// The user the app password belongs to, whose uploads these are.
+    async fn user(&self) -> Result<&str> {
+        let uid = self
+            .user
+            .get_or_try_init(|| async {
+                let resp = self.request(Method::GET, "/session").send().await?;
+                if !resp.status().is_success() {
+                    return Err(Error::http(resp.status(), "PhotoPrism session"));
+                }
+                let session: PhotoPrismSession = resp.json().await?;
+                Ok(session.user.uid)
+            })
+            .await?;
+        Ok(uid)
+    }
+
+    /// The UID of the album titled `title`, created when missing.
+    async fn album_uid(&self, title: &str) -> Result<String> {
+        let resp = self
+            .request(Method::GET, "/albums")
+            .query(&[("count", "1000"), ("type", "album"), ("q", title)])
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(Error::http(resp.status(), "PhotoPrism album list"));
+        }
+        let albums: Vec<PhotoPrismAlbum> = resp.json().await?;
+        // The search also matches titles that only contain `title`
+        if let Some(album) = albums.into_iter().find(|a| a.title == title) {
+            return Ok(album.uid);
+        }
+
+        eprintln!("[DEBUG] Creating PhotoPrism album {:?}", title);
+        let resp = self
+            .request(Method::POST, "/albums")
+            .json(&serde_json::json!({ "Title": title }))
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(Error::http(resp.status(), "PhotoPrism album creation"));
+        }
+        let album: PhotoPrismAlbum = resp.json().await?;
+        Ok(album.uid)
+    }
+
+    /// The upload batch of an album, named after its title and the time so
+    /// each run imports only what it uploaded.
+    fn batch(&self, album_title: &str) -> String {
+        self.batches
+            .lock()
+            .unwrap()
+            .entry(album_title.to_string())
+            .or_insert_with(|| {
+                let seed = format!(
+                    "{}{}",
+                    album_title,
+                    chrono::Utc::now().timestamp_nanos_opt().unwrap_or(0)
+                );
+                format!("kidplan{}", &sha1_hex(seed.as_bytes())[..12])
+            })
+            .clone()
+    }
+}
+
+impl PhotoLibrary for PhotoPrism {
+    fn describe(&self) -> String {
+        format!("PhotoPrism at {}", self.url)
+    }
+
+    fn upload<'a>(&'a self, asset: &'a Asset<'a>) -> BoxFuture<'a, Result<Uploaded>> {
+        async move {
+            let sha1 = sha1_hex(asset.bytes);
+            let resp = self
+                .request(Method::GET, &format!("/files/{}", sha1))
+                .send()
+                .await?;
+            match resp.status() {
+                s if s.is_success() => {
+                    let file: PhotoPrismFile = resp.json().await?;
+                    return Ok(Uploaded::Existing(file.photo_uid));
+                }
+                StatusCode::NOT_FOUND => {}
+                s => return Err(Error::http(s, "PhotoPrism duplicate check")),
+            }
+
+            let user = self.user().await?;
+            let batch = self.batch(asset.album_title);
+            let form = Form::new().part(
+                "files",
+                Part::bytes(asset.bytes.to_vec()).file_name(asset.filename.to_string()),
+            );
+            let resp = self
+                .request(Method::POST, &format!("/users/{}/upload/{}", user, batch))
+                .multipart(form)
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                return Err(Error::http(resp.status(), "PhotoPrism upload"));
+            }
+            Ok(Uploaded::Created(sha1))
+        }
+        .boxed()
+    }
+
+    fn add_to_album<'a>(
+        &'a self,
+        album_title: &'a str,
+        photos: &'a [Uploaded],
+    ) -> BoxFuture<'a, Result<()>> {
+        async move {
+            // Photos sent in this run are imported into the album with their
+            // batch; those PhotoPrism already had are added by UID
+            let batch = self.batches.lock().unwrap().remove(album_title);
+            if let Some(batch) = batch {
+                let user = self.user().await?;
+                let resp = self
+                    .request(Method::PUT, &format!("/users/{}/upload/{}", user, batch))
+                    .json(&serde_json::json!({ "albums": [album_title] }))
+                    .send()
+                    .await?;
+                if !resp.status().is_success() {
+                    return Err(Error::http(resp.status(), "PhotoPrism import"));
+                }
+            }
+            let existing: Vec<&str> = photos
+                .iter()
+                .filter_map(|photo| match photo {
+                    Uploaded::Existing(uid) => Some(uid.as_str()),
+                    Uploaded::Created(_) => None,
+                })
+                .collect();
+            if existing.is_empty() {
+                return Ok(());
+            }
+            let album = self.album_uid(album_title).await?;
+            let resp = self
+                .request(Method::POST, &format!("/albums/{}/photos", album))
+                .json(&serde_json::json!({ "photos": existing }))
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                return Err(Error::http(
+                    resp.status(),
+                    "Adding photos to the PhotoPrism album",
+                ));
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
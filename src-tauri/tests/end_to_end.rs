@@ -0,0 +1,1988 @@
+mod support;
+
+use kidplan_downloader_lib::dedupe::DuplicateMode;
+use kidplan_downloader_lib::error::{Error, ErrorCode};
+use kidplan_downloader_lib::export::{self, ZipLayout, ZipSettings};
+use kidplan_downloader_lib::gallery::{self, GalleryLanguage};
+use kidplan_downloader_lib::manifest::{AlbumManifest, Manifest, Verification};
+use kidplan_downloader_lib::progress::CollectingReporter;
+use kidplan_downloader_lib::queue::{JobKind, JobQueue, JobStatus};
+use kidplan_downloader_lib::schedule::{Schedule, ScheduleConfig, Scheduler};
+use kidplan_downloader_lib::session::{self, SessionStore};
+use kidplan_downloader_lib::storage::StorageConfig;
+use kidplan_downloader_lib::upload::UploadConfig;
+use kidplan_downloader_lib::{
+    downloader, kindergartens, manifest, naming, sync, thumbnails, AppState, Credentials,
+    DownloadSettings,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use support::{
+    MockAlbum, MockKidplan, MockPhotoLibrary, MockS3, MockServer, MockWebDav, LIBRARY_KEY,
+};
+
+async fn logged_in_state(server: &MockServer) -> AppState {
+    let state = AppState::with_endpoints(server.endpoints());
+    let client = downloader::build_client(state.cookie_jar.clone());
+    let kids = downloader::fetch_kindergarten_ids(&client, &state.endpoints, &server.credentials())
+        .await
+        .expect("kindergartens");
+    *state.kindergartens.lock().await = kids;
+    downloader::login(&client, &state.endpoints, &server.credentials(), 101)
+        .await
+        .expect("login");
+    state.select_kindergarten(101).await;
+    *state.client.lock().await = Some(client);
+    state
+}
+
+fn settings(out_dir: &std::path::Path) -> DownloadSettings {
+    DownloadSettings {
+        out_dir: out_dir.to_string_lossy().to_string(),
+        delay_ms: 0,
+        ..DownloadSettings::default()
+    }
+}
+
+#[tokio::test]
+async fn lists_kindergartens_and_rejects_bad_credentials() {
+    let server = MockKidplan::sample().start().await;
+    let endpoints = server.endpoints();
+    let client = downloader::build_client(Default::default());
+
+    let kids = downloader::fetch_kindergarten_ids(&client, &endpoints, &server.credentials())
+        .await
+        .unwrap();
+    assert_eq!(kids.len(), 1);
+    assert_eq!(kids[0].id, 101);
+
+    let wrong = Credentials {
+        email: "parent@example.com".to_string(),
+        password: "wrong".to_string(),
+    };
+    assert!(matches!(
+        downloader::fetch_kindergarten_ids(&client, &endpoints, &wrong).await,
+        Err(Error::InvalidCredentials)
+    ));
+    assert!(matches!(
+        downloader::login(&client, &endpoints, &wrong, 101).await,
+        Err(Error::InvalidCredentials)
+    ));
+}
+
+#[tokio::test]
+async fn fetch_albums_follows_pagination() {
+    let mut mock = MockKidplan::sample();
+    mock.albums = (0..120)
+        .map(|n| MockAlbum::new(&format!("p{}", n), &format!("Album {}", n), 1))
+        .collect();
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    assert_eq!(albums.len(), 120);
+    assert_eq!(albums[119].id, "p119");
+    assert!(albums[0].url.starts_with(&server.base_url()));
+    assert_eq!(albums[0].image_count, Some(1));
+}
+
+#[tokio::test]
+async fn album_metadata_is_captured_from_the_album_list() {
+    let mut mock = MockKidplan::sample();
+    let album = &mut mock.albums[0];
+    album.date = Some("/Date(1718965800000)/".to_string());
+    for (key, value) in [
+        (
+            "Description",
+            serde_json::json!("  Grilling og leker i hagen "),
+        ),
+        ("CoverImageUrl", serde_json::json!("/bilder/cover/a1.jpeg")),
+        ("CreatedByName", serde_json::json!("Kari Nordmann")),
+        ("DepartmentName", serde_json::json!("Blåbær")),
+        ("Created", serde_json::json!("2024-06-22")),
+        ("IsShared", serde_json::json!(true)),
+    ] {
+        album.fields.insert(key.to_string(), value);
+    }
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let album = &albums[0];
+    // AlbumDate wins over the less specific Created
+    assert_eq!(album.date.unwrap().date().to_string(), "2024-06-21");
+    assert_eq!(
+        album.description.as_deref(),
+        Some("Grilling og leker i hagen")
+    );
+    assert_eq!(
+        album.cover_url.as_deref(),
+        Some(format!("{}/bilder/cover/a1.jpeg", server.base_url()).as_str())
+    );
+    assert_eq!(album.author.as_deref(), Some("Kari Nordmann"));
+    assert_eq!(album.department.as_deref(), Some("Blåbær"));
+    // Only fields we don't understand are kept raw
+    assert_eq!(album.extra.len(), 1);
+    assert_eq!(album.extra["IsShared"], serde_json::json!(true));
+    assert!(albums[1].description.is_none() && albums[1].extra.is_empty());
+
+    // The metadata survives the round trip through the UI
+    let json = serde_json::to_string(album).unwrap();
+    let back: kidplan_downloader_lib::Album = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.department, album.department);
+    assert_eq!(back.extra, album.extra);
+
+    let paths = naming::preview(
+        "{department}/{album_title}/{index}.{ext}",
+        &albums[..1],
+        None,
+    )
+    .unwrap();
+    assert_eq!(paths[0], "Blåbær/Sommerfest 2024/1.jpeg");
+}
+
+#[tokio::test]
+async fn login_list_and_download_every_album() {
+    let server = MockKidplan::sample().start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let reporter = CollectingReporter::new();
+    let result = downloader::download_albums(&state, albums, settings(out.path()), &reporter)
+        .await
+        .unwrap();
+
+    assert_eq!(result.total_albums, 2);
+    assert_eq!(result.total_images, 5);
+    assert_eq!(result.failed, 0);
+    let file = out.path().join("Sommerfest-2024").join("id-a1-001.jpeg");
+    assert_eq!(std::fs::read(file).unwrap(), support::fake_jpeg("a1/1"));
+    let downloaded = reporter
+        .events()
+        .iter()
+        .filter(|p| p.status == "downloaded")
+        .count();
+    assert_eq!(downloaded, 5);
+}
+
+#[tokio::test]
+async fn second_run_skips_existing_files() {
+    let server = MockKidplan::sample().start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+
+    let reporter = CollectingReporter::new();
+    downloader::download_albums(&state, albums.clone(), settings(out.path()), &reporter)
+        .await
+        .unwrap();
+    let fetched = server.image_requests();
+
+    let result = downloader::download_albums(&state, albums, settings(out.path()), &reporter)
+        .await
+        .unwrap();
+    assert_eq!(result.total_images, 0);
+    assert_eq!(result.skipped, 5);
+    assert_eq!(server.image_requests(), fetched);
+}
+
+#[tokio::test]
+async fn expired_session_is_reported_per_album() {
+    let server = MockKidplan::sample().start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+
+    server.expire_sessions();
+    let reporter = CollectingReporter::new();
+    let result = downloader::download_albums(&state, albums, settings(out.path()), &reporter)
+        .await
+        .unwrap();
+
+    assert_eq!(result.failed, 2);
+    assert!(reporter
+        .events()
+        .iter()
+        .all(|p| p.error_type == Some(ErrorCode::SessionExpired)));
+}
+
+#[tokio::test]
+async fn expired_sessions_are_renewed_with_the_stored_credentials() {
+    let logins = |server: &MockServer| {
+        server
+            .requests()
+            .iter()
+            .filter(|r| r.starts_with("/LogOn"))
+            .count()
+    };
+
+    // Expired before the first album page, and again between two images
+    let mut mock = MockKidplan::sample();
+    mock.expire_after_images = Some(2);
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    *state.credentials.lock().await = Some(server.credentials());
+    let client = state.client.lock().await.clone().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    server.expire_sessions();
+    let before = logins(&server);
+    let out = tempfile::tempdir().unwrap();
+    let result = downloader::download_albums(
+        &state,
+        albums.clone(),
+        settings(out.path()),
+        &CollectingReporter::new(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(result.failed, 0);
+    assert_eq!(result.total_images, 5);
+    assert_eq!(logins(&server) - before, 2);
+
+    // A password that stopped working gives up after a bounded number of tries
+    *state.credentials.lock().await = Some(Credentials {
+        password: "changed".to_string(),
+        ..server.credentials()
+    });
+    server.expire_sessions();
+    let before = logins(&server);
+    let out = tempfile::tempdir().unwrap();
+    let result = downloader::download_albums(
+        &state,
+        albums,
+        settings(out.path()),
+        &CollectingReporter::new(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(result.failed, 2);
+    assert!(logins(&server) - before <= 3);
+}
+
+#[tokio::test]
+async fn parallel_download_reports_in_album_order() {
+    let mut mock = MockKidplan::sample();
+    mock.albums = vec![MockAlbum::new("big", "Big album", 20)];
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+
+    let reporter = CollectingReporter::new();
+    let settings = DownloadSettings {
+        concurrency: 4,
+        ..settings(out.path())
+    };
+    let result = downloader::download_albums(&state, albums, settings, &reporter)
+        .await
+        .unwrap();
+
+    assert_eq!(result.total_images, 20);
+    let order: Vec<usize> = reporter
+        .events()
+        .iter()
+        .filter(|p| p.status == "downloaded")
+        .map(|p| p.image_index)
+        .collect();
+    assert_eq!(order, (1..=20).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn paused_downloads_wait_and_continue_where_they_stopped() {
+    let server = MockKidplan::sample().start().await;
+    let state = Arc::new(logged_in_state(&server).await);
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let reporter = Arc::new(CollectingReporter::new());
+
+    state.controls.pause();
+    let download = tokio::spawn({
+        let (state, reporter) = (state.clone(), reporter.clone());
+        let settings = DownloadSettings {
+            concurrency: 3,
+            ..settings(out.path())
+        };
+        async move { downloader::download_albums(&state, albums, settings, &*reporter).await }
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!download.is_finished());
+    assert_eq!(server.image_requests(), 0);
+    let paused: Vec<_> = reporter.events().into_iter().filter(|p| p.paused).collect();
+    assert_eq!(paused.len(), 1, "one update per pause");
+    assert_eq!(paused[0].status, "paused");
+    assert_eq!(paused[0].album_index, 1);
+
+    state.controls.resume();
+    let result = download.await.unwrap().unwrap();
+    assert_eq!(result.total_images, 5);
+    assert_eq!(result.failed, 0);
+    assert_eq!(server.image_requests(), 5);
+
+    // Cancelling a paused download ends it instead of leaving it waiting
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    state.controls.pause();
+    let download = tokio::spawn({
+        let state = state.clone();
+        let settings = settings(out.path());
+        async move {
+            downloader::download_albums(&state, albums, settings, &CollectingReporter::new()).await
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    state.controls.cancel();
+    tokio::time::timeout(Duration::from_secs(5), download)
+        .await
+        .expect("cancel ends a paused download")
+        .unwrap()
+        .unwrap();
+    assert_eq!(server.image_requests(), 5);
+}
+
+#[tokio::test]
+async fn cancel_interrupts_slow_requests_and_reports_what_was_done() {
+    let mut mock = MockKidplan::sample();
+    mock.image_delay = Some(Duration::from_secs(30));
+    let server = mock.start().await;
+    let state = Arc::new(logged_in_state(&server).await);
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    // Left behind by an earlier run that was killed while writing
+    let part = out
+        .path()
+        .join("Sommerfest-2024")
+        .join("id-a1-002.jpeg.part");
+    std::fs::create_dir_all(part.parent().unwrap()).unwrap();
+    std::fs::write(&part, b"half").unwrap();
+
+    let reporter = Arc::new(CollectingReporter::new());
+    let download = tokio::spawn({
+        let (state, reporter) = (state.clone(), reporter.clone());
+        let settings = DownloadSettings {
+            concurrency: 2,
+            ..settings(out.path())
+        };
+        async move { downloader::download_albums(&state, albums, settings, &*reporter).await }
+    });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(server.image_requests(), 2);
+    state.controls.cancel();
+    let result = tokio::time::timeout(Duration::from_secs(2), download)
+        .await
+        .expect("cancel does not wait for the requests in flight")
+        .unwrap()
+        .unwrap();
+
+    assert!(result.cancelled);
+    assert_eq!(result.total_albums, 1);
+    assert_eq!(result.total_images, 0);
+    assert!(!part.exists());
+    let events = reporter.events();
+    let last = events.last().unwrap();
+    assert_eq!(last.status, "cancelled");
+    assert_eq!(last.album_index, 1);
+    let reported = last.result.as_ref().expect("partial result");
+    assert!(reported.cancelled);
+    assert_eq!(reported.total_albums, 1);
+}
+
+#[tokio::test]
+async fn cancel_interrupts_retry_backoff() {
+    let mut mock = MockKidplan::sample();
+    mock.image_status = Some(axum::http::StatusCode::SERVICE_UNAVAILABLE);
+    let server = mock.start().await;
+    let state = Arc::new(logged_in_state(&server).await);
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+
+    let download = tokio::spawn({
+        let state = state.clone();
+        let settings = settings(out.path());
+        async move {
+            downloader::download_albums(&state, albums, settings, &CollectingReporter::new()).await
+        }
+    });
+    // The first image has failed once and waits 1s before trying again
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(server.image_requests(), 1);
+    state.controls.cancel();
+    let result = tokio::time::timeout(Duration::from_millis(500), download)
+        .await
+        .expect("cancel does not wait for the backoff")
+        .unwrap()
+        .unwrap();
+    assert!(result.cancelled);
+    assert_eq!(result.failed, 0);
+    assert_eq!(server.image_requests(), 1);
+}
+
+#[tokio::test]
+async fn queued_jobs_run_in_order_and_survive_a_restart() {
+    let server = MockKidplan::sample().start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let app_data = tempfile::tempdir().unwrap();
+    let out = tempfile::tempdir().unwrap();
+
+    let queue = JobQueue::open(app_data.path()).unwrap();
+    let first = queue
+        .enqueue(&state, vec![albums[0].clone()], settings(out.path()))
+        .await
+        .unwrap();
+    let second = queue
+        .enqueue(&state, vec![albums[1].clone()], settings(out.path()))
+        .await
+        .unwrap();
+    let dropped = queue
+        .enqueue(&state, albums.clone(), settings(out.path()))
+        .await
+        .unwrap();
+    queue.move_job(second, 0).unwrap();
+    queue.cancel(dropped).unwrap();
+    assert!(matches!(queue.move_job(99, 0), Err(Error::JobNotFound(99))));
+
+    // Unfinished jobs are still there after a restart, in their order
+    drop(queue);
+    let queue = JobQueue::open(app_data.path()).unwrap();
+    let jobs = queue.list();
+    let order: Vec<_> = jobs.iter().map(|j| (j.id, j.status)).collect();
+    assert_eq!(
+        order,
+        [
+            (second, JobStatus::Queued),
+            (first, JobStatus::Queued),
+            (dropped, JobStatus::Cancelled),
+        ]
+    );
+    assert_eq!(jobs[0].kindergarten.as_ref().unwrap().id, 101);
+
+    let reporter = CollectingReporter::new();
+    assert_eq!(queue.run_next(&state, &reporter).await, Some(second));
+    assert_eq!(queue.run_next(&state, &reporter).await, Some(first));
+    assert_eq!(queue.run_next(&state, &reporter).await, None);
+
+    let events = reporter.into_events();
+    assert!(events.iter().all(|p| p.job_id.is_some()));
+    let downloaded: Vec<_> = events
+        .iter()
+        .filter(|p| p.status == "downloaded")
+        .map(|p| p.job_id.unwrap())
+        .collect();
+    assert_eq!(downloaded, [second, second, first, first, first]);
+    let jobs = JobQueue::open(app_data.path()).unwrap().list();
+    assert_eq!(jobs[0].status, JobStatus::Completed);
+    assert_eq!(jobs[0].result.as_ref().unwrap().total_images, 2);
+    assert_eq!(jobs[1].result.as_ref().unwrap().total_images, 3);
+    let newer = queue
+        .enqueue(&state, vec![albums[0].clone()], settings(out.path()))
+        .await
+        .unwrap();
+    assert!(newer > dropped, "ids are not reused");
+    queue.clear_finished().unwrap();
+    assert_eq!(queue.list().len(), 1);
+}
+
+#[tokio::test]
+async fn queued_jobs_keep_their_secrets_out_of_the_queue_file() {
+    let server = MockKidplan::sample().start().await;
+    let state = logged_in_state(&server).await;
+    let app_data = tempfile::tempdir().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let storage = StorageConfig::S3 {
+        endpoint: "http://127.0.0.1:9000".to_string(),
+        bucket: "photos".to_string(),
+        region: "us-east-1".to_string(),
+        access_key: "minio".to_string(),
+        secret_key: "s3-secret".to_string(),
+        prefix: String::new(),
+    };
+    let upload = UploadConfig::Immich {
+        url: "http://127.0.0.1:2283".to_string(),
+        api_key: "immich-key".to_string(),
+    };
+    let settings = DownloadSettings {
+        storage: storage.clone(),
+        upload: Some(upload.clone()),
+        ..settings(out.path())
+    };
+
+    let queue = JobQueue::open(app_data.path()).unwrap();
+    let id = queue.enqueue(&state, Vec::new(), settings).await.unwrap();
+    let saved = std::fs::read_to_string(app_data.path().join("queue.json")).unwrap();
+    assert!(saved.contains("minio"));
+    assert!(!saved.contains("s3-secret"));
+    assert!(!saved.contains("immich-key"));
+
+    // The job still has them after a restart
+    drop(queue);
+    let queue = JobQueue::open(app_data.path()).unwrap();
+    let job = queue.get(id).unwrap();
+    assert!(job.settings.storage == storage);
+    assert!(job.settings.upload == Some(upload));
+
+    // and lets go of them once it can't run any more
+    queue.cancel(id).unwrap();
+    assert!(!app_data.path().join("queue-secrets.bin").exists());
+}
+
+#[tokio::test]
+async fn downloads_through_the_queue_wait_for_their_job() {
+    let server = MockKidplan::sample().start().await;
+    let state = Arc::new(logged_in_state(&server).await);
+    let client = state.client.lock().await.clone().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let app_data = tempfile::tempdir().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let queue = Arc::new(JobQueue::open(app_data.path()).unwrap());
+    let reporter = Arc::new(CollectingReporter::new());
+    tokio::spawn({
+        let (state, queue, reporter) = (state.clone(), queue.clone(), reporter.clone());
+        async move { queue.run(&state, &*reporter).await }
+    });
+
+    let result = queue
+        .enqueue_and_wait(&state, albums, settings(out.path()))
+        .await
+        .unwrap();
+    assert_eq!(result.total_images, 5);
+    assert_eq!(queue.list()[0].status, JobStatus::Completed);
+    assert!(reporter.events().iter().all(|p| p.job_id == Some(1)));
+
+    // Syncs are jobs too, and find the albums downloaded already
+    let summary = queue.sync(&state, settings(out.path())).await.unwrap();
+    assert_eq!((summary.new_albums, summary.new_images), (2, 0));
+    assert_eq!(summary.result.skipped, 5);
+    let jobs = queue.list();
+    assert!(matches!(jobs[1].kind, JobKind::Sync));
+    assert_eq!(jobs[1].status, JobStatus::Completed);
+}
+
+#[tokio::test]
+async fn cancelling_a_running_job_leaves_the_next_one_running() {
+    let mut mock = MockKidplan::sample();
+    mock.image_delay = Some(Duration::from_millis(200));
+    let server = mock.start().await;
+    let state = Arc::new(logged_in_state(&server).await);
+    let client = state.client.lock().await.clone().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let app_data = tempfile::tempdir().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let queue = Arc::new(JobQueue::open(app_data.path()).unwrap());
+    let first = queue
+        .enqueue(&state, vec![albums[0].clone()], settings(out.path()))
+        .await
+        .unwrap();
+    tokio::spawn({
+        let (state, queue) = (state.clone(), queue.clone());
+        async move { queue.run(&state, &CollectingReporter::new()).await }
+    });
+    while server.image_requests() == 0 {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    queue.cancel(first).unwrap();
+
+    // The cancel belongs to the first job, so it does not stop the second
+    let result = queue
+        .enqueue_and_wait(&state, vec![albums[1].clone()], settings(out.path()))
+        .await
+        .unwrap();
+    assert!(!result.cancelled);
+    assert_eq!(result.total_images, 2);
+    let jobs = queue.list();
+    assert_eq!(jobs[0].status, JobStatus::Cancelled);
+    assert_eq!(jobs[1].status, JobStatus::Completed);
+    assert!(!state.controls.is_cancelled());
+}
+
+#[tokio::test]
+async fn cancelling_a_queued_sync_stops_listing_albums() {
+    let mut mock = MockKidplan::sample();
+    mock.album_list_delay = Some(Duration::from_secs(30));
+    let server = mock.start().await;
+    let state = Arc::new(logged_in_state(&server).await);
+    let app_data = tempfile::tempdir().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let queue = Arc::new(JobQueue::open(app_data.path()).unwrap());
+    tokio::spawn({
+        let (state, queue) = (state.clone(), queue.clone());
+        async move { queue.run(&state, &CollectingReporter::new()).await }
+    });
+
+    let sync = tokio::spawn({
+        let (state, queue) = (state.clone(), queue.clone());
+        let settings = settings(out.path());
+        async move { queue.sync(&state, settings).await }
+    });
+    while queue.list().first().map(|j| j.status) != Some(JobStatus::Running) {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    queue.cancel(queue.list()[0].id).unwrap();
+    let summary = tokio::time::timeout(Duration::from_secs(2), sync)
+        .await
+        .expect("cancel does not wait for the album list")
+        .unwrap()
+        .unwrap();
+    assert!(summary.result.cancelled);
+    assert_eq!(summary.new_albums, 0);
+    assert_eq!(queue.list()[0].status, JobStatus::Cancelled);
+}
+
+#[tokio::test]
+async fn queued_jobs_keep_their_kindergarten_when_the_app_switches() {
+    let mut mock = MockKidplan::sample();
+    mock.kindergartens.push((202, "Trollskogen".to_string()));
+    mock.kid_albums
+        .insert(202, vec![MockAlbum::new("t1", "Karneval", 2)]);
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    *state.credentials.lock().await = Some(server.credentials());
+    let client = state.client.lock().await.clone().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let app_data = tempfile::tempdir().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let queue = JobQueue::open(app_data.path()).unwrap();
+    queue
+        .enqueue(&state, albums, settings(out.path()))
+        .await
+        .unwrap();
+
+    // The app moves on to the other kindergarten before the job runs
+    downloader::login(&client, &state.endpoints, &server.credentials(), 202)
+        .await
+        .unwrap();
+    state.select_kindergarten(202).await;
+    queue
+        .run_next(&state, &CollectingReporter::new())
+        .await
+        .unwrap();
+    let job = &queue.list()[0];
+    assert_eq!(job.status, JobStatus::Completed);
+    assert_eq!(job.result.as_ref().unwrap().total_images, 5);
+
+    // and stays there
+    assert_eq!(state.kindergarten.lock().await.as_ref().unwrap().id, 202);
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    assert_eq!(albums[0].id, "t1");
+}
+
+#[tokio::test]
+async fn truncated_files_are_fetched_again() {
+    let server = MockKidplan::sample().start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+
+    let reporter = CollectingReporter::new();
+    downloader::download_albums(&state, albums.clone(), settings(out.path()), &reporter)
+        .await
+        .unwrap();
+
+    // Simulate a crash that left one file cut short and another missing
+    let album_dir = out.path().join("Sommerfest-2024");
+    std::fs::write(album_dir.join("id-a1-001.jpeg"), b"\xFF\xD8").unwrap();
+    std::fs::remove_file(album_dir.join("id-a1-002.jpeg")).unwrap();
+
+    let result = downloader::download_albums(&state, albums, settings(out.path()), &reporter)
+        .await
+        .unwrap();
+    assert_eq!(result.total_images, 2);
+    assert_eq!(result.skipped, 3);
+    assert_eq!(
+        std::fs::read(album_dir.join("id-a1-001.jpeg")).unwrap(),
+        support::fake_jpeg("a1/1")
+    );
+
+    let manifest = Manifest::open(out.path()).unwrap();
+    assert_eq!(manifest.entries().count(), 5);
+    assert!(manifest
+        .entries()
+        .all(|e| manifest.verify(e) == Verification::Ok));
+}
+
+#[tokio::test]
+async fn sync_only_fetches_new_and_changed_albums() {
+    let server = MockKidplan::sample().start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let reporter = CollectingReporter::new();
+    let album_pages = |server: &MockServer| {
+        server
+            .requests()
+            .iter()
+            .filter(|r| r.starts_with("/bilder/album/"))
+            .count()
+    };
+
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let first = sync::sync_albums(&state, &albums, settings(out.path()), &reporter)
+        .await
+        .unwrap();
+    assert_eq!((first.new_albums, first.new_images), (2, 5));
+
+    let pages_before = album_pages(&server);
+    let second = sync::sync_albums(&state, &albums, settings(out.path()), &reporter)
+        .await
+        .unwrap();
+    assert_eq!((second.new_albums, second.new_images), (0, 0));
+    assert_eq!(second.unchanged_albums, 2);
+    assert_eq!(album_pages(&server), pages_before);
+
+    server.set_albums(vec![
+        MockAlbum::new("a1", "Sommerfest 2024", 3),
+        MockAlbum::new("a2", "Tur til skogen", 3),
+        MockAlbum::new("a3", "Karneval", 4),
+    ]);
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let third = sync::sync_albums(&state, &albums, settings(out.path()), &reporter)
+        .await
+        .unwrap();
+    assert_eq!(third.new_albums, 1);
+    assert_eq!(third.changed_albums, 1);
+    assert_eq!(third.new_images, 5);
+    assert_eq!(third.to_string(), "1 new album, 5 new photos");
+    assert_eq!(album_pages(&server), pages_before + 2);
+}
+
+#[tokio::test]
+async fn albums_cut_short_by_the_limit_are_synced_again() {
+    let server = MockKidplan::sample().start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let reporter = CollectingReporter::new();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+
+    let limited = DownloadSettings {
+        limit_per_album: 2,
+        ..settings(out.path())
+    };
+    let first = sync::sync_albums(&state, &albums, limited, &reporter)
+        .await
+        .unwrap();
+    assert_eq!((first.new_albums, first.new_images), (2, 4));
+
+    // Only the album with three images is left to finish
+    let second = sync::sync_albums(&state, &albums, settings(out.path()), &reporter)
+        .await
+        .unwrap();
+    assert_eq!(second.changed_albums + second.new_albums, 1);
+    assert_eq!(second.unchanged_albums, 1);
+    assert_eq!(second.new_images, 1);
+}
+
+/// Sample albums where the second album re-posts the first photo of the first.
+fn albums_with_repost() -> Vec<MockAlbum> {
+    let first = MockAlbum::new("a1", "Sommerfest 2024", 3);
+    let mut second = MockAlbum::new("a2", "Tur til skogen", 2);
+    second.images[1].bytes = first.images[0].bytes.clone();
+    vec![first, second]
+}
+
+#[tokio::test]
+async fn duplicate_content_is_skipped_across_albums_and_runs() {
+    let mut mock = MockKidplan::sample();
+    mock.albums = albums_with_repost();
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+
+    let reporter = CollectingReporter::new();
+    let result = downloader::download_albums(&state, albums, settings(out.path()), &reporter)
+        .await
+        .unwrap();
+    assert_eq!(result.total_images, 4);
+    assert_eq!(result.duplicates, 1);
+    assert!(!out
+        .path()
+        .join("Tur-til-skogen")
+        .join("id-a2-002.jpeg")
+        .exists());
+    assert!(reporter
+        .events()
+        .iter()
+        .any(|p| p.status == "skipped: duplicate of Sommerfest-2024/id-a1-001.jpeg"));
+
+    // A later album re-uploading the same photo under a new id
+    let mut reupload = MockAlbum::new("a3", "Karneval", 1);
+    reupload.images[0].bytes = support::fake_jpeg("a1/1");
+    let mut albums = albums_with_repost();
+    albums.push(reupload);
+    server.set_albums(albums);
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let result = downloader::download_albums(&state, albums, settings(out.path()), &reporter)
+        .await
+        .unwrap();
+    assert_eq!(result.total_images, 0);
+    assert_eq!(result.skipped, 6);
+    assert_eq!(result.duplicates, 1);
+}
+
+#[tokio::test]
+async fn duplicates_are_found_by_downloaded_content_when_exif_is_written() {
+    let mut mock = MockKidplan::sample();
+    mock.albums = albums_with_repost();
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let settings = DownloadSettings {
+        write_exif: true,
+        ..settings(out.path())
+    };
+
+    let reporter = CollectingReporter::new();
+    downloader::download_albums(&state, vec![albums[0].clone()], settings.clone(), &reporter)
+        .await
+        .unwrap();
+    // The index is rebuilt from the manifest, whose files carry EXIF
+    std::fs::remove_file(out.path().join(manifest::STATE_DIR).join("hashes.json")).unwrap();
+    let result = downloader::download_albums(&state, vec![albums[1].clone()], settings, &reporter)
+        .await
+        .unwrap();
+    assert_eq!(result.duplicates, 1);
+    assert!(!out
+        .path()
+        .join("Tur-til-skogen")
+        .join("id-a2-002.jpeg")
+        .exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn duplicates_can_be_hardlinked_or_symlinked() {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut mock = MockKidplan::sample();
+    mock.albums = albums_with_repost();
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let reporter = CollectingReporter::new();
+
+    for mode in [DuplicateMode::Hardlink, DuplicateMode::Symlink] {
+        let out = tempfile::tempdir().unwrap();
+        let settings = DownloadSettings {
+            duplicates: mode,
+            ..settings(out.path())
+        };
+        let result = downloader::download_albums(&state, albums.clone(), settings, &reporter)
+            .await
+            .unwrap();
+        assert_eq!(result.duplicates, 1);
+
+        let original = out.path().join("Sommerfest-2024").join("id-a1-001.jpeg");
+        let copy = out.path().join("Tur-til-skogen").join("id-a2-002.jpeg");
+        assert_eq!(std::fs::read(&copy).unwrap(), support::fake_jpeg("a1/1"));
+        match mode {
+            DuplicateMode::Hardlink => assert_eq!(
+                std::fs::metadata(&copy).unwrap().ino(),
+                std::fs::metadata(&original).unwrap().ino()
+            ),
+            _ => assert_eq!(
+                std::fs::read_link(&copy).unwrap(),
+                std::path::Path::new("../Sommerfest-2024/id-a1-001.jpeg")
+            ),
+        }
+    }
+}
+
+#[tokio::test]
+async fn path_template_names_files_and_resolves_collisions() {
+    let mut mock = MockKidplan::sample();
+    mock.albums = vec![
+        MockAlbum::new("a1", "Tur/retur: skogen", 2),
+        MockAlbum::new("a2", "Sommerfest 2024", 1),
+    ];
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+
+    let named = DownloadSettings {
+        path_template: Some("{kindergarten}/{album_title}/{index:03} {image_id}.{ext}".to_string()),
+        ..settings(out.path())
+    };
+    let reporter = CollectingReporter::new();
+    downloader::download_albums(&state, albums.clone(), named, &reporter)
+        .await
+        .unwrap();
+    let file = out
+        .path()
+        .join("Solstråla barnehage")
+        .join("Tur_retur_ skogen")
+        .join("002 a1-002.jpeg");
+    assert_eq!(std::fs::read(file).unwrap(), support::fake_jpeg("a1/2"));
+
+    // Every image renders to the same name
+    let out = tempfile::tempdir().unwrap();
+    let flat = DownloadSettings {
+        path_template: Some("flat/photo.{ext}".to_string()),
+        ..settings(out.path())
+    };
+    let result = downloader::download_albums(&state, albums.clone(), flat.clone(), &reporter)
+        .await
+        .unwrap();
+    assert_eq!(result.total_images, 3);
+    for name in ["photo.jpeg", "photo (2).jpeg", "photo (3).jpeg"] {
+        assert!(out.path().join("flat").join(name).exists(), "{}", name);
+    }
+    let result = downloader::download_albums(&state, albums, flat, &reporter)
+        .await
+        .unwrap();
+    assert_eq!((result.total_images, result.skipped), (0, 3));
+    let images = std::fs::read_dir(out.path().join("flat"))
+        .unwrap()
+        .filter(|e| {
+            e.as_ref()
+                .unwrap()
+                .path()
+                .extension()
+                .is_some_and(|x| x == "jpeg")
+        })
+        .count();
+    assert_eq!(images, 3);
+}
+
+#[tokio::test]
+async fn invalid_templates_are_rejected_before_downloading() {
+    let server = MockKidplan::sample().start().await;
+    let state = logged_in_state(&server).await;
+    let out = tempfile::tempdir().unwrap();
+
+    for template in [
+        "../{album_title}",
+        "/abs/{index}",
+        "{nope}",
+        "{album_title:03}",
+        "a\\b",
+    ] {
+        let settings = DownloadSettings {
+            path_template: Some(template.to_string()),
+            ..settings(out.path())
+        };
+        let result =
+            downloader::download_albums(&state, vec![], settings, &CollectingReporter::new()).await;
+        assert!(
+            matches!(result, Err(Error::InvalidTemplate(_))),
+            "{} was accepted",
+            template
+        );
+    }
+    assert_eq!(server.image_requests(), 0);
+
+    let preview = naming::preview("{album_id}/{index:02}.{ext}", &[], Some("Solstråla")).unwrap();
+    assert_eq!(preview, ["12345/01.jpeg", "12345/02.jpeg"]);
+}
+
+#[tokio::test]
+async fn exif_carries_album_date_title_and_kindergarten() {
+    let mut mock = MockKidplan::sample();
+    mock.albums[0].date = Some("2024-06-21T10:30:00".to_string());
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    assert_eq!(
+        albums[0].date.map(|d| d.to_string()),
+        Some("2024-06-21 10:30:00".to_string())
+    );
+    assert_eq!(albums[1].date, None);
+
+    let settings = DownloadSettings {
+        write_exif: true,
+        ..settings(out.path())
+    };
+    let reporter = CollectingReporter::new();
+    downloader::download_albums(&state, albums, settings, &reporter)
+        .await
+        .unwrap();
+
+    let read_exif = |path: std::path::PathBuf| {
+        let bytes = std::fs::read(path).unwrap();
+        exif::Reader::new()
+            .read_from_container(&mut std::io::Cursor::new(bytes))
+            .unwrap()
+    };
+    let text = |exif: &exif::Exif, tag| match &exif.get_field(tag, exif::In::PRIMARY)?.value {
+        exif::Value::Ascii(parts) => String::from_utf8(parts[0].clone()).ok(),
+        _ => None,
+    };
+    let dated = read_exif(out.path().join("Sommerfest-2024").join("id-a1-001.jpeg"));
+    assert_eq!(
+        text(&dated, exif::Tag::DateTimeOriginal).as_deref(),
+        Some("2024:06:21 10:30:00")
+    );
+    assert_eq!(
+        text(&dated, exif::Tag::ImageDescription).as_deref(),
+        Some("Sommerfest 2024")
+    );
+    assert_eq!(
+        text(&dated, exif::Tag::Artist).as_deref(),
+        Some("Solstråla barnehage")
+    );
+
+    // No date from Kidplan means no DateTimeOriginal, never the download time
+    let undated = read_exif(out.path().join("Tur-til-skogen").join("id-a2-001.jpeg"));
+    assert_eq!(text(&undated, exif::Tag::DateTimeOriginal), None);
+
+    let manifest = Manifest::open(out.path()).unwrap();
+    assert!(manifest
+        .entries()
+        .all(|e| manifest.verify(e) == Verification::Ok));
+}
+
+#[tokio::test]
+async fn xmp_sidecars_describe_each_image_without_touching_it() {
+    let mut mock = MockKidplan::sample();
+    mock.albums[0].images[0].caption = Some("Kake & is <3".to_string());
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+
+    let settings = DownloadSettings {
+        write_xmp: true,
+        ..settings(out.path())
+    };
+    let reporter = CollectingReporter::new();
+    downloader::download_albums(&state, albums, settings, &reporter)
+        .await
+        .unwrap();
+
+    let image = out.path().join("Sommerfest-2024").join("id-a1-001.jpeg");
+    assert_eq!(std::fs::read(&image).unwrap(), support::fake_jpeg("a1/1"));
+    let xmp = std::fs::read_to_string(
+        out.path()
+            .join("Sommerfest-2024")
+            .join("id-a1-001.jpeg.xmp"),
+    )
+    .unwrap();
+    for expected in [
+        "<xmpDM:album>Sommerfest 2024</xmpDM:album>",
+        "<kidplan:Kindergarten>Solstråla barnehage</kidplan:Kindergarten>",
+        "<kidplan:AlbumId>a1</kidplan:AlbumId>",
+        "<kidplan:ImageId>a1-001.jpeg</kidplan:ImageId>",
+        &format!(
+            "<dc:source>{}/albumpicture/?id=a1-001.jpeg</dc:source>",
+            server.base_url()
+        ),
+        "<rdf:li xml:lang=\"x-default\">Kake &amp; is &lt;3</rdf:li>",
+        "<rdf:li>Kidplan|Solstråla barnehage|Sommerfest 2024</rdf:li>",
+    ] {
+        assert!(xmp.contains(expected), "missing {} in\n{}", expected, xmp);
+    }
+
+    let uncaptioned =
+        std::fs::read_to_string(out.path().join("Tur-til-skogen").join("id-a2-001.jpeg.xmp"))
+            .unwrap();
+    assert!(!uncaptioned.contains("dc:description"));
+}
+
+#[tokio::test]
+async fn album_folders_get_an_album_json() {
+    let server = MockKidplan::sample().start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let reporter = CollectingReporter::new();
+    downloader::download_albums(&state, albums.clone(), settings(out.path()), &reporter)
+        .await
+        .unwrap();
+
+    let read = |path: std::path::PathBuf| -> AlbumManifest {
+        serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+    };
+    let album = read(out.path().join("Sommerfest-2024").join("album.json"));
+    assert_eq!(album.album_id, "a1");
+    assert_eq!(album.title, "Sommerfest 2024");
+    assert_eq!(album.url, albums[0].url);
+    assert_eq!(album.kindergarten.as_deref(), Some("Solstråla barnehage"));
+    assert_eq!(album.image_count, Some(3));
+    assert_eq!(album.images.len(), 3);
+    let first = &album.images[0];
+    assert_eq!(first.image_id, "a1-001.jpeg");
+    assert_eq!(first.file, "id-a1-001.jpeg");
+    assert_eq!(first.path, "Sommerfest-2024/id-a1-001.jpeg");
+    assert_eq!(first.size, support::fake_jpeg("a1/1").len() as u64);
+    assert_eq!(
+        first.sha256.as_deref(),
+        Some(manifest::sha256_hex(&support::fake_jpeg("a1/1")).as_str())
+    );
+
+    // Albums sharing a folder each get their own file
+    let out = tempfile::tempdir().unwrap();
+    let flat = DownloadSettings {
+        path_template: Some("all/{album_id}-{index}.{ext}".to_string()),
+        ..settings(out.path())
+    };
+    downloader::download_albums(&state, albums, flat, &reporter)
+        .await
+        .unwrap();
+    assert_eq!(
+        read(out.path().join("all").join("album.json")).album_id,
+        "a1"
+    );
+    assert_eq!(
+        read(out.path().join("all").join("album-a2.json"))
+            .images
+            .len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn several_kindergartens_download_in_one_job() {
+    let mut mock = MockKidplan::sample();
+    mock.kindergartens.push((202, "Trollskogen".to_string()));
+    mock.kid_albums
+        .insert(202, vec![MockAlbum::new("t1", "Karneval", 2)]);
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let credentials = server.credentials();
+    let out = tempfile::tempdir().unwrap();
+
+    let mut groups = kindergartens::fetch_albums(&state, &credentials, &[101, 202])
+        .await
+        .unwrap();
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].kindergarten.name, "Solstråla barnehage");
+    assert_eq!(groups[0].albums.len(), 2);
+    assert_eq!(groups[1].kindergarten.name, "Trollskogen");
+    assert_eq!(groups[1].albums[0].id, "t1");
+    groups[0].albums.truncate(1);
+
+    let reporter = CollectingReporter::new();
+    let result = kindergartens::download_kindergartens(
+        &state,
+        &credentials,
+        groups,
+        settings(out.path()),
+        &reporter,
+    )
+    .await
+    .unwrap();
+    assert_eq!(result.total_albums, 2);
+    assert_eq!(result.total_images, 5);
+    assert_eq!(result.failed, 0);
+    assert!(out
+        .path()
+        .join("Solstråla barnehage/Sommerfest-2024/id-a1-001.jpeg")
+        .exists());
+    assert!(out
+        .path()
+        .join("Trollskogen/Karneval/id-t1-002.jpeg")
+        .exists());
+    assert!(!out.path().join("Solstråla barnehage/Karneval").exists());
+
+    // Progress reads as one job
+    let events = reporter.into_events();
+    assert!(events.iter().all(|e| e.album_total == 2));
+    let last = events.last().unwrap();
+    assert_eq!(last.album_index, 2);
+    assert_eq!(last.album_title, "Trollskogen: Karneval");
+
+    // The session is back on the kindergarten chosen at login
+    assert_eq!(state.kindergarten.lock().await.as_ref().unwrap().id, 101);
+    let client = state.client.lock().await.clone().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    assert_eq!(albums[0].id, "a1");
+}
+
+#[tokio::test]
+async fn session_survives_a_restart_until_it_expires_or_logs_out() {
+    let server = MockKidplan::sample().start().await;
+    let state = logged_in_state(&server).await;
+    let data_dir = tempfile::tempdir().unwrap();
+    let store = SessionStore::new(data_dir.path());
+    store.save(&state).await.unwrap();
+
+    // The cookie value is not readable from disk
+    let saved = std::fs::read(data_dir.path().join("session.bin")).unwrap();
+    assert!(!String::from_utf8_lossy(&saved).contains("session-"));
+
+    // A fresh start picks the session up without logging in
+    let restarted = AppState::with_endpoints(server.endpoints());
+    let logins = server
+        .requests()
+        .iter()
+        .filter(|r| r.starts_with("/LogOn"))
+        .count();
+    let restored = session::restore(&restarted, &store).await.unwrap().unwrap();
+    assert_eq!(restored.kindergarten.unwrap().name, "Solstråla barnehage");
+    let client = restarted.client.lock().await.clone().unwrap();
+    let albums = downloader::fetch_albums(&client, &restarted.endpoints)
+        .await
+        .unwrap();
+    assert_eq!(albums.len(), 2);
+    assert_eq!(
+        server
+            .requests()
+            .iter()
+            .filter(|r| r.starts_with("/LogOn"))
+            .count(),
+        logins
+    );
+
+    // Logging out forgets it in memory and on disk
+    session::logout(&restarted, &store).await.unwrap();
+    assert!(restarted.client.lock().await.is_none());
+    let old_client = downloader::build_client(restarted.cookie_jar.clone());
+    let err = downloader::fetch_albums(&old_client, &restarted.endpoints)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::SessionExpired);
+    let again = AppState::with_endpoints(server.endpoints());
+    assert!(session::restore(&again, &store).await.unwrap().is_none());
+
+    // An expired session is dropped after the probe instead of restored
+    store.save(&state).await.unwrap();
+    server.expire_sessions();
+    let expired = AppState::with_endpoints(server.endpoints());
+    assert!(session::restore(&expired, &store).await.unwrap().is_none());
+    assert!(expired.client.lock().await.is_none());
+    assert!(!data_dir.path().join("session.bin").exists());
+}
+
+#[test]
+fn schedules_parse_and_catch_up_on_missed_runs() {
+    use chrono::{Local, NaiveTime, TimeZone};
+    let at = |h, m| Local.with_ymd_and_hms(2024, 3, 10, h, m, 0).unwrap();
+    let two = NaiveTime::from_hms_opt(2, 0, 0).unwrap();
+    assert_eq!("02:00".parse(), Ok(Schedule::Daily { at: two }));
+    assert_eq!("90m".parse(), Ok(Schedule::Every { minutes: 90 }));
+    assert_eq!("6h".parse(), Ok(Schedule::Every { minutes: 360 }));
+    assert!("0m".parse::<Schedule>().is_err());
+    assert!("nightly".parse::<Schedule>().is_err());
+
+    let daily = Schedule::Daily { at: two };
+    // A new schedule waits for its time; a missed one runs right away
+    assert_eq!(
+        daily.next_run(None, at(12, 0)),
+        at(2, 0) + chrono::Duration::days(1)
+    );
+    assert_eq!(daily.next_run(None, at(1, 0)), at(2, 0));
+    let last_night = at(2, 0) - chrono::Duration::days(1);
+    assert_eq!(daily.next_run(Some(last_night), at(12, 0)), at(12, 0));
+    assert_eq!(
+        daily.next_run(Some(at(2, 0)), at(12, 0)),
+        at(2, 0) + chrono::Duration::days(1)
+    );
+
+    let hourly = Schedule::Every { minutes: 60 };
+    assert_eq!(hourly.next_run(Some(at(10, 0)), at(10, 30)), at(11, 0));
+    assert_eq!(hourly.next_run(Some(at(6, 0)), at(10, 30)), at(10, 30));
+}
+
+#[tokio::test]
+async fn scheduled_syncs_record_each_run_and_its_failure() {
+    let server = MockKidplan::sample().start().await;
+    let state = Arc::new(logged_in_state(&server).await);
+    let out = tempfile::tempdir().unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    let queue = Arc::new(JobQueue::open(data_dir.path()).unwrap());
+    tokio::spawn({
+        let (state, queue) = (state.clone(), queue.clone());
+        async move { queue.run(&state, &CollectingReporter::new()).await }
+    });
+
+    let scheduler = Scheduler::open(data_dir.path()).unwrap();
+    assert!(scheduler.run_now(&state, &queue, None).await.is_none());
+    scheduler
+        .configure(Some(ScheduleConfig {
+            enabled: true,
+            schedule: "02:00".parse().unwrap(),
+            kid_ids: Vec::new(),
+            settings: settings(out.path()),
+        }))
+        .unwrap();
+    assert!(scheduler.next_run().is_some());
+
+    let first = scheduler.run_now(&state, &queue, None).await.unwrap();
+    assert!(first.error.is_none());
+    assert_eq!((first.new_albums, first.new_images), (2, 5));
+    let second = scheduler.run_now(&state, &queue, None).await.unwrap();
+    assert_eq!((second.new_albums, second.new_images), (0, 0));
+    // Each run is a sync job of the queue
+    let jobs = queue.list();
+    assert_eq!(jobs.len(), 2);
+    assert!(jobs
+        .iter()
+        .all(|j| matches!(j.kind, JobKind::Sync) && j.status == JobStatus::Completed));
+
+    // Kidplan dropped the session and there is no password to log in with
+    server.expire_sessions();
+    *state.credentials.lock().await = None;
+    let failed = scheduler.run_now(&state, &queue, None).await.unwrap();
+    assert_eq!(failed.error.unwrap().code, ErrorCode::SessionExpired);
+    assert_eq!(queue.list()[2].status, JobStatus::Failed);
+
+    // The schedule and its history survive a restart
+    let reopened = Scheduler::open(data_dir.path()).unwrap();
+    assert_eq!(
+        reopened.config().unwrap().schedule,
+        Schedule::Daily {
+            at: chrono::NaiveTime::from_hms_opt(2, 0, 0).unwrap()
+        }
+    );
+    let runs = reopened.runs();
+    assert_eq!(runs.len(), 3);
+    assert_eq!(runs[0].new_images, 5);
+    assert!(runs[2].error.is_some());
+}
+
+#[test]
+fn schedules_keep_their_secrets_out_of_the_config_file() {
+    let data_dir = tempfile::tempdir().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let storage = StorageConfig::WebDav {
+        url: "https://cloud.example.com/remote.php/dav/files/anna/Kidplan".to_string(),
+        username: Some("anna".to_string()),
+        password: Some("dav-password".to_string()),
+    };
+    let upload = UploadConfig::PhotoPrism {
+        url: "http://127.0.0.1:2342".to_string(),
+        token: "photoprism-token".to_string(),
+    };
+    let config = ScheduleConfig {
+        enabled: true,
+        schedule: "02:00".parse().unwrap(),
+        kid_ids: Vec::new(),
+        settings: DownloadSettings {
+            storage: storage.clone(),
+            upload: Some(upload.clone()),
+            ..settings(out.path())
+        },
+    };
+    Scheduler::open(data_dir.path())
+        .unwrap()
+        .configure(Some(config))
+        .unwrap();
+    let saved = std::fs::read_to_string(data_dir.path().join("schedule.json")).unwrap();
+    assert!(saved.contains("anna"));
+    assert!(!saved.contains("dav-password"));
+    assert!(!saved.contains("photoprism-token"));
+
+    let reopened = Scheduler::open(data_dir.path()).unwrap();
+    let settings = reopened.config().unwrap().settings;
+    assert!(settings.storage == storage);
+    assert!(settings.upload == Some(upload));
+    reopened.configure(None).unwrap();
+    assert!(!data_dir.path().join("schedule-secrets.bin").exists());
+}
+
+/// Names and contents of the files in a ZIP archive.
+fn zip_contents(path: &std::path::Path) -> Vec<(String, Vec<u8>)> {
+    use std::io::Read;
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path).unwrap()).unwrap();
+    (0..archive.len())
+        .map(|i| {
+            let mut file = archive.by_index(i).unwrap();
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes).unwrap();
+            (file.name().to_string(), bytes)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn albums_are_zipped_after_download_or_on_export() {
+    let server = MockKidplan::sample().start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+
+    // One archive per album, packed once the download is done
+    let reporter = CollectingReporter::new();
+    let zipped = DownloadSettings {
+        zip: Some(ZipSettings::default()),
+        ..settings(out.path())
+    };
+    downloader::download_albums(&state, albums.clone(), zipped, &reporter)
+        .await
+        .unwrap();
+    let summer = zip_contents(&out.path().join("Sommerfest 2024.zip"));
+    assert_eq!(
+        summer
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>(),
+        ["id-a1-001.jpeg", "id-a1-002.jpeg", "id-a1-003.jpeg"]
+    );
+    assert_eq!(summer[0].1, support::fake_jpeg("a1/1"));
+    assert_eq!(
+        zip_contents(&out.path().join("Tur til skogen.zip")).len(),
+        2
+    );
+    let events = reporter.into_events();
+    let zipping: Vec<_> = events.iter().filter(|e| e.status == "zipping").collect();
+    assert_eq!(zipping.len(), 5);
+    assert_eq!((zipping[0].image_index, zipping[0].image_total), (1, 3));
+    assert_eq!(events.iter().filter(|e| e.status == "zipped").count(), 2);
+
+    // Exported later into one compressed archive, a folder per album
+    let dest = tempfile::tempdir().unwrap();
+    let manifest = Manifest::open(out.path()).unwrap();
+    let combined = ZipSettings {
+        level: 9,
+        layout: ZipLayout::Combined,
+    };
+    let archives = export::zip_albums(
+        &manifest,
+        &albums,
+        dest.path(),
+        &combined,
+        &CollectingReporter::new(),
+        &state.controls.cancellation(),
+    )
+    .unwrap();
+    assert_eq!(archives.len(), 1);
+    let all = zip_contents(&archives[0]);
+    assert_eq!(all.len(), 5);
+    assert_eq!(all[3].0, "Tur til skogen/id-a2-001.jpeg");
+    assert_eq!(all[3].1, support::fake_jpeg("a2/1"));
+
+    // A plain folder, without the downloader's bookkeeping
+    let archive = dest.path().join("folder.zip");
+    export::zip_directory(
+        out.path(),
+        &archive,
+        &ZipSettings::default(),
+        &CollectingReporter::new(),
+        &state.controls.cancellation(),
+    )
+    .unwrap();
+    let names: Vec<String> = zip_contents(&archive).into_iter().map(|(n, _)| n).collect();
+    assert!(names.contains(&"Sommerfest-2024/id-a1-001.jpeg".to_string()));
+    assert!(names.contains(&"Sommerfest 2024.zip".to_string()));
+    assert!(!names.iter().any(|n| n.starts_with(".kidplan")));
+
+    // A cancelled export leaves no archive behind
+    state.controls.cancel();
+    let err = export::zip_albums(
+        &manifest,
+        &albums,
+        &dest.path().join("cancelled"),
+        &ZipSettings::default(),
+        &CollectingReporter::new(),
+        &state.controls.cancellation(),
+    )
+    .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::Cancelled);
+    assert_eq!(
+        std::fs::read_dir(dest.path().join("cancelled"))
+            .unwrap()
+            .count(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn downloads_keep_their_counts_when_zipping_fails() {
+    let server = MockKidplan::sample().start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    // In the way of the archive being written
+    std::fs::create_dir_all(out.path().join("Sommerfest 2024.zip.part")).unwrap();
+
+    let zipped = DownloadSettings {
+        zip: Some(ZipSettings::default()),
+        ..settings(out.path())
+    };
+    let result = downloader::download_albums(&state, albums, zipped, &CollectingReporter::new())
+        .await
+        .unwrap();
+    assert_eq!(result.total_images, 5);
+    assert!(!result.cancelled);
+    assert!(result
+        .export_error
+        .as_deref()
+        .is_some_and(|e| e.contains("Sommerfest 2024.zip.part")));
+    assert!(!out.path().join("Sommerfest 2024.zip").exists());
+}
+
+#[tokio::test]
+async fn images_are_stored_on_webdav_and_s3_without_local_copies() {
+    let mut mock = MockKidplan::sample();
+    mock.albums = albums_with_repost();
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let reporter = CollectingReporter::new();
+
+    let dav = MockWebDav::new();
+    let dav_server = dav.clone().start().await;
+    let s3 = MockS3 {
+        // Two objects per page, so listing follows continuation tokens
+        page_size: 2,
+        ..MockS3::new()
+    };
+    let s3_server = s3.clone().start().await;
+    let backends = [
+        (
+            StorageConfig::WebDav {
+                url: dav_server.dav_url(),
+                username: Some(dav.username.clone()),
+                password: Some(dav.password.clone()),
+            },
+            &dav_server,
+            "",
+        ),
+        (
+            StorageConfig::S3 {
+                endpoint: s3_server.base_url(),
+                bucket: s3.bucket.clone(),
+                region: "us-east-1".to_string(),
+                access_key: s3.access_key.clone(),
+                secret_key: s3.secret_key.clone(),
+                prefix: "family/".to_string(),
+            },
+            &s3_server,
+            "family/",
+        ),
+    ];
+
+    for (storage, remote, prefix) in backends {
+        let out = tempfile::tempdir().unwrap();
+        let settings = DownloadSettings {
+            write_xmp: true,
+            storage: storage.clone(),
+            ..settings(out.path())
+        };
+        let images_before = server.image_requests();
+        let result =
+            downloader::download_albums(&state, albums.clone(), settings.clone(), &reporter)
+                .await
+                .unwrap();
+        assert_eq!(result.total_images, 4, "{:?}", storage);
+        assert_eq!(result.duplicates, 1);
+
+        let files = remote.files();
+        let file = |path: &str| files.get(&format!("{}{}", prefix, path));
+        // The repost is recognised from the listing and not stored twice
+        assert!(file("Tur-til-skogen/id-a2-002.jpeg").is_none());
+        assert_eq!(
+            file("Sommerfest-2024/id-a1-001.jpeg"),
+            Some(&support::fake_jpeg("a1/1"))
+        );
+        assert!(file("Sommerfest-2024/id-a1-003.jpeg.xmp").is_some());
+        assert!(file("Sommerfest-2024/album.json").is_some());
+        assert_eq!(files.len(), 4 + 4 + 2);
+        assert!(files.keys().all(|k| !k.ends_with(".part")));
+        // Only the bookkeeping stays local
+        assert!(!out
+            .path()
+            .join("Sommerfest-2024")
+            .join("id-a1-001.jpeg")
+            .exists());
+
+        let stored = storage.open(out.path()).unwrap().list().await.unwrap();
+        assert_eq!(stored.len(), files.len());
+        assert!(stored.contains_key("Sommerfest-2024/id-a1-001.jpeg"));
+
+        // What is stored is found again, without fetching any image
+        let result =
+            downloader::download_albums(&state, albums.clone(), settings.clone(), &reporter)
+                .await
+                .unwrap();
+        assert_eq!(result.total_images, 0);
+        assert_eq!(result.skipped, 5);
+        assert_eq!(server.image_requests(), images_before + 5);
+
+        // Files already in the storage but unknown to this library are
+        // adopted rather than uploaded again. Their content is not fetched,
+        // so the repost is kept as a copy this time
+        let fresh = tempfile::tempdir().unwrap();
+        let result = downloader::download_albums(
+            &state,
+            albums.clone(),
+            DownloadSettings {
+                out_dir: fresh.path().to_string_lossy().to_string(),
+                ..settings
+            },
+            &reporter,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.total_images, 1);
+        assert_eq!(result.skipped, 4);
+        assert_eq!(server.image_requests(), images_before + 6);
+        assert_eq!(
+            remote
+                .files()
+                .get(&format!("{}Tur-til-skogen/id-a2-002.jpeg", prefix)),
+            Some(&support::fake_jpeg("a1/1"))
+        );
+    }
+
+    assert!(dav_server.requests().iter().any(|r| r.starts_with("MOVE ")));
+    let wrong_password = StorageConfig::WebDav {
+        url: dav_server.dav_url(),
+        username: Some(dav.username.clone()),
+        password: Some("wrong".to_string()),
+    };
+    let err = wrong_password
+        .open(std::path::Path::new("."))
+        .unwrap()
+        .list()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::HttpStatus { .. }), "{:?}", err);
+}
+
+#[tokio::test]
+async fn saved_images_are_pushed_into_immich_or_photoprism_albums() {
+    let mut mock = MockKidplan::sample();
+    mock.albums = albums_with_repost();
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+
+    for immich in [true, false] {
+        let library = if immich {
+            MockPhotoLibrary::immich().await
+        } else {
+            MockPhotoLibrary::photoprism().await
+        };
+        let config = if immich {
+            UploadConfig::Immich {
+                url: library.url(),
+                api_key: LIBRARY_KEY.to_string(),
+            }
+        } else {
+            UploadConfig::PhotoPrism {
+                url: library.url(),
+                token: LIBRARY_KEY.to_string(),
+            }
+        };
+        let out = tempfile::tempdir().unwrap();
+        let first = DownloadSettings {
+            upload: Some(config.clone()),
+            ..settings(out.path())
+        };
+        let reporter = CollectingReporter::new();
+        let result = downloader::download_albums(&state, albums.clone(), first, &reporter)
+            .await
+            .unwrap();
+        assert_eq!(result.total_images, 4);
+        assert_eq!(result.duplicates, 1);
+        assert_eq!(result.uploaded, 4);
+        assert_eq!(result.failed, 0);
+
+        // The repost is sent once but ends up in both albums
+        assert_eq!(library.assets().len(), 4);
+        let library_albums = library.albums();
+        assert_eq!(
+            library_albums["Sommerfest 2024"],
+            ["id-a1-001.jpeg", "id-a1-002.jpeg", "id-a1-003.jpeg"]
+        );
+        assert_eq!(
+            library_albums["Tur til skogen"],
+            ["id-a1-001.jpeg", "id-a2-001.jpeg"]
+        );
+        if immich {
+            assert!(library
+                .assets()
+                .iter()
+                .all(|a| a.device_asset_id.contains('/')));
+        }
+        assert!(reporter
+            .events()
+            .iter()
+            .any(|p| p.status.starts_with("uploaded: 3 new, 0 already in")));
+
+        // Another library downloading the same photos sends nothing new
+        let uploads = library.uploads();
+        let again = tempfile::tempdir().unwrap();
+        let second = DownloadSettings {
+            upload: Some(config),
+            ..settings(again.path())
+        };
+        let result = downloader::download_albums(&state, albums.clone(), second, &reporter)
+            .await
+            .unwrap();
+        assert_eq!(result.total_images, 4);
+        assert_eq!(result.uploaded, 0);
+        assert_eq!(library.uploads(), uploads);
+        assert_eq!(library.assets().len(), 4);
+        assert_eq!(library.albums(), library_albums);
+    }
+
+    // A rejected key fails the upload but not the download
+    let library = MockPhotoLibrary::immich().await;
+    let out = tempfile::tempdir().unwrap();
+    let settings = DownloadSettings {
+        upload: Some(UploadConfig::Immich {
+            url: library.url(),
+            api_key: "wrong".to_string(),
+        }),
+        ..settings(out.path())
+    };
+    let reporter = CollectingReporter::new();
+    let result = downloader::download_albums(&state, albums, settings, &reporter)
+        .await
+        .unwrap();
+    assert_eq!(result.total_images, 4);
+    assert_eq!(result.uploaded, 0);
+    let failures: Vec<_> = reporter
+        .events()
+        .into_iter()
+        .filter(|p| p.status.starts_with("failed: upload to Immich"))
+        .collect();
+    assert_eq!(failures.len(), 5);
+    assert!(failures
+        .iter()
+        .all(|p| p.error_type == Some(ErrorCode::HttpStatus)));
+    assert!(library.assets().is_empty());
+}
+
+/// The `href` and `src` values of a gallery page.
+fn page_links(html: &str) -> Vec<String> {
+    regex::Regex::new(r#"(?:href|src)="([^"]*)""#)
+        .unwrap()
+        .captures_iter(html)
+        .map(|c| c[1].to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn downloaded_albums_get_a_static_gallery() {
+    let mut mock = MockKidplan::sample();
+    mock.kindergartens.push((202, "Trollskogen".to_string()));
+    mock.kid_albums
+        .insert(202, vec![MockAlbum::new("t1", "Mat & moro", 2)]);
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let credentials = server.credentials();
+    let out = tempfile::tempdir().unwrap();
+    let groups = kindergartens::fetch_albums(&state, &credentials, &[101, 202])
+        .await
+        .unwrap();
+    kindergartens::download_kindergartens(
+        &state,
+        &credentials,
+        groups,
+        settings(out.path()),
+        &CollectingReporter::new(),
+    )
+    .await
+    .unwrap();
+
+    let written = gallery::write_gallery(out.path(), GalleryLanguage::En).unwrap();
+    assert_eq!(written.index, out.path().join("index.html"));
+    assert_eq!(written.albums, 3);
+    assert_eq!(written.images, 7);
+    let index = std::fs::read_to_string(&written.index).unwrap();
+    assert!(index.contains("<h2>Solstråla barnehage</h2>"));
+    assert!(index.contains("<h2>Trollskogen</h2>"));
+    assert!(index.contains("Mat &amp; moro"));
+    assert!(index.contains("3 photos"));
+    let album = std::fs::read_to_string(out.path().join("gallery/a1.html")).unwrap();
+    assert!(
+        album.contains(r#"href="../Solstr%C3%A5la%20barnehage/Sommerfest-2024/id-a1-001.jpeg""#)
+    );
+    assert!(album.contains(r#"id="lightbox""#));
+
+    // Every page stands alone: no network, and every link leads to a file
+    let mut pages: Vec<_> = std::fs::read_dir(out.path().join("gallery"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    assert_eq!(pages.len(), 3);
+    pages.push(written.index.clone());
+    for page in &pages {
+        let html = std::fs::read_to_string(page).unwrap();
+        assert!(!html.contains("http://") && !html.contains("https://"));
+        for link in page_links(&html) {
+            let target =
+                link.split('/')
+                    .fold(page.parent().unwrap().to_path_buf(), |path, part| {
+                        if part == ".." {
+                            path.parent().unwrap().to_path_buf()
+                        } else {
+                            path.join(urlencoding::decode(part).unwrap().as_ref())
+                        }
+                    });
+            assert!(target.is_file(), "{:?} links to {}", page, link);
+        }
+    }
+
+    // Rewriting follows the library and drops pages of albums gone since
+    std::fs::remove_dir_all(out.path().join("Trollskogen")).unwrap();
+    let written = gallery::write_gallery(out.path(), GalleryLanguage::No).unwrap();
+    assert_eq!(written.albums, 2);
+    assert_eq!(written.images, 5);
+    assert!(!out.path().join("gallery/t1.html").exists());
+    let index = std::fs::read_to_string(&written.index).unwrap();
+    assert!(index.contains(r#"<html lang="no">"#));
+    assert!(index.contains("<h1>Solstråla barnehage</h1>"));
+    assert!(!index.contains("<h2>"));
+    assert!(index.contains("3 bilder"));
+}
+
+#[tokio::test]
+async fn thumbnails_are_cached_by_content_and_follow_changes() {
+    use image::ImageFormat;
+    let landscape = support::photo(ImageFormat::Jpeg, 800, 600);
+    let mut mock = MockKidplan::sample();
+    mock.albums[0].images[0].bytes = landscape.clone();
+    mock.albums[0].images[1].bytes = support::photo(ImageFormat::Png, 300, 900);
+    mock.albums[0].images[2].bytes = support::photo(ImageFormat::WebP, 640, 480);
+    mock.albums[1].images[1].bytes = support::photo(ImageFormat::Png, 100, 100);
+    let server = mock.start().await;
+    let state = logged_in_state(&server).await;
+    let client = state.client.lock().await.clone().unwrap();
+    let albums = downloader::fetch_albums(&client, &state.endpoints)
+        .await
+        .unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let root = out.path();
+    downloader::download_albums(&state, albums, settings(root), &CollectingReporter::new())
+        .await
+        .unwrap();
+    let find =
+        |album: &str, image: Option<&str>, size: u32| thumbnails::find(root, album, image, size);
+    let dimensions = |path: &std::path::Path| image::image_dimensions(path).unwrap();
+
+    let first = find("a1", Some("a1-001.jpeg"), 320).unwrap().unwrap();
+    assert_eq!(
+        first,
+        root.join(".kidplan/thumbnails")
+            .join(format!("{}-320.jpg", manifest::sha256_hex(&landscape)))
+    );
+    assert_eq!(dimensions(&first), (320, 240));
+    let (width, height) = dimensions(&find("a1", Some("a1-002.jpeg"), 320).unwrap().unwrap());
+    assert_eq!(height, 320);
+    assert!(width < 110);
+    let wide = find("a1", Some("a1-003.jpeg"), 320).unwrap().unwrap();
+    assert_eq!(dimensions(&wide), (320, 240));
+    let small = find("a1", Some("a1-001.jpeg"), 64).unwrap().unwrap();
+    assert_ne!(small, first);
+    assert_eq!(dimensions(&small), (64, 48));
+
+    // Albums show their first image, passing over what can't be decoded
+    assert_eq!(find("a1", None, 320).unwrap(), Some(first.clone()));
+    let cover = find("a2", None, 320).unwrap().unwrap();
+    assert_eq!(find("a2", Some("a2-002.jpeg"), 320).unwrap(), Some(cover));
+    let err = find("a2", Some("a2-001.jpeg"), 320).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::UnsupportedImage);
+    assert_eq!(find("missing", None, 320).unwrap(), None);
+
+    // Unchanged images keep their thumbnail; changed ones get a new one
+    let made = std::fs::metadata(&first).unwrap().modified().unwrap();
+    assert_eq!(
+        find("a1", Some("a1-001.jpeg"), 320).unwrap(),
+        Some(first.clone())
+    );
+    assert_eq!(std::fs::metadata(&first).unwrap().modified().unwrap(), made);
+    std::fs::write(
+        root.join("Sommerfest-2024/id-a1-003.jpeg"),
+        support::photo(ImageFormat::WebP, 480, 640),
+    )
+    .unwrap();
+    let changed = find("a1", Some("a1-003.jpeg"), 320).unwrap().unwrap();
+    assert_ne!(changed, wide);
+    assert!(!wide.exists());
+    assert_eq!(dimensions(&changed), (240, 320));
+
+    // The gallery shows thumbnails and opens the originals
+    gallery::write_gallery(root, GalleryLanguage::En).unwrap();
+    let page = std::fs::read_to_string(root.join("gallery/a1.html")).unwrap();
+    assert!(page.contains(&format!(
+        r#"<a href="../Sommerfest-2024/id-a1-001.jpeg"><img src="../.kidplan/thumbnails/{}""#,
+        first.file_name().unwrap().to_string_lossy()
+    )));
+    let page = std::fs::read_to_string(root.join("gallery/a2.html")).unwrap();
+    assert!(page.contains(r#"<img src="../Tur-til-skogen/id-a2-001.jpeg""#));
+}
@@ -0,0 +1,1200 @@
+//! A small in-process stand-in for the Kidplan service.
+//!
+//! It implements just enough of the real endpoints (kindergarten lookup,
+//! login, paginated album JSON, album HTML and image bytes) for the
+//! downloader to run end to end against it.
+
+#![allow(dead_code)]
+
+use axum::extract::{Form, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode, Uri};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use kidplan_downloader_lib::endpoints::Endpoints;
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+const SESSION_COOKIE: &str = "KidplanSession";
+
+#[derive(Clone)]
+pub struct MockImage {
+    pub id: String,
+    pub bytes: Vec<u8>,
+    /// Shown as a `<figcaption>` on the album page when set.
+    pub caption: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct MockAlbum {
+    pub id: String,
+    pub title: String,
+    /// Sent as `AlbumDate` when set.
+    pub date: Option<String>,
+    /// Further `GetAlbumsAsJson` fields, sent as given.
+    pub fields: serde_json::Map<String, serde_json::Value>,
+    pub images: Vec<MockImage>,
+}
+
+impl MockAlbum {
+    /// An album with `count` small fake JPEGs.
+    pub fn new(id: &str, title: &str, count: usize) -> Self {
+        let images = (1..=count)
+            .map(|n| MockImage {
+                id: format!("{}-{:03}.jpeg", id, n),
+                bytes: fake_jpeg(&format!("{}/{}", id, n)),
+                caption: None,
+            })
+            .collect();
+        Self {
+            id: id.to_string(),
+            title: title.to_string(),
+            date: None,
+            fields: serde_json::Map::new(),
+            images,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MockKidplan {
+    pub email: String,
+    pub password: String,
+    pub kindergartens: Vec<(i64, String)>,
+    pub albums: Vec<MockAlbum>,
+    /// Albums of kindergartens other than the first; those not listed here
+    /// serve `albums`.
+    pub kid_albums: HashMap<i64, Vec<MockAlbum>>,
+    /// Drop all sessions once this many images have been served, as if the
+    /// session timed out during a download.
+    pub expire_after_images: Option<usize>,
+    /// Answer image requests only after this long, like a slow connection.
+    pub image_delay: Option<std::time::Duration>,
+    /// Answer every image request with this status instead of the image.
+    pub image_status: Option<StatusCode>,
+    /// Answer album list requests only after this long.
+    pub album_list_delay: Option<std::time::Duration>,
+}
+
+impl MockKidplan {
+    pub fn sample() -> Self {
+        Self {
+            email: "parent@example.com".to_string(),
+            password: "hunter2".to_string(),
+            kindergartens: vec![(101, "Solstråla barnehage".to_string())],
+            albums: vec![
+                MockAlbum::new("a1", "Sommerfest 2024", 3),
+                MockAlbum::new("a2", "Tur til skogen", 2),
+            ],
+            kid_albums: HashMap::new(),
+            expire_after_images: None,
+            image_delay: None,
+            image_status: None,
+            album_list_delay: None,
+        }
+    }
+
+    fn albums_for(&self, kid: i64) -> &[MockAlbum] {
+        self.kid_albums.get(&kid).unwrap_or(&self.albums)
+    }
+
+    pub async fn start(self) -> MockServer {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind mock server");
+        let addr = listener.local_addr().unwrap();
+        let shared = Arc::new(Shared {
+            config: Mutex::new(self),
+            addr,
+            sessions: Mutex::new(HashMap::new()),
+            requests: Mutex::new(Vec::new()),
+        });
+        let app = Router::new()
+            .route("/Account/GetKinderGartenIds", get(kindergarten_ids))
+            .route("/LogOn", post(logon))
+            .route("/bilder/GetAlbumsAsJson", get(albums_json))
+            .route("/bilder/album/{id}", get(album_page))
+            .route("/albumpicture/", get(album_picture))
+            .with_state(shared.clone());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        MockServer { shared }
+    }
+}
+
+pub struct MockServer {
+    shared: Arc<Shared>,
+}
+
+impl MockServer {
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.shared.addr)
+    }
+
+    pub fn endpoints(&self) -> Endpoints {
+        Endpoints::new(&self.base_url(), vec!["127.0.0.1".to_string()])
+    }
+
+    pub fn credentials(&self) -> kidplan_downloader_lib::Credentials {
+        let config = self.shared.config.lock().unwrap();
+        kidplan_downloader_lib::Credentials {
+            email: config.email.clone(),
+            password: config.password.clone(),
+        }
+    }
+
+    /// Every request path (with query) served so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.shared.requests.lock().unwrap().clone()
+    }
+
+    pub fn image_requests(&self) -> usize {
+        self.requests()
+            .iter()
+            .filter(|r| r.starts_with("/albumpicture/"))
+            .count()
+    }
+
+    /// Invalidate all sessions, as if the server-side session timed out.
+    pub fn expire_sessions(&self) {
+        self.shared.sessions.lock().unwrap().clear();
+    }
+
+    /// Change the albums served from now on.
+    pub fn set_albums(&self, albums: Vec<MockAlbum>) {
+        self.shared.config.lock().unwrap().albums = albums;
+    }
+}
+
+struct Shared {
+    config: Mutex<MockKidplan>,
+    addr: SocketAddr,
+    /// Session token to the kindergarten it was opened for.
+    sessions: Mutex<HashMap<String, i64>>,
+    requests: Mutex<Vec<String>>,
+}
+
+impl Shared {
+    fn record(&self, uri: &Uri) {
+        let path = uri
+            .path_and_query()
+            .map(|p| p.to_string())
+            .unwrap_or_default();
+        self.requests.lock().unwrap().push(path);
+    }
+
+    /// The kindergarten of the request's session, if it has a valid one.
+    fn session_kid(&self, headers: &HeaderMap) -> Option<i64> {
+        let sessions = self.sessions.lock().unwrap();
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(';'))
+            .filter_map(|c| c.trim().strip_prefix(&format!("{}=", SESSION_COOKIE)))
+            .find_map(|token| sessions.get(token).copied())
+    }
+
+    fn credentials_match(&self, email: &str, password: &str) -> bool {
+        let config = self.config.lock().unwrap();
+        config.email == email && config.password == password
+    }
+}
+
+async fn kindergarten_ids(
+    State(shared): State<Arc<Shared>>,
+    uri: Uri,
+    Query(params): Query<HashMap<String, String>>,
+) -> Json<serde_json::Value> {
+    shared.record(&uri);
+    let email = params.get("username").map(String::as_str).unwrap_or("");
+    let password = params.get("password").map(String::as_str).unwrap_or("");
+    if !shared.credentials_match(email, password) {
+        return Json(json!([]));
+    }
+    let config = shared.config.lock().unwrap();
+    let kids: Vec<_> = config
+        .kindergartens
+        .iter()
+        .map(|(id, name)| json!({ "Id": id, "Name": name }))
+        .collect();
+    Json(json!(kids))
+}
+
+async fn logon(
+    State(shared): State<Arc<Shared>>,
+    uri: Uri,
+    Query(params): Query<HashMap<String, String>>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Response {
+    shared.record(&uri);
+    let email = form.get("UserName").map(String::as_str).unwrap_or("");
+    let password = form.get("Password").map(String::as_str).unwrap_or("");
+    let kid = params
+        .get("kid")
+        .and_then(|k| k.parse::<i64>().ok())
+        .filter(|kid| {
+            let config = shared.config.lock().unwrap();
+            config.kindergartens.iter().any(|(id, _)| id == kid)
+        });
+    let Some(kid) = kid.filter(|_| shared.credentials_match(email, password)) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let token = {
+        let mut sessions = shared.sessions.lock().unwrap();
+        let token = format!("session-{}", sessions.len() + 1);
+        sessions.insert(token.clone(), kid);
+        token
+    };
+    (
+        [(
+            header::SET_COOKIE,
+            format!("{}={}; Path=/; HttpOnly", SESSION_COOKIE, token),
+        )],
+        "OK",
+    )
+        .into_response()
+}
+
+async fn albums_json(
+    State(shared): State<Arc<Shared>>,
+    uri: Uri,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    shared.record(&uri);
+    let Some(kid) = shared.session_kid(&headers) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let delay = shared.config.lock().unwrap().album_list_delay;
+    if let Some(delay) = delay {
+        tokio::time::sleep(delay).await;
+    }
+    let take: usize = params
+        .get("take")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(50);
+    let skip: usize = params.get("skip").and_then(|v| v.parse().ok()).unwrap_or(0);
+    let config = shared.config.lock().unwrap();
+    let page: Vec<_> = config
+        .albums_for(kid)
+        .iter()
+        .skip(skip)
+        .take(take)
+        .map(|a| {
+            let mut album = json!({
+                "AlbumId": a.id,
+                "Title": a.title,
+                "AlbumUrl": format!("/bilder/album/{}", a.id),
+                "NumberOfImages": a.images.len(),
+            });
+            if let Some(date) = &a.date {
+                album["AlbumDate"] = json!(date);
+            }
+            for (key, value) in &a.fields {
+                album[key] = value.clone();
+            }
+            album
+        })
+        .collect();
+    Json(json!(page)).into_response()
+}
+
+async fn album_page(
+    State(shared): State<Arc<Shared>>,
+    uri: Uri,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    shared.record(&uri);
+    let Some(kid) = shared.session_kid(&headers) else {
+        return Html(
+            "<html><head><title>Log in Kidplan</title></head>\
+             <body><form id=\"loginform\"></form></body></html>",
+        )
+        .into_response();
+    };
+    let config = shared.config.lock().unwrap();
+    let Some(album) = config.albums_for(kid).iter().find(|a| a.id == id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let mut body = format!("<html><body><h1>{}</h1><ul>", album.title);
+    for image in &album.images {
+        let caption = image
+            .caption
+            .as_ref()
+            .map(|c| format!("<figcaption>{}</figcaption>", html_escape::encode_text(c)))
+            .unwrap_or_default();
+        body.push_str(&format!(
+            "<li><figure><a href=\"http://{addr}/albumpicture/?id={id}\">\
+             <img src=\"http://{addr}/albumpicture/?id={id}&amp;size=small\"></a>\
+             {caption}</figure></li>",
+            addr = shared.addr,
+            id = image.id,
+        ));
+    }
+    body.push_str("</ul></body></html>");
+    Html(body).into_response()
+}
+
+async fn album_picture(
+    State(shared): State<Arc<Shared>>,
+    uri: Uri,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    shared.record(&uri);
+    let Some(kid) = shared.session_kid(&headers) else {
+        return StatusCode::FORBIDDEN.into_response();
+    };
+    let id = params.get("id").cloned().unwrap_or_default();
+    let (delay, status) = {
+        let config = shared.config.lock().unwrap();
+        (config.image_delay, config.image_status)
+    };
+    if let Some(delay) = delay {
+        tokio::time::sleep(delay).await;
+    }
+    if let Some(status) = status {
+        return status.into_response();
+    }
+    let mut config = shared.config.lock().unwrap();
+    if let Some(left) = config.expire_after_images.as_mut() {
+        if *left == 0 {
+            config.expire_after_images = None;
+            shared.sessions.lock().unwrap().clear();
+            return StatusCode::FORBIDDEN.into_response();
+        }
+        *left -= 1;
+    }
+    let image = config
+        .albums_for(kid)
+        .iter()
+        .flat_map(|a| a.images.iter())
+        .find(|img| img.id == id);
+    match image {
+        Some(img) => ([(header::CONTENT_TYPE, "image/jpeg")], img.bytes.clone()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// A minimal well-formed JPEG (a JFIF header carrying `seed`, no image
+/// data), unique per `seed`.
+pub fn fake_jpeg(seed: &str) -> Vec<u8> {
+    let mut app0 = b"JFIF\0".to_vec();
+    app0.extend_from_slice(seed.as_bytes());
+    let mut bytes = vec![0xFF, 0xD8, 0xFF, 0xE0];
+    bytes.extend_from_slice(&(app0.len() as u16 + 2).to_be_bytes());
+    bytes.extend_from_slice(&app0);
+    bytes.extend_from_slice(&[0xFF, 0xD9]);
+    bytes
+}
+
+/// A decodable `width`×`height` gradient in `format` (JPEG, PNG or WebP),
+/// unlike [`fake_jpeg`].
+pub fn photo(format: image::ImageFormat, width: u32, height: u32) -> Vec<u8> {
+    let pixels = image::RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, 128])
+    });
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    pixels.write_to(&mut bytes, format).expect("encode photo");
+    bytes.into_inner()
+}
+
+/// A WebDAV server keeping files in memory, with just the methods the
+/// WebDAV storage uses: MKCOL, PUT, MOVE, HEAD and PROPFIND (depth 1).
+#[derive(Clone)]
+pub struct MockWebDav {
+    pub username: String,
+    pub password: String,
+}
+
+impl MockWebDav {
+    pub fn new() -> Self {
+        Self {
+            username: "parent".to_string(),
+            password: "dav-secret".to_string(),
+        }
+    }
+
+    pub async fn start(self) -> MockStorageServer {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind mock WebDAV server");
+        let addr = listener.local_addr().unwrap();
+        let shared = Arc::new(StorageShared {
+            addr,
+            files: Mutex::new(HashMap::new()),
+            dirs: Mutex::new(std::collections::HashSet::from([String::new()])),
+            requests: Mutex::new(Vec::new()),
+            webdav: Some(self),
+            s3: None,
+        });
+        let app = Router::new().fallback(webdav).with_state(shared.clone());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        MockStorageServer { shared }
+    }
+}
+
+/// An S3-compatible server keeping objects in memory, addressed path-style.
+/// It checks that requests are signed by `access_key` and carry the hash of
+/// their body, but not the signature itself.
+#[derive(Clone)]
+pub struct MockS3 {
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// Objects per listing page, to exercise continuation tokens.
+    pub page_size: usize,
+}
+
+impl MockS3 {
+    pub fn new() -> Self {
+        Self {
+            bucket: "photos".to_string(),
+            access_key: "AKIDEXAMPLE".to_string(),
+            secret_key: "s3-secret".to_string(),
+            page_size: 1000,
+        }
+    }
+
+    pub async fn start(self) -> MockStorageServer {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind mock S3 server");
+        let addr = listener.local_addr().unwrap();
+        let shared = Arc::new(StorageShared {
+            addr,
+            files: Mutex::new(HashMap::new()),
+            dirs: Mutex::new(std::collections::HashSet::new()),
+            requests: Mutex::new(Vec::new()),
+            webdav: None,
+            s3: Some(self),
+        });
+        let app = Router::new().fallback(s3).with_state(shared.clone());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        MockStorageServer { shared }
+    }
+}
+
+pub struct MockStorageServer {
+    shared: Arc<StorageShared>,
+}
+
+impl MockStorageServer {
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.shared.addr)
+    }
+
+    /// Where the WebDAV stand-in keeps its files.
+    pub fn dav_url(&self) -> String {
+        format!("{}/dav/Kidplan", self.base_url())
+    }
+
+    /// Stored files by path (WebDAV, below `dav_url`) or key (S3).
+    pub fn files(&self) -> HashMap<String, Vec<u8>> {
+        self.shared.files.lock().unwrap().clone()
+    }
+
+    /// Method and path of every request served so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.shared.requests.lock().unwrap().clone()
+    }
+
+    /// Store a file directly, as if another client had uploaded it.
+    pub fn insert(&self, path: &str, bytes: Vec<u8>) {
+        self.shared
+            .files
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), bytes);
+    }
+}
+
+struct StorageShared {
+    addr: SocketAddr,
+    files: Mutex<HashMap<String, Vec<u8>>>,
+    /// WebDAV collections, relative to `/dav/Kidplan`.
+    dirs: Mutex<std::collections::HashSet<String>>,
+    requests: Mutex<Vec<String>>,
+    webdav: Option<MockWebDav>,
+    s3: Option<MockS3>,
+}
+
+const DAV_ROOT: &str = "/dav/Kidplan";
+
+/// The path below [`DAV_ROOT`], decoded and without trailing slash.
+fn dav_path(path: &str) -> Option<String> {
+    let decoded = urlencoding::decode(path).ok()?;
+    let rest = decoded.strip_prefix(DAV_ROOT)?;
+    Some(rest.trim_matches('/').to_string())
+}
+
+fn dav_parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+fn dav_href(path: &str, collection: bool) -> String {
+    let mut href = DAV_ROOT.to_string();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        href.push('/');
+        href.push_str(&urlencoding::encode(segment));
+    }
+    if collection {
+        href.push('/');
+    }
+    href
+}
+
+async fn webdav(
+    State(shared): State<Arc<StorageShared>>,
+    method: axum::http::Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Response {
+    shared
+        .requests
+        .lock()
+        .unwrap()
+        .push(format!("{} {}", method, uri.path()));
+    let dav = shared.webdav.as_ref().unwrap();
+    let expected = format!(
+        "Basic {}",
+        base64_encode(format!("{}:{}", dav.username, dav.password).as_bytes())
+    );
+    if headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        != Some(&expected)
+    {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let Some(path) = dav_path(uri.path()) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let mut files = shared.files.lock().unwrap();
+    let mut dirs = shared.dirs.lock().unwrap();
+    match method.as_str() {
+        "MKCOL" => {
+            if dirs.contains(&path) || files.contains_key(&path) {
+                StatusCode::METHOD_NOT_ALLOWED.into_response()
+            } else if !dirs.contains(dav_parent(&path)) {
+                StatusCode::CONFLICT.into_response()
+            } else {
+                dirs.insert(path);
+                StatusCode::CREATED.into_response()
+            }
+        }
+        "PUT" => {
+            if !dirs.contains(dav_parent(&path)) {
+                return StatusCode::CONFLICT.into_response();
+            }
+            files.insert(path, body.to_vec());
+            StatusCode::CREATED.into_response()
+        }
+        "MOVE" => {
+            let destination = headers
+                .get("Destination")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| url::Url::parse(v).ok())
+                .and_then(|url| dav_path(url.path()));
+            let (Some(destination), Some(bytes)) = (destination, files.remove(&path)) else {
+                return StatusCode::BAD_REQUEST.into_response();
+            };
+            files.insert(destination, bytes);
+            StatusCode::CREATED.into_response()
+        }
+        "HEAD" | "GET" => match files.get(&path) {
+            Some(bytes) => (
+                [(header::CONTENT_LENGTH, bytes.len().to_string())],
+                if method == axum::http::Method::GET {
+                    bytes.clone()
+                } else {
+                    Vec::new()
+                },
+            )
+                .into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        },
+        "PROPFIND" => {
+            if headers.get("Depth").and_then(|v| v.to_str().ok()) != Some("1") {
+                return StatusCode::FORBIDDEN.into_response();
+            }
+            if !dirs.contains(&path) {
+                return StatusCode::NOT_FOUND.into_response();
+            }
+            let in_dir = |p: &String| !p.is_empty() && dav_parent(p) == path;
+            let mut responses = vec![dav_response(&dav_href(&path, true), None)];
+            for dir in dirs.iter().filter(|d| in_dir(d)) {
+                responses.push(dav_response(&dav_href(dir, true), None));
+            }
+            for (file, bytes) in files.iter().filter(|(f, _)| in_dir(f)) {
+                responses.push(dav_response(&dav_href(file, false), Some(bytes.len())));
+            }
+            (
+                StatusCode::MULTI_STATUS,
+                [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+                format!(
+                    r#"<?xml version="1.0" encoding="utf-8"?><d:multistatus xmlns:d="DAV:">{}</d:multistatus>"#,
+                    responses.concat()
+                ),
+            )
+                .into_response()
+        }
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+fn dav_response(href: &str, size: Option<usize>) -> String {
+    let props = match size {
+        Some(size) => format!(
+            "<d:resourcetype/><d:getcontentlength>{}</d:getcontentlength>",
+            size
+        ),
+        None => "<d:resourcetype><d:collection/></d:resourcetype>".to_string(),
+    };
+    format!(
+        "<d:response><d:href>{}</d:href><d:propstat><d:prop>{}</d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+        href, props
+    )
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+async fn s3(
+    State(shared): State<Arc<StorageShared>>,
+    method: axum::http::Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Response {
+    shared
+        .requests
+        .lock()
+        .unwrap()
+        .push(format!("{} {}", method, uri));
+    let s3 = shared.s3.as_ref().unwrap();
+    let signed = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with(&format!("AWS4-HMAC-SHA256 Credential={}/", s3.access_key)));
+    let body_hash = format!("{:x}", <sha2::Sha256 as sha2::Digest>::digest(&body));
+    let hash_matches = headers
+        .get("x-amz-content-sha256")
+        .and_then(|v| v.to_str().ok())
+        == Some(body_hash.as_str());
+    if !signed || !hash_matches {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let path = urlencoding::decode(uri.path()).unwrap().into_owned();
+    let mut parts = path.trim_start_matches('/').splitn(2, '/');
+    if parts.next() != Some(s3.bucket.as_str()) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let key = parts.next().unwrap_or("").to_string();
+    let mut files = shared.files.lock().unwrap();
+    match (method.as_str(), key.is_empty()) {
+        ("PUT", false) => {
+            files.insert(key, body.to_vec());
+            StatusCode::OK.into_response()
+        }
+        ("HEAD", false) => match files.get(&key) {
+            Some(bytes) => [(header::CONTENT_LENGTH, bytes.len().to_string())].into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        },
+        ("GET", true) => {
+            let query: HashMap<String, String> = uri
+                .query()
+                .map(|q| {
+                    url::form_urlencoded::parse(q.as_bytes())
+                        .into_owned()
+                        .collect()
+                })
+                .unwrap_or_default();
+            if query.get("list-type").map(String::as_str) != Some("2") {
+                return StatusCode::BAD_REQUEST.into_response();
+            }
+            let prefix = query.get("prefix").cloned().unwrap_or_default();
+            let start: usize = query
+                .get("continuation-token")
+                .and_then(|t| t.parse().ok())
+                .unwrap_or(0);
+            let mut keys: Vec<_> = files.keys().filter(|k| k.starts_with(&prefix)).collect();
+            keys.sort();
+            let page: Vec<_> = keys.iter().skip(start).take(s3.page_size).collect();
+            let truncated = start + page.len() < keys.len();
+            let contents: String = page
+                .iter()
+                .map(|key| {
+                    format!(
+                        "<Contents><Key>{}</Key><Size>{}</Size></Contents>",
+                        key.replace('&', "&amp;"),
+                        files[**key].len()
+                    )
+                })
+                .collect();
+            let next = if truncated {
+                format!(
+                    "<NextContinuationToken>{}</NextContinuationToken>",
+                    start + page.len()
+                )
+            } else {
+                String::new()
+            };
+            (
+                [(header::CONTENT_TYPE, "application/xml")],
+                format!(
+                    r#"<?xml version="1.0" encoding="UTF-8"?><ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><IsTruncated>{}</IsTruncated>{}{}</ListBucketResult>"#,
+                    s3.bucket,
+                    prefix,
+                    page.len(),
+                    truncated,
+                    contents,
+                    next
+                ),
+            )
+                .into_response()
+        }
+        ("GET", false) => match files.get(&key) {
+            Some(bytes) => bytes.clone().into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        },
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+/// An asset held by the photo library stand-ins.
+#[derive(Clone)]
+pub struct LibraryAsset {
+    pub id: String,
+    pub filename: String,
+    pub bytes: Vec<u8>,
+    /// Immich's `deviceAssetId`; empty for PhotoPrism.
+    pub device_asset_id: String,
+    checksum: String,
+}
+
+#[derive(Default)]
+struct LibraryState {
+    assets: Vec<LibraryAsset>,
+    /// Album id, name and asset ids, in creation order.
+    albums: Vec<(String, String, Vec<String>)>,
+    /// PhotoPrism uploads by batch token, not yet imported.
+    pending: HashMap<String, Vec<(String, Vec<u8>)>>,
+    /// Method and path of every request, in order.
+    requests: Vec<String>,
+}
+
+impl LibraryState {
+    fn find_checksum(&self, checksum: &str) -> Option<&LibraryAsset> {
+        self.assets.iter().find(|a| a.checksum == checksum)
+    }
+
+    fn add_asset(&mut self, filename: String, bytes: Vec<u8>, device_asset_id: String) -> String {
+        let id = format!("asset-{}", self.assets.len() + 1);
+        self.assets.push(LibraryAsset {
+            id: id.clone(),
+            checksum: sha1_hex(&bytes),
+            filename,
+            bytes,
+            device_asset_id,
+        });
+        id
+    }
+
+    fn album_named(&mut self, name: &str) -> &mut (String, String, Vec<String>) {
+        if let Some(i) = self.albums.iter().position(|a| a.1 == name) {
+            return &mut self.albums[i];
+        }
+        let id = format!("album-{}", self.albums.len() + 1);
+        self.albums.push((id, name.to_string(), Vec::new()));
+        self.albums.last_mut().unwrap()
+    }
+}
+
+fn sha1_hex(bytes: &[u8]) -> String {
+    format!("{:x}", <sha1::Sha1 as sha1::Digest>::digest(bytes))
+}
+
+/// A photo library stand-in speaking the parts of the Immich or the
+/// PhotoPrism API the uploader uses.
+pub struct MockPhotoLibrary {
+    state: Arc<Mutex<LibraryState>>,
+    addr: SocketAddr,
+}
+
+/// The API key Immich expects and the app password PhotoPrism expects.
+pub const LIBRARY_KEY: &str = "library-key";
+
+impl MockPhotoLibrary {
+    pub async fn immich() -> Self {
+        let state = Arc::new(Mutex::new(LibraryState::default()));
+        let app = Router::new()
+            .route("/api/assets/bulk-upload-check", post(immich_check))
+            .route("/api/assets", post(immich_upload))
+            .route("/api/albums", get(immich_albums).post(immich_create_album))
+            .route(
+                "/api/albums/{id}/assets",
+                axum::routing::put(immich_add_assets),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                ("x-api-key", state.clone()),
+                library_auth,
+            ))
+            .with_state(state.clone());
+        Self::serve(state, app).await
+    }
+
+    pub async fn photoprism() -> Self {
+        let state = Arc::new(Mutex::new(LibraryState::default()));
+        let app = Router::new()
+            .route("/api/v1/session", get(photoprism_session))
+            .route("/api/v1/files/{hash}", get(photoprism_file))
+            .route(
+                "/api/v1/albums",
+                get(photoprism_albums).post(photoprism_create_album),
+            )
+            .route("/api/v1/albums/{uid}/photos", post(photoprism_add_photos))
+            .route(
+                "/api/v1/users/{uid}/upload/{token}",
+                post(photoprism_upload).put(photoprism_import),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                ("x-auth-token", state.clone()),
+                library_auth,
+            ))
+            .with_state(state.clone());
+        Self::serve(state, app).await
+    }
+
+    async fn serve(state: Arc<Mutex<LibraryState>>, app: Router) -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind mock photo library");
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        Self { state, addr }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn assets(&self) -> Vec<LibraryAsset> {
+        self.state.lock().unwrap().assets.clone()
+    }
+
+    /// Album names with the file names of their assets, sorted.
+    pub fn albums(&self) -> HashMap<String, Vec<String>> {
+        let state = self.state.lock().unwrap();
+        state
+            .albums
+            .iter()
+            .map(|(_, name, ids)| {
+                let mut files: Vec<String> = ids
+                    .iter()
+                    .filter_map(|id| state.assets.iter().find(|a| a.id == *id))
+                    .map(|a| a.filename.clone())
+                    .collect();
+                files.sort();
+                (name.clone(), files)
+            })
+            .collect()
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Requests that carried photo bytes.
+    pub fn uploads(&self) -> usize {
+        self.requests()
+            .iter()
+            .filter(|r| r == &"POST /api/assets" || (r.starts_with("POST /api/v1/users/")))
+            .count()
+    }
+}
+
+async fn library_auth(
+    State((header_name, state)): State<(&'static str, Arc<Mutex<LibraryState>>)>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    state
+        .lock()
+        .unwrap()
+        .requests
+        .push(format!("{} {}", request.method(), request.uri().path()));
+    let key = request
+        .headers()
+        .get(header_name)
+        .and_then(|v| v.to_str().ok());
+    if key != Some(LIBRARY_KEY) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(request).await
+}
+
+async fn immich_check(
+    State(state): State<Arc<Mutex<LibraryState>>>,
+    Json(body): Json<serde_json::Value>,
+) -> Json<serde_json::Value> {
+    let state = state.lock().unwrap();
+    let results: Vec<_> = body["assets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|asset| {
+            let checksum = asset["checksum"].as_str().unwrap_or_default();
+            match state.find_checksum(checksum) {
+                Some(found) => json!({
+                    "id": asset["id"],
+                    "action": "reject",
+                    "reason": "duplicate",
+                    "assetId": found.id,
+                }),
+                None => json!({ "id": asset["id"], "action": "accept" }),
+            }
+        })
+        .collect();
+    Json(json!({ "results": results }))
+}
+
+async fn immich_upload(
+    State(state): State<Arc<Mutex<LibraryState>>>,
+    mut form: axum::extract::Multipart,
+) -> Response {
+    let mut fields = HashMap::new();
+    let mut file = None;
+    while let Some(field) = form.next_field().await.unwrap() {
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "assetData" {
+            let filename = field.file_name().unwrap_or_default().to_string();
+            file = Some((filename, field.bytes().await.unwrap().to_vec()));
+        } else {
+            fields.insert(name, field.text().await.unwrap());
+        }
+    }
+    let (Some((filename, bytes)), Some(device_asset_id)) = (file, fields.remove("deviceAssetId"))
+    else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let created = fields
+        .get("fileCreatedAt")
+        .map(|t| chrono::DateTime::parse_from_rfc3339(t));
+    if !matches!(created, Some(Ok(_))) || !fields.contains_key("deviceId") {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    let mut state = state.lock().unwrap();
+    if let Some(found) = state.find_checksum(&sha1_hex(&bytes)) {
+        return Json(json!({ "id": found.id, "status": "duplicate" })).into_response();
+    }
+    let id = state.add_asset(filename, bytes, device_asset_id);
+    (
+        StatusCode::CREATED,
+        Json(json!({ "id": id, "status": "created" })),
+    )
+        .into_response()
+}
+
+async fn immich_albums(State(state): State<Arc<Mutex<LibraryState>>>) -> Json<serde_json::Value> {
+    let state = state.lock().unwrap();
+    Json(json!(state
+        .albums
+        .iter()
+        .map(|(id, name, ids)| json!({ "id": id, "albumName": name, "assetCount": ids.len() }))
+        .collect::<Vec<_>>()))
+}
+
+async fn immich_create_album(
+    State(state): State<Arc<Mutex<LibraryState>>>,
+    Json(body): Json<serde_json::Value>,
+) -> Response {
+    let Some(name) = body["albumName"].as_str() else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let mut state = state.lock().unwrap();
+    let id = format!("album-{}", state.albums.len() + 1);
+    state
+        .albums
+        .push((id.clone(), name.to_string(), Vec::new()));
+    (
+        StatusCode::CREATED,
+        Json(json!({ "id": id, "albumName": name })),
+    )
+        .into_response()
+}
+
+async fn immich_add_assets(
+    State(state): State<Arc<Mutex<LibraryState>>>,
+    Path(album_id): Path<String>,
+    Json(body): Json<serde_json::Value>,
+) -> Response {
+    let mut state = state.lock().unwrap();
+    let Some(album) = state.albums.iter_mut().find(|a| a.0 == album_id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let results: Vec<_> = body["ids"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|id| id.as_str())
+        .map(|id| {
+            if album.2.iter().any(|a| a == id) {
+                json!({ "id": id, "success": false, "error": "duplicate" })
+            } else {
+                album.2.push(id.to_string());
+                json!({ "id": id, "success": true })
+            }
+        })
+        .collect();
+    Json(json!(results)).into_response()
+}
+
+const PHOTOPRISM_USER: &str = "uqparent";
+
+async fn photoprism_session() -> Json<serde_json::Value> {
+    Json(json!({ "id": "session-1", "user": { "UID": PHOTOPRISM_USER, "Name": "parent" } }))
+}
+
+async fn photoprism_file(
+    State(state): State<Arc<Mutex<LibraryState>>>,
+    Path(hash): Path<String>,
+) -> Response {
+    match state.lock().unwrap().find_checksum(&hash) {
+        Some(asset) => Json(json!({ "Hash": hash, "Name": asset.filename, "PhotoUID": asset.id }))
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Albums whose title contains `q`, as PhotoPrism's search matches them.
+async fn photoprism_albums(
+    State(state): State<Arc<Mutex<LibraryState>>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Json<serde_json::Value> {
+    let q = query.get("q").cloned().unwrap_or_default();
+    let state = state.lock().unwrap();
+    Json(json!(state
+        .albums
+        .iter()
+        .filter(|(_, title, _)| title.contains(&q))
+        .map(|(uid, title, _)| json!({ "UID": uid, "Title": title }))
+        .collect::<Vec<_>>()))
+}
+
+async fn photoprism_create_album(
+    State(state): State<Arc<Mutex<LibraryState>>>,
+    Json(body): Json<serde_json::Value>,
+) -> Response {
+    let Some(title) = body["Title"].as_str() else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let mut state = state.lock().unwrap();
+    let uid = state.album_named(title).0.clone();
+    Json(json!({ "UID": uid, "Title": title })).into_response()
+}
+
+async fn photoprism_add_photos(
+    State(state): State<Arc<Mutex<LibraryState>>>,
+    Path(uid): Path<String>,
+    Json(body): Json<serde_json::Value>,
+) -> Response {
+    let mut state = state.lock().unwrap();
+    let Some(album) = state.albums.iter_mut().find(|a| a.0 == uid) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    for photo in body["photos"].as_array().into_iter().flatten() {
+        let photo = photo.as_str().unwrap_or_default().to_string();
+        if !album.2.contains(&photo) {
+            album.2.push(photo);
+        }
+    }
+    Json(json!({ "code": 200 })).into_response()
+}
+
+async fn photoprism_upload(
+    State(state): State<Arc<Mutex<LibraryState>>>,
+    Path((uid, token)): Path<(String, String)>,
+    mut form: axum::extract::Multipart,
+) -> Response {
+    if uid != PHOTOPRISM_USER {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    let mut files = Vec::new();
+    while let Some(field) = form.next_field().await.unwrap() {
+        if field.name() == Some("files") {
+            let filename = field.file_name().unwrap_or_default().to_string();
+            files.push((filename, field.bytes().await.unwrap().to_vec()));
+        }
+    }
+    state
+        .lock()
+        .unwrap()
+        .pending
+        .entry(token)
+        .or_default()
+        .extend(files);
+    Json(json!({ "code": 200 })).into_response()
+}
+
+async fn photoprism_import(
+    State(state): State<Arc<Mutex<LibraryState>>>,
+    Path((uid, token)): Path<(String, String)>,
+    Json(body): Json<serde_json::Value>,
+) -> Response {
+    let mut state = state.lock().unwrap();
+    let Some(files) = state
+        .pending
+        .remove(&token)
+        .filter(|_| uid == PHOTOPRISM_USER)
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let mut ids = Vec::new();
+    for (filename, bytes) in files {
+        // Like PhotoPrism, files already indexed are not imported again
+        let id = match state.find_checksum(&sha1_hex(&bytes)) {
+            Some(found) => found.id.clone(),
+            None => state.add_asset(filename, bytes, String::new()),
+        };
+        ids.push(id);
+    }
+    for name in body["albums"].as_array().into_iter().flatten() {
+        let album = state.album_named(name.as_str().unwrap_or_default());
+        for id in &ids {
+            if !album.2.contains(id) {
+                album.2.push(id.clone());
+            }
+        }
+    }
+    Json(json!({ "code": 200 })).into_response()
+}
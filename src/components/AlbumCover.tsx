@@ -0,0 +1,49 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { useApp } from "../context/AppContext";
+import type { Album } from "../types";
+
+/** Thumbnail size in pixels: twice the 40px cover, for sharp high-DPI screens. */
+const COVER_SIZE = 80;
+
+/**
+ * The album's cover: a thumbnail of its first downloaded photo, or the
+ * cover Kidplan shows until the album is downloaded.
+ */
+export default function AlbumCover({ album }: { album: Album }) {
+  const { settings } = useApp();
+  const [local, setLocal] = useState<string | null>(null);
+
+  useEffect(() => {
+    let url: string | null = null;
+    let cancelled = false;
+    invoke<ArrayBuffer>("read_thumbnail", {
+      outDir: settings.out_dir,
+      albumId: album.id,
+      size: COVER_SIZE,
+    })
+      .then((bytes) => {
+        if (cancelled || bytes.byteLength === 0) return;
+        url = URL.createObjectURL(new Blob([bytes], { type: "image/jpeg" }));
+        setLocal(url);
+      })
+      .catch((err) => console.error("[FRONTEND] read_thumbnail error:", err));
+    return () => {
+      cancelled = true;
+      if (url) URL.revokeObjectURL(url);
+      setLocal(null);
+    };
+  }, [album.id, settings.out_dir]);
+
+  const src = local ?? album.cover_url;
+  if (!src) return null;
+  return (
+    <img
+      src={src}
+      alt=""
+      loading="lazy"
+      className="w-10 h-10 mr-3 rounded object-cover shrink-0 bg-gray-100 dark:bg-gray-800"
+      onError={(e) => (e.currentTarget.style.display = "none")}
+    />
+  );
+}
@@ -0,0 +1,107 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { useLanguage } from "../i18n/LanguageContext";
+import type { Job, JobStatus } from "../types";
+
+/** Albums a download job covers, across kindergartens for those with several. */
+function albumCount(job: Job): number {
+  return job.kind.type === "kindergartens"
+    ? job.kind.selections.reduce((count, s) => count + s.albums.length, 0)
+    : job.albums.length;
+}
+
+/** The persisted download queue, with controls to reorder and cancel jobs. */
+export default function JobQueue() {
+  const { t } = useLanguage();
+  const [jobs, setJobs] = useState<Job[]>([]);
+
+  useEffect(() => {
+    invoke<Job[]>("list_jobs")
+      .then(setJobs)
+      .catch((err) => console.error("[FRONTEND] list_jobs error:", err));
+    const unlisten = listen<Job[]>("download-jobs", (event) => setJobs(event.payload));
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  const run = async (command: string, args?: Record<string, unknown>) => {
+    try {
+      await invoke(command, args);
+    } catch (err) {
+      console.error(`[FRONTEND] ${command} error:`, err);
+    }
+  };
+
+  const statusLabel: Record<JobStatus, string> = {
+    queued: t.jobQueued,
+    running: t.jobRunning,
+    completed: t.jobCompleted,
+    failed: t.jobFailed,
+    cancelled: t.jobCancelled,
+  };
+  const hasFinished = jobs.some((j) => !["queued", "running"].includes(j.status));
+  // "Run next" puts a job right after the one running now
+  const nextPosition = jobs.findIndex((j) => j.status === "queued");
+
+  if (jobs.length === 0) {
+    return (
+      <div className="mx-6 mt-4 text-sm text-gray-400 dark:text-gray-500">{t.noJobs}</div>
+    );
+  }
+
+  return (
+    <div className="mx-6 mt-4 bg-white dark:bg-gray-900 border border-gray-200 dark:border-gray-800 rounded-lg">
+      <div className="flex items-center justify-between px-4 py-2 border-b border-gray-100 dark:border-gray-800">
+        <h3 className="text-sm font-medium text-gray-900 dark:text-gray-100">{t.queue}</h3>
+        {hasFinished && (
+          <button
+            onClick={() => run("clear_finished_jobs")}
+            className="text-xs text-gray-500 dark:text-gray-400 hover:text-gray-700 dark:hover:text-gray-300 transition-colors"
+          >
+            {t.clearFinished}
+          </button>
+        )}
+      </div>
+      <ul className="max-h-48 overflow-y-auto divide-y divide-gray-100 dark:divide-gray-800">
+        {jobs.map((job, index) => (
+          <li key={job.id} className="flex items-center gap-3 px-4 py-2 text-sm">
+            <div className="flex-1 min-w-0">
+              <span className="text-gray-900 dark:text-gray-100 truncate block">
+                {job.kind.type === "sync"
+                  ? t.syncJobTitle.replace("{id}", String(job.id))
+                  : t.jobTitle
+                      .replace("{id}", String(job.id))
+                      .replace("{albums}", String(albumCount(job)))}
+                {job.kindergarten && ` · ${job.kindergarten.name}`}
+              </span>
+              <span className="text-xs text-gray-500 dark:text-gray-400 truncate block">
+                {statusLabel[job.status]}
+                {job.result &&
+                  ` · ${job.result.total_images} ${t.downloaded}, ${job.result.skipped} ${t.skipped}, ${job.result.failed} ${t.failed}`}
+                {job.error && ` · ${job.error}`}
+              </span>
+            </div>
+            {job.status === "queued" && index > nextPosition && (
+              <button
+                onClick={() => run("move_job", { jobId: job.id, position: nextPosition })}
+                className="text-xs text-blue-600 dark:text-blue-500 hover:text-blue-700 dark:hover:text-blue-400 transition-colors shrink-0"
+              >
+                {t.runNext}
+              </button>
+            )}
+            {(job.status === "queued" || job.status === "running") && (
+              <button
+                onClick={() => run("cancel_job", { jobId: job.id })}
+                className="text-xs text-red-600 dark:text-red-500 hover:text-red-700 dark:hover:text-red-400 transition-colors shrink-0"
+              >
+                {t.cancel}
+              </button>
+            )}
+          </li>
+        ))}
+      </ul>
+    </div>
+  );
+}
@@ -6,10 +6,10 @@ interface ProtectedRouteProps {
 }
 
 export default function ProtectedRoute({ children }: ProtectedRouteProps) {
-  const { credentials } = useApp();
+  const { credentials, selectedKid } = useApp();
 
-  // If not authenticated, redirect to login
-  if (!credentials) {
+  // If not authenticated (or restored from a saved session), redirect to login
+  if (!credentials && !selectedKid) {
     return <Navigate to="/login" replace />;
   }
 
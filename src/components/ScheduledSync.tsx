@@ -0,0 +1,118 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { useApp } from "../context/AppContext";
+import { useLanguage } from "../i18n/LanguageContext";
+import type { ScheduleConfig, ScheduledRun } from "../types";
+import { errorMessage } from "../utils/errors";
+
+/** Nightly background sync of the selected kindergartens, with its last run. */
+export default function ScheduledSync() {
+  const { settings, selectedKids } = useApp();
+  const { t } = useLanguage();
+  const [config, setConfig] = useState<ScheduleConfig | null>(null);
+  const [lastRun, setLastRun] = useState<ScheduledRun | null>(null);
+  const [running, setRunning] = useState(false);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    invoke<ScheduleConfig | null>("get_schedule")
+      .then(setConfig)
+      .catch((err) => console.error("[FRONTEND] get_schedule error:", err));
+    invoke<ScheduledRun[]>("list_scheduled_runs")
+      .then((runs) => setLastRun(runs[runs.length - 1] ?? null))
+      .catch((err) => console.error("[FRONTEND] list_scheduled_runs error:", err));
+    const unlisten = listen<ScheduledRun>("scheduled-sync", (event) => setLastRun(event.payload));
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  const time = config?.schedule.kind === "daily" ? config.schedule.at.slice(0, 5) : "02:00";
+
+  const save = async (enabled: boolean, at: string) => {
+    const next: ScheduleConfig = {
+      enabled,
+      schedule: { kind: "daily", at: `${at}:00` },
+      kid_ids: selectedKids.map((k) => k.id),
+      settings,
+    };
+    try {
+      await invoke("set_schedule", { config: next });
+      setConfig(next);
+      setError(null);
+    } catch (err) {
+      setError(errorMessage(err));
+    }
+  };
+
+  const syncNow = async () => {
+    setRunning(true);
+    try {
+      await invoke("run_scheduled_sync");
+      setError(null);
+    } catch (err) {
+      setError(errorMessage(err));
+    } finally {
+      setRunning(false);
+    }
+  };
+
+  const runTime = lastRun ? new Date(lastRun.started_at).toLocaleString() : "";
+
+  return (
+    <div>
+      <div className="flex items-center">
+        <input
+          type="checkbox"
+          id="scheduledSync"
+          checked={config?.enabled ?? false}
+          onChange={(e) => save(e.target.checked, time)}
+          className="mr-2 h-4 w-4 rounded border-gray-300 dark:border-gray-700 text-blue-600 focus:ring-blue-500 dark:focus:ring-blue-600"
+        />
+        <label
+          htmlFor="scheduledSync"
+          className="text-sm font-medium text-gray-700 dark:text-gray-300"
+        >
+          {t.scheduledSync}
+        </label>
+      </div>
+      <div className="flex items-center gap-2 mt-2">
+        <label htmlFor="scheduledSyncAt" className="text-sm text-gray-600 dark:text-gray-400">
+          {t.scheduledSyncAt}
+        </label>
+        <input
+          type="time"
+          id="scheduledSyncAt"
+          value={time}
+          onChange={(e) => e.target.value && save(config?.enabled ?? false, e.target.value)}
+          className="px-3 py-1.5 bg-white dark:bg-gray-950 border border-gray-300 dark:border-gray-700 rounded-lg focus:outline-none focus:ring-2 focus:ring-blue-500 dark:focus:ring-blue-600 focus:border-transparent text-gray-900 dark:text-gray-100 text-sm"
+        />
+        <button
+          onClick={syncNow}
+          disabled={running || !config?.enabled}
+          className="ml-auto px-3 py-1.5 text-sm bg-gray-600 dark:bg-gray-700 text-white rounded-lg hover:bg-gray-700 dark:hover:bg-gray-600 disabled:opacity-50 disabled:cursor-not-allowed transition-colors font-medium"
+        >
+          {t.syncNow}
+        </button>
+      </div>
+      <p className="text-xs text-gray-400 dark:text-gray-500 mt-2">{t.scheduledSyncHelp}</p>
+      {lastRun && !lastRun.error && (
+        <p className="text-xs text-gray-500 dark:text-gray-400 mt-2">
+          {t.lastScheduledRun
+            .replace("{time}", runTime)
+            .replace("{albums}", String(lastRun.new_albums))
+            .replace("{images}", String(lastRun.new_images))}
+        </p>
+      )}
+      {lastRun?.error && (
+        <p className="text-xs text-red-600 dark:text-red-400 mt-2">
+          {t.scheduledRunFailed
+            .replace("{time}", runTime)
+            .replace("{error}", lastRun.error.message)}
+        </p>
+      )}
+      {error && <p className="text-xs text-red-600 dark:text-red-400 mt-2">{error}</p>}
+    </div>
+  );
+}
@@ -0,0 +1,99 @@
+import { useApp } from "../context/AppContext";
+import { useLanguage } from "../i18n/LanguageContext";
+import type { StorageConfig } from "../types";
+
+const inputClass =
+  "w-full px-3 py-2 bg-white dark:bg-gray-950 border border-gray-300 dark:border-gray-700 rounded-lg focus:outline-none focus:ring-2 focus:ring-blue-500 dark:focus:ring-blue-600 focus:border-transparent text-gray-900 dark:text-gray-100 text-sm";
+
+const defaults: Record<StorageConfig["kind"], StorageConfig> = {
+  local: { kind: "local" },
+  webdav: { kind: "webdav", url: "", username: null, password: null },
+  s3: {
+    kind: "s3",
+    endpoint: "",
+    bucket: "",
+    region: "us-east-1",
+    access_key: "",
+    secret_key: "",
+    prefix: "",
+  },
+};
+
+/** Where downloaded images are kept: the download folder, WebDAV or S3. */
+export default function StorageSettings() {
+  const { settings, setSettings } = useApp();
+  const { t } = useLanguage();
+  const storage = settings.storage ?? defaults.local;
+
+  const update = (changes: Partial<StorageConfig>) =>
+    setSettings({ ...settings, storage: { ...storage, ...changes } as StorageConfig });
+
+  const field = (
+    key: string,
+    label: string,
+    value: string | null,
+    type: "text" | "password" = "text",
+    placeholder?: string
+  ) => (
+    <input
+      type={type}
+      aria-label={label}
+      placeholder={placeholder ?? label}
+      value={value ?? ""}
+      onChange={(e) => update({ [key]: e.target.value } as Partial<StorageConfig>)}
+      className={inputClass}
+    />
+  );
+
+  return (
+    <div>
+      <label className="block text-sm font-medium text-gray-700 dark:text-gray-300 mb-2">
+        {t.storage}
+      </label>
+      <select
+        value={storage.kind}
+        onChange={(e) =>
+          setSettings({
+            ...settings,
+            storage: defaults[e.target.value as StorageConfig["kind"]],
+          })
+        }
+        className="w-full px-4 py-2.5 bg-white dark:bg-gray-950 border border-gray-300 dark:border-gray-700 rounded-lg focus:outline-none focus:ring-2 focus:ring-blue-500 dark:focus:ring-blue-600 focus:border-transparent text-gray-900 dark:text-gray-100"
+      >
+        <option value="local">{t.storageLocal}</option>
+        <option value="webdav">{t.storageWebdav}</option>
+        <option value="s3">{t.storageS3}</option>
+      </select>
+      {storage.kind === "webdav" && (
+        <div className="grid grid-cols-2 gap-2 mt-2">
+          <div className="col-span-2">
+            {field(
+              "url",
+              t.storageUrl,
+              storage.url,
+              "text",
+              "https://cloud.example.com/remote.php/dav/files/me/Kidplan"
+            )}
+          </div>
+          {field("username", t.storageUsername, storage.username)}
+          {field("password", t.storagePassword, storage.password, "password")}
+        </div>
+      )}
+      {storage.kind === "s3" && (
+        <div className="grid grid-cols-2 gap-2 mt-2">
+          <div className="col-span-2">
+            {field("endpoint", t.storageEndpoint, storage.endpoint, "text", "https://s3.example.com")}
+          </div>
+          {field("bucket", t.storageBucket, storage.bucket)}
+          {field("region", t.storageRegion, storage.region)}
+          {field("access_key", t.storageAccessKey, storage.access_key)}
+          {field("secret_key", t.storageSecretKey, storage.secret_key, "password")}
+          <div className="col-span-2">{field("prefix", t.storagePrefix, storage.prefix)}</div>
+        </div>
+      )}
+      <p className="text-xs text-gray-400 dark:text-gray-500 mt-2">
+        {storage.kind === "local" ? t.storageLocalHelp : t.storageRemoteHelp}
+      </p>
+    </div>
+  );
+}
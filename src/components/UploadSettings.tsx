@@ -0,0 +1,66 @@
+import { useApp } from "../context/AppContext";
+import { useLanguage } from "../i18n/LanguageContext";
+import type { UploadConfig } from "../types";
+
+const inputClass =
+  "w-full px-3 py-2 bg-white dark:bg-gray-950 border border-gray-300 dark:border-gray-700 rounded-lg focus:outline-none focus:ring-2 focus:ring-blue-500 dark:focus:ring-blue-600 focus:border-transparent text-gray-900 dark:text-gray-100 text-sm";
+
+/** Push saved photos into a self-hosted Immich or PhotoPrism library. */
+export default function UploadSettings() {
+  const { settings, setSettings } = useApp();
+  const { t } = useLanguage();
+  const upload = settings.upload ?? null;
+
+  const setUpload = (next: UploadConfig | null) => setSettings({ ...settings, upload: next });
+
+  const select = (kind: string) => {
+    if (kind === "immich") setUpload({ kind: "immich", url: upload?.url ?? "", api_key: "" });
+    else if (kind === "photoprism")
+      setUpload({ kind: "photoprism", url: upload?.url ?? "", token: "" });
+    else setUpload(null);
+  };
+
+  return (
+    <div>
+      <label className="block text-sm font-medium text-gray-700 dark:text-gray-300 mb-2">
+        {t.photoLibrary}
+      </label>
+      <select
+        value={upload?.kind ?? "none"}
+        onChange={(e) => select(e.target.value)}
+        className="w-full px-4 py-2.5 bg-white dark:bg-gray-950 border border-gray-300 dark:border-gray-700 rounded-lg focus:outline-none focus:ring-2 focus:ring-blue-500 dark:focus:ring-blue-600 focus:border-transparent text-gray-900 dark:text-gray-100"
+      >
+        <option value="none">{t.photoLibraryNone}</option>
+        <option value="immich">Immich</option>
+        <option value="photoprism">PhotoPrism</option>
+      </select>
+      {upload && (
+        <div className="grid grid-cols-2 gap-2 mt-2">
+          <input
+            type="text"
+            aria-label={t.photoLibraryUrl}
+            placeholder={upload.kind === "immich" ? "https://immich.example.com" : "https://photos.example.com"}
+            value={upload.url}
+            onChange={(e) => setUpload({ ...upload, url: e.target.value })}
+            className={inputClass}
+          />
+          <input
+            type="password"
+            aria-label={upload.kind === "immich" ? t.immichApiKey : t.photoprismToken}
+            placeholder={upload.kind === "immich" ? t.immichApiKey : t.photoprismToken}
+            value={upload.kind === "immich" ? upload.api_key : upload.token}
+            onChange={(e) =>
+              setUpload(
+                upload.kind === "immich"
+                  ? { ...upload, api_key: e.target.value }
+                  : { ...upload, token: e.target.value }
+              )
+            }
+            className={inputClass}
+          />
+        </div>
+      )}
+      <p className="text-xs text-gray-400 dark:text-gray-500 mt-2">{t.photoLibraryHelp}</p>
+    </div>
+  );
+}
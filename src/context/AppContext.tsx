@@ -10,11 +10,14 @@ import type {
   Credentials,
   Kindergarten,
   Album,
+  KindergartenAlbums,
   DownloadSettings,
   DownloadProgress,
   DownloadResult,
+  SyncSummary,
 } from "../types";
 import { checkForUpdates } from "../utils/updater";
+import { albumKey } from "../utils/albums";
 
 interface AppContextType {
   // Auth
@@ -24,10 +27,15 @@ interface AppContextType {
   setKindergartens: (kids: Kindergarten[]) => void;
   selectedKid: Kindergarten | null;
   setSelectedKid: (kid: Kindergarten | null) => void;
+  // Set when more than one kindergarten is chosen; albums are then keyed by albumKey
+  selectedKids: Kindergarten[];
+  setSelectedKids: (kids: Kindergarten[]) => void;
 
   // Albums
   albums: Album[];
   setAlbums: (albums: Album[]) => void;
+  albumGroups: KindergartenAlbums[];
+  setAlbumGroups: (groups: KindergartenAlbums[]) => void;
   selectedAlbumIds: Set<string>;
   toggleAlbum: (id: string) => void;
   selectAllAlbums: () => void;
@@ -45,6 +53,11 @@ interface AppContextType {
   setResult: (r: DownloadResult | null) => void;
   isDownloading: boolean;
   setIsDownloading: (v: boolean) => void;
+  // Sync mode downloads only albums that are new or changed since last time
+  syncMode: boolean;
+  setSyncMode: (v: boolean) => void;
+  syncSummary: SyncSummary | null;
+  setSyncSummary: (s: SyncSummary | null) => void;
 }
 
 const AppContext = createContext<AppContextType | null>(null);
@@ -53,7 +66,9 @@ export function AppProvider({ children }: { children: ReactNode }) {
   const [credentials, setCredentials] = useState<Credentials | null>(null);
   const [kindergartens, setKindergartens] = useState<Kindergarten[]>([]);
   const [selectedKid, setSelectedKid] = useState<Kindergarten | null>(null);
+  const [selectedKids, setSelectedKids] = useState<Kindergarten[]>([]);
   const [albums, setAlbums] = useState<Album[]>([]);
+  const [albumGroups, setAlbumGroups] = useState<KindergartenAlbums[]>([]);
   const [selectedAlbumIds, setSelectedAlbumIds] = useState<Set<string>>(
     new Set()
   );
@@ -61,10 +76,20 @@ export function AppProvider({ children }: { children: ReactNode }) {
     out_dir: "kidplan-albums",
     delay_ms: 200,
     limit_per_album: 0,
+    concurrency: 1,
+    duplicates: "skip",
+    path_template: null,
+    write_exif: false,
+    write_xmp: false,
+    zip: null,
+    storage: { kind: "local" },
+    upload: null,
   });
   const [progressLog, setProgressLog] = useState<DownloadProgress[]>([]);
   const [result, setResult] = useState<DownloadResult | null>(null);
   const [isDownloading, setIsDownloading] = useState(false);
+  const [syncMode, setSyncMode] = useState(false);
+  const [syncSummary, setSyncSummary] = useState<SyncSummary | null>(null);
 
   // Check for updates on app startup
   useEffect(() => {
@@ -89,8 +114,18 @@ export function AppProvider({ children }: { children: ReactNode }) {
   }, []);
 
   const selectAllAlbums = useCallback(() => {
-    setSelectedAlbumIds(new Set(albums.map((a) => a.id)));
-  }, [albums]);
+    if (albumGroups.length > 1) {
+      setSelectedAlbumIds(
+        new Set(
+          albumGroups.flatMap((g) =>
+            g.albums.map((a) => albumKey(g.kindergarten.id, a.id))
+          )
+        )
+      );
+    } else {
+      setSelectedAlbumIds(new Set(albums.map((a) => a.id)));
+    }
+  }, [albums, albumGroups]);
 
   const deselectAllAlbums = useCallback(() => {
     setSelectedAlbumIds(new Set());
@@ -113,8 +148,12 @@ export function AppProvider({ children }: { children: ReactNode }) {
         setKindergartens,
         selectedKid,
         setSelectedKid,
+        selectedKids,
+        setSelectedKids,
         albums,
         setAlbums,
+        albumGroups,
+        setAlbumGroups,
         selectedAlbumIds,
         toggleAlbum,
         selectAllAlbums,
@@ -128,6 +167,10 @@ export function AppProvider({ children }: { children: ReactNode }) {
         setResult,
         isDownloading,
         setIsDownloading,
+        syncMode,
+        setSyncMode,
+        syncSummary,
+        setSyncSummary,
       }}
     >
       {children}
@@ -31,6 +31,15 @@ export interface Translations {
   loading: string;
   fetchingAlbums: string;
   noAlbumsFound: string;
+  syncNew: string;
+  addToQueue: string;
+  addedToQueue: string; // "Added as job {id}"
+  logOut: string;
+  sortBy: string;
+  sortKidplan: string;
+  sortNewest: string;
+  sortOldest: string;
+  sortTitle: string;
   
   // Download page
   downloading: string;
@@ -41,16 +50,76 @@ export interface Translations {
   failed: string;
   backToAlbums: string;
   cancel: string;
+  pause: string;
+  resume: string;
+  paused: string;
   retryFailed: string;
   downloadAgain: string;
   startDownload: string;
   clickStartDownload: string;
   downloadFinished: string;
+  downloadCancelled: string;
   totalAlbums: string;
   totalImages: string;
   album: string;
   albumProgress: string; // "Album X/Y"
   albumsSelectedCount: string; // "X albums selected"
+  syncResult: string; // "X new albums, Y new photos"
+  queue: string;
+  noJobs: string;
+  jobTitle: string; // "Job {id}: {albums} albums"
+  syncJobTitle: string; // "Job {id}: sync"
+  jobQueued: string;
+  jobRunning: string;
+  jobCompleted: string;
+  jobFailed: string;
+  jobCancelled: string;
+  runNext: string;
+  clearFinished: string;
+  scheduledSync: string;
+  scheduledSyncHelp: string;
+  scheduledSyncAt: string;
+  syncNow: string;
+  lastScheduledRun: string;
+  scheduledRunFailed: string;
+  zipAfterDownload: string;
+  zipAfterDownloadHelp: string;
+  zipLevel: string;
+  zipLevelStored: string;
+  zipLevelFast: string;
+  zipLevelSmallest: string;
+  zipLayout: string;
+  zipPerAlbum: string;
+  zipCombined: string;
+  storage: string;
+  storageLocal: string;
+  storageWebdav: string;
+  storageS3: string;
+  storageUrl: string;
+  storageUsername: string;
+  storagePassword: string;
+  storageEndpoint: string;
+  storageBucket: string;
+  storageRegion: string;
+  storageAccessKey: string;
+  storageSecretKey: string;
+  storagePrefix: string;
+  storageLocalHelp: string;
+  storageRemoteHelp: string;
+  photoLibrary: string;
+  photoLibraryNone: string;
+  photoLibraryUrl: string;
+  immichApiKey: string;
+  photoprismToken: string;
+  photoLibraryHelp: string;
+  exportZip: string;
+  exportingZip: string;
+  exportedZip: string;
+  zipFailed: string;
+  createGallery: string;
+  createGalleryHelp: string;
+  creatingGallery: string;
+  createdGallery: string;
   
   // Settings page
   settingsTitle: string;
@@ -62,6 +131,20 @@ export interface Translations {
   delayHelp: string;
   maxImagesPerAlbum: string;
   maxImagesHelp: string;
+  parallelDownloads: string;
+  parallelDownloadsHelp: string;
+  duplicatePhotos: string;
+  duplicatePhotosHelp: string;
+  duplicatesSkip: string;
+  duplicatesHardlink: string;
+  duplicatesSymlink: string;
+  fileNameTemplate: string;
+  fileNameTemplateHelp: string;
+  templatePreview: string;
+  writeExif: string;
+  writeExifHelp: string;
+  writeXmp: string;
+  writeXmpHelp: string;
   language: string;
   languageHelp: string;
   checkForUpdates: string;
@@ -84,7 +167,7 @@ export const translations: Record<Language, Translations> = {
     rememberMe: "Remember my credentials",
     logIn: "Log In",
     loggingIn: "Logging in...",
-    multipleKindergartens: "Multiple kindergartens found. Select one:",
+    multipleKindergartens: "Multiple kindergartens found. Select one or more:",
     continue: "Continue",
     back: "Back",
     
@@ -104,6 +187,15 @@ export const translations: Record<Language, Translations> = {
     loading: "Loading...",
     fetchingAlbums: "Fetching albums...",
     noAlbumsFound: "No albums found",
+    syncNew: "Sync new",
+    addToQueue: "Add to queue",
+    addedToQueue: "Added as job {id}",
+    logOut: "Log out",
+    sortBy: "Sort",
+    sortKidplan: "As in Kidplan",
+    sortNewest: "Newest first",
+    sortOldest: "Oldest first",
+    sortTitle: "Title",
     
     // Download page
     downloading: "Downloading...",
@@ -114,16 +206,76 @@ export const translations: Record<Language, Translations> = {
     failed: "failed",
     backToAlbums: "Back to Albums",
     cancel: "Cancel",
+    pause: "Pause",
+    resume: "Resume",
+    paused: "Paused",
     retryFailed: "Retry Failed",
     downloadAgain: "Download Again",
     startDownload: "Start Download",
     clickStartDownload: "Click \"Start Download\" to begin",
     downloadFinished: "Download finished",
+    downloadCancelled: "Download cancelled",
     totalAlbums: "Albums",
     totalImages: "Downloaded",
     album: "Album",
     albumProgress: "Album",
     albumsSelectedCount: "albums selected",
+    syncResult: "{albums} new albums, {photos} new photos",
+    queue: "Queue",
+    noJobs: "No queued downloads",
+    jobTitle: "Job {id}: {albums} albums",
+    syncJobTitle: "Job {id}: sync",
+    jobQueued: "Queued",
+    jobRunning: "Running",
+    jobCompleted: "Completed",
+    jobFailed: "Failed",
+    jobCancelled: "Cancelled",
+    runNext: "Run next",
+    clearFinished: "Clear finished",
+    scheduledSync: "Sync every night",
+    scheduledSyncHelp: "Downloads new photos of the selected kindergartens to the folder above while the app is running. Uses the saved login.",
+    scheduledSyncAt: "Time",
+    syncNow: "Sync now",
+    lastScheduledRun: "Last sync {time}: {albums} new albums, {images} new photos",
+    scheduledRunFailed: "Sync {time} failed: {error}",
+    zipAfterDownload: "Pack albums as ZIP",
+    zipAfterDownloadHelp: "Creates ZIP archives in the download folder when a download finishes, e.g. to share an album as one file.",
+    zipLevel: "Compression",
+    zipLevelStored: "None (fastest; photos barely shrink)",
+    zipLevelFast: "Fast",
+    zipLevelSmallest: "Smallest",
+    zipLayout: "Archives",
+    zipPerAlbum: "One per album",
+    zipCombined: "One for all albums",
+    storage: "Save photos to",
+    storageLocal: "Download folder",
+    storageWebdav: "WebDAV (e.g. Nextcloud)",
+    storageS3: "S3-compatible storage",
+    storageUrl: "Folder URL",
+    storageUsername: "User name",
+    storagePassword: "Password",
+    storageEndpoint: "Endpoint",
+    storageBucket: "Bucket",
+    storageRegion: "Region",
+    storageAccessKey: "Access key",
+    storageSecretKey: "Secret key",
+    storagePrefix: "Folder in bucket (optional)",
+    storageLocalHelp: "Photos are saved in the download folder.",
+    photoLibrary: "Also add photos to",
+    photoLibraryNone: "No photo library",
+    photoLibraryUrl: "Server address",
+    immichApiKey: "API key",
+    photoprismToken: "App password",
+    photoLibraryHelp: "Each saved photo is uploaded into an album named after the Kidplan album. Photos the library already has are not sent again.",
+    storageRemoteHelp: "Photos are uploaded; the download folder keeps only the list of what was downloaded. ZIP packing needs the download folder.",
+    exportZip: "Export ZIP",
+    exportingZip: "Packing ZIP…",
+    exportedZip: "{count} ZIP archives written",
+    zipFailed: "The photos were downloaded, but packing them as ZIP failed: {error}",
+    createGallery: "Create gallery",
+    createGalleryHelp: "Write web pages of the downloaded albums into the download folder, which open in any browser. Copy the folder to share it.",
+    creatingGallery: "Creating gallery…",
+    createdGallery: "Gallery with {albums} albums and {photos} photos created",
     
     // Settings page
     settingsTitle: "Settings",
@@ -135,6 +287,20 @@ export const translations: Record<Language, Translations> = {
     delayHelp: "Adds a delay between image downloads to avoid overloading the server. Default: 200ms.",
     maxImagesPerAlbum: "Max images per album",
     maxImagesHelp: "Set to 0 for no limit.",
+    parallelDownloads: "Parallel downloads",
+    parallelDownloadsHelp: "Number of images downloaded at the same time (1–8). The delay above still applies across all of them.",
+    duplicatePhotos: "Duplicate photos",
+    duplicatePhotosHelp: "Photos whose content is already in the output folder, e.g. the same picture posted to two albums.",
+    duplicatesSkip: "Skip",
+    duplicatesHardlink: "Hardlink to existing file",
+    duplicatesSymlink: "Symlink to existing file",
+    fileNameTemplate: "File name template",
+    fileNameTemplateHelp: "Leave empty for the default layout. Placeholders: {album_title}, {album_slug}, {album_id}, {kindergarten}, {department}, {author}, {image_id}, {index}, {ext}, {year}, {month}, {day}, {date}. Use / for folders and {index:03} for zero padding.",
+    templatePreview: "Example paths",
+    writeExif: "Write date and album into photos (EXIF)",
+    writeExifHelp: "Adds the album date, album title and kindergarten name to each JPEG so photo libraries sort them by when they were taken. Dates already in the photo are kept.",
+    writeXmp: "Write XMP sidecar files",
+    writeXmpHelp: "Saves album, kindergarten, caption and Kidplan ids in a .xmp file next to each photo for darktable, digiKam and similar tools. The photos themselves are not changed.",
     language: "Language",
     languageHelp: "Choose your preferred language",
     checkForUpdates: "Check for Updates",
@@ -155,7 +321,7 @@ export const translations: Record<Language, Translations> = {
     rememberMe: "Husk legitimasjon",
     logIn: "Logg inn",
     loggingIn: "Logger inn...",
-    multipleKindergartens: "Flere barnehager funnet. Velg en:",
+    multipleKindergartens: "Flere barnehager funnet. Velg en eller flere:",
     continue: "Fortsett",
     back: "Tilbake",
     
@@ -175,6 +341,15 @@ export const translations: Record<Language, Translations> = {
     loading: "Laster...",
     fetchingAlbums: "Henter album...",
     noAlbumsFound: "Ingen album funnet",
+    syncNew: "Synkroniser nye",
+    addToQueue: "Legg i kø",
+    addedToQueue: "Lagt til som jobb {id}",
+    logOut: "Logg ut",
+    sortBy: "Sorter",
+    sortKidplan: "Som i Kidplan",
+    sortNewest: "Nyeste først",
+    sortOldest: "Eldste først",
+    sortTitle: "Tittel",
     
     // Download page
     downloading: "Laster ned...",
@@ -185,16 +360,76 @@ export const translations: Record<Language, Translations> = {
     failed: "feilet",
     backToAlbums: "Tilbake til album",
     cancel: "Avbryt",
+    pause: "Pause",
+    resume: "Fortsett",
+    paused: "Pauset",
     retryFailed: "Prøv feilede på nytt",
     downloadAgain: "Last ned på nytt",
     startDownload: "Start nedlasting",
     clickStartDownload: "Klikk \"Start nedlasting\" for å begynne",
     downloadFinished: "Nedlasting fullført",
+    downloadCancelled: "Nedlasting avbrutt",
     totalAlbums: "Album",
     totalImages: "Lastet ned",
     album: "Album",
     albumProgress: "Album",
     albumsSelectedCount: "album valgt",
+    syncResult: "{albums} nye album, {photos} nye bilder",
+    queue: "Kø",
+    noJobs: "Ingen nedlastinger i kø",
+    jobTitle: "Jobb {id}: {albums} album",
+    syncJobTitle: "Jobb {id}: synkronisering",
+    jobQueued: "I kø",
+    jobRunning: "Kjører",
+    jobCompleted: "Fullført",
+    jobFailed: "Feilet",
+    jobCancelled: "Avbrutt",
+    runNext: "Kjør neste",
+    clearFinished: "Fjern ferdige",
+    scheduledSync: "Synkroniser hver natt",
+    scheduledSyncHelp: "Laster ned nye bilder fra de valgte barnehagene til mappen over mens appen kjører. Bruker den lagrede innloggingen.",
+    scheduledSyncAt: "Tidspunkt",
+    syncNow: "Synkroniser nå",
+    lastScheduledRun: "Siste synkronisering {time}: {albums} nye album, {images} nye bilder",
+    scheduledRunFailed: "Synkronisering {time} feilet: {error}",
+    zipAfterDownload: "Pakk album som ZIP",
+    zipAfterDownloadHelp: "Lager ZIP-arkiver i nedlastingsmappen når en nedlasting er ferdig, f.eks. for å dele et album som én fil.",
+    zipLevel: "Komprimering",
+    zipLevelStored: "Ingen (raskest; bilder blir knapt mindre)",
+    zipLevelFast: "Rask",
+    zipLevelSmallest: "Minst",
+    zipLayout: "Arkiver",
+    zipPerAlbum: "Ett per album",
+    zipCombined: "Ett for alle album",
+    storage: "Lagre bilder i",
+    storageLocal: "Nedlastingsmappen",
+    storageWebdav: "WebDAV (f.eks. Nextcloud)",
+    storageS3: "S3-kompatibel lagring",
+    storageUrl: "Mappe-URL",
+    storageUsername: "Brukernavn",
+    storagePassword: "Passord",
+    storageEndpoint: "Endepunkt",
+    storageBucket: "Bøtte",
+    storageRegion: "Region",
+    storageAccessKey: "Tilgangsnøkkel",
+    storageSecretKey: "Hemmelig nøkkel",
+    storagePrefix: "Mappe i bøtten (valgfritt)",
+    storageLocalHelp: "Bildene lagres i nedlastingsmappen.",
+    photoLibrary: "Legg også bildene i",
+    photoLibraryNone: "Ingen bildebibliotek",
+    photoLibraryUrl: "Serveradresse",
+    immichApiKey: "API-nøkkel",
+    photoprismToken: "Apppassord",
+    photoLibraryHelp: "Hvert lagrede bilde lastes opp til et album med samme navn som Kidplan-albumet. Bilder biblioteket allerede har, sendes ikke på nytt.",
+    storageRemoteHelp: "Bildene lastes opp; nedlastingsmappen beholder bare oversikten over hva som er lastet ned. ZIP-pakking krever nedlastingsmappen.",
+    exportZip: "Eksporter ZIP",
+    exportingZip: "Pakker ZIP…",
+    exportedZip: "{count} ZIP-arkiver skrevet",
+    zipFailed: "Bildene ble lastet ned, men pakkingen som ZIP feilet: {error}",
+    createGallery: "Lag galleri",
+    createGalleryHelp: "Skriv nettsider av de nedlastede albumene i nedlastingsmappen, som kan åpnes i hvilken som helst nettleser. Kopier mappen for å dele den.",
+    creatingGallery: "Lager galleri…",
+    createdGallery: "Galleri med {albums} album og {photos} bilder laget",
     
     // Settings page
     settingsTitle: "Innstillinger",
@@ -206,6 +441,20 @@ export const translations: Record<Language, Translations> = {
     delayHelp: "Legger til en forsinkelse mellom bildene for å unngå å overbelaste serveren. Standard: 200ms.",
     maxImagesPerAlbum: "Maks bilder per album",
     maxImagesHelp: "Sett til 0 for ingen grense.",
+    parallelDownloads: "Parallelle nedlastinger",
+    parallelDownloadsHelp: "Antall bilder som lastes ned samtidig (1–8). Forsinkelsen over gjelder fortsatt på tvers av alle.",
+    duplicatePhotos: "Dupliserte bilder",
+    duplicatePhotosHelp: "Bilder som allerede finnes i mappen, f.eks. samme bilde lagt ut i to album.",
+    duplicatesSkip: "Hopp over",
+    duplicatesHardlink: "Hardlenke til eksisterende fil",
+    duplicatesSymlink: "Symlenke til eksisterende fil",
+    fileNameTemplate: "Mal for filnavn",
+    fileNameTemplateHelp: "La stå tom for standard oppsett. Plassholdere: {album_title}, {album_slug}, {album_id}, {kindergarten}, {department}, {author}, {image_id}, {index}, {ext}, {year}, {month}, {day}, {date}. Bruk / for mapper og {index:03} for å fylle ut med nuller.",
+    templatePreview: "Eksempelstier",
+    writeExif: "Skriv dato og album inn i bildene (EXIF)",
+    writeExifHelp: "Legger albumdato, albumtittel og barnehagenavn inn i hver JPEG, slik at bildebiblioteker sorterer dem etter når de ble tatt. Datoer som allerede finnes i bildet beholdes.",
+    writeXmp: "Skriv XMP-sidefiler",
+    writeXmpHelp: "Lagrer album, barnehage, bildetekst og Kidplan-ID-er i en .xmp-fil ved siden av hvert bilde for darktable, digiKam og lignende. Selve bildene endres ikke.",
     language: "Språk",
     languageHelp: "Velg ditt foretrukne språk",
     checkForUpdates: "Se etter oppdateringer",
@@ -1,26 +1,83 @@
-import { useState, useEffect } from "react";
+import { useState, useEffect, useMemo } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { open } from "@tauri-apps/plugin-dialog";
+import { revealItemInDir } from "@tauri-apps/plugin-opener";
 import { useNavigate } from "react-router-dom";
 import { useApp } from "../context/AppContext";
 import { useLanguage } from "../i18n/LanguageContext";
-import type { Album } from "../types";
+import type { Album, AlbumSort, Gallery, KindergartenAlbums } from "../types";
+import AlbumCover from "../components/AlbumCover";
+import { albumKey } from "../utils/albums";
+import { errorMessage } from "../utils/errors";
 
 export default function AlbumsPage() {
   const {
     albums,
     setAlbums,
+    albumGroups,
+    setAlbumGroups,
+    credentials,
+    selectedKids,
+    setSelectedKid,
+    setSelectedKids,
+    setKindergartens,
     selectedAlbumIds,
     toggleAlbum,
     selectAllAlbums,
     deselectAllAlbums,
+    setSyncMode,
+    settings,
   } = useApp();
   
   const navigate = useNavigate();
   
-  const { t } = useLanguage();
+  const { t, language } = useLanguage();
 
   const [loading, setLoading] = useState(false);
   const [error, setError] = useState("");
+  const [sort, setSort] = useState<AlbumSort>("kidplan");
+  const [notice, setNotice] = useState("");
+
+  const sortAlbums = (list: Album[]) => {
+    if (sort === "kidplan") return list;
+    const sorted = [...list];
+    if (sort === "title") {
+      sorted.sort((a, b) => a.title.localeCompare(b.title, language));
+    } else {
+      // Undated albums go last either way
+      const direction = sort === "newest" ? -1 : 1;
+      sorted.sort((a, b) => {
+        if (!a.date || !b.date) return (a.date ? 0 : 1) - (b.date ? 0 : 1);
+        return direction * a.date.localeCompare(b.date);
+      });
+    }
+    return sorted;
+  };
+
+  // One section per kindergarten when several were chosen at login
+  const sections = useMemo(
+    () =>
+      albumGroups.length > 1
+        ? albumGroups.map((g) => ({
+            kindergarten: g.kindergarten,
+            albums: sortAlbums(g.albums),
+            key: (a: Album) => albumKey(g.kindergarten.id, a.id),
+          }))
+        : [{ kindergarten: null, albums: sortAlbums(albums), key: (a: Album) => a.id }],
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+    [albums, albumGroups, sort, language]
+  );
+  const multiKindergarten = albumGroups.length > 1;
+
+  const albumDetails = (album: Album) =>
+    [
+      album.date &&
+        new Date(album.date).toLocaleDateString(language === "no" ? "nb-NO" : "en-GB"),
+      album.department,
+      album.author,
+    ]
+      .filter(Boolean)
+      .join(" \u00B7 ");
 
   useEffect(() => {
     if (albums.length > 0) return;
@@ -32,17 +89,130 @@ export default function AlbumsPage() {
     setLoading(true);
     setError("");
     try {
-      const result = await invoke<Album[]>("fetch_albums");
-      setAlbums(result);
+      if (selectedKids.length > 1 && credentials) {
+        const groups = await invoke<KindergartenAlbums[]>("fetch_kindergarten_albums", {
+          credentials,
+          kidIds: selectedKids.map((k) => k.id),
+        });
+        setAlbumGroups(groups);
+        setAlbums(groups.flatMap((g) => g.albums));
+      } else {
+        const result = await invoke<Album[]>("fetch_albums");
+        setAlbums(result);
+      }
     } catch (err) {
-      setError(String(err));
+      setError(errorMessage(err));
     }
     setLoading(false);
   };
 
+  const handleLogout = async () => {
+    try {
+      await invoke("logout");
+    } catch (err) {
+      console.error("Logout failed:", err);
+    }
+    setAlbums([]);
+    setAlbumGroups([]);
+    deselectAllAlbums();
+    setSelectedKid(null);
+    setSelectedKids([]);
+    setKindergartens([]);
+    navigate("/login");
+  };
+
+  const handleEnqueue = async () => {
+    try {
+      const jobId = await invoke<number>("enqueue_download", {
+        albums: albums.filter((a) => selectedAlbumIds.has(a.id)),
+        settings,
+      });
+      setNotice(t.addedToQueue.replace("{id}", String(jobId)));
+      deselectAllAlbums();
+    } catch (err) {
+      setError(errorMessage(err));
+    }
+  };
+
+  const handleExportZip = async () => {
+    const dest = await open({ directory: true, multiple: false, title: t.exportZip });
+    if (!dest || typeof dest !== "string") return;
+    setNotice(t.exportingZip);
+    try {
+      const archives = await invoke<string[]>("export_zip", {
+        albums: albums.filter((a) => selectedAlbumIds.has(a.id)),
+        outDir: settings.out_dir,
+        zip: settings.zip ?? { level: 0, layout: "per_album" },
+        destDir: dest,
+      });
+      setNotice(t.exportedZip.replace("{count}", String(archives.length)));
+    } catch (err) {
+      setNotice("");
+      setError(errorMessage(err));
+    }
+  };
+
+  const handleGallery = async () => {
+    setNotice(t.creatingGallery);
+    try {
+      const gallery = await invoke<Gallery>("generate_gallery", {
+        outDir: settings.out_dir,
+        language,
+      });
+      setNotice(
+        t.createdGallery
+          .replace("{albums}", String(gallery.albums))
+          .replace("{photos}", String(gallery.images))
+      );
+      await revealItemInDir(gallery.index);
+    } catch (err) {
+      setNotice("");
+      setError(errorMessage(err));
+    }
+  };
+
   const selectedCount = selectedAlbumIds.size;
   const allSelected = selectedCount === albums.length && albums.length > 0;
 
+  const renderAlbum = (album: Album, key: string) => (
+    <label
+      key={key}
+      className={`flex items-center p-4 bg-white dark:bg-gray-900 border rounded-lg cursor-pointer transition-all ${
+        selectedAlbumIds.has(key)
+          ? "border-blue-500 dark:border-blue-600 bg-blue-50 dark:bg-blue-950/30"
+          : "border-gray-200 dark:border-gray-800 hover:border-gray-300 dark:hover:border-gray-700"
+      }`}
+    >
+      <input
+        type="checkbox"
+        checked={selectedAlbumIds.has(key)}
+        onChange={() => toggleAlbum(key)}
+        className="mr-3 h-4 w-4 rounded border-gray-300 dark:border-gray-700 text-blue-600 focus:ring-blue-500"
+      />
+      <AlbumCover album={album} />
+      <div className="flex-1 min-w-0">
+        <span className="text-gray-900 dark:text-gray-100 text-sm font-medium truncate block">
+          {album.title}
+        </span>
+        {albumDetails(album) && (
+          <span className="text-gray-500 dark:text-gray-400 text-xs truncate block">
+            {albumDetails(album)}
+          </span>
+        )}
+        {album.description && (
+          <span className="text-gray-400 dark:text-gray-500 text-xs truncate block">
+            {album.description}
+          </span>
+        )}
+      </div>
+      {album.image_count != null && (
+        <span className="text-gray-400 dark:text-gray-500 text-xs ml-2 shrink-0">
+          {album.image_count} {t.images}
+        </span>
+      )}
+    </label>
+  );
+
   return (
     <div className="flex flex-col h-screen bg-gray-50 dark:bg-gray-950">
       {/* Header */}
@@ -53,9 +223,16 @@ export default function AlbumsPage() {
             <p className="text-sm text-gray-500 dark:text-gray-400">
               {albums.length} {t.albumsFound}
               {selectedCount > 0 && ` \u00B7 ${selectedCount} ${t.selected}`}
+              {notice && ` \u00B7 ${notice}`}
             </p>
           </div>
           <div className="flex gap-2">
+            <button
+              onClick={handleLogout}
+              className="px-4 py-2 text-sm text-gray-600 dark:text-gray-400 border border-gray-300 dark:border-gray-700 rounded-lg hover:bg-gray-50 dark:hover:bg-gray-800 transition-colors"
+            >
+              {t.logOut}
+            </button>
             <button
               onClick={() => navigate("/settings")}
               className="px-4 py-2 text-sm text-gray-600 dark:text-gray-400 border border-gray-300 dark:border-gray-700 rounded-lg hover:bg-gray-50 dark:hover:bg-gray-800 transition-colors"
@@ -63,7 +240,41 @@ export default function AlbumsPage() {
               {t.settings}
             </button>
             <button
-              onClick={() => navigate("/download")}
+              onClick={() => {
+                setSyncMode(true);
+                navigate("/download");
+              }}
+              disabled={albums.length === 0 || multiKindergarten}
+              className="px-4 py-2 text-sm text-blue-600 dark:text-blue-500 border border-blue-300 dark:border-blue-800 rounded-lg hover:bg-blue-50 dark:hover:bg-blue-950/30 disabled:opacity-50 disabled:cursor-not-allowed transition-colors"
+            >
+              {t.syncNew}
+            </button>
+            <button
+              onClick={handleEnqueue}
+              disabled={selectedCount === 0 || multiKindergarten}
+              className="px-4 py-2 text-sm text-blue-600 dark:text-blue-500 border border-blue-300 dark:border-blue-800 rounded-lg hover:bg-blue-50 dark:hover:bg-blue-950/30 disabled:opacity-50 disabled:cursor-not-allowed transition-colors"
+            >
+              {t.addToQueue}
+            </button>
+            <button
+              onClick={handleExportZip}
+              disabled={selectedCount === 0 || multiKindergarten}
+              className="px-4 py-2 text-sm text-blue-600 dark:text-blue-500 border border-blue-300 dark:border-blue-800 rounded-lg hover:bg-blue-50 dark:hover:bg-blue-950/30 disabled:opacity-50 disabled:cursor-not-allowed transition-colors"
+            >
+              {t.exportZip}
+            </button>
+            <button
+              onClick={handleGallery}
+              title={t.createGalleryHelp}
+              className="px-4 py-2 text-sm text-blue-600 dark:text-blue-500 border border-blue-300 dark:border-blue-800 rounded-lg hover:bg-blue-50 dark:hover:bg-blue-950/30 transition-colors"
+            >
+              {t.createGallery}
+            </button>
+            <button
+              onClick={() => {
+                setSyncMode(false);
+                navigate("/download");
+              }}
               disabled={selectedCount === 0}
               className="px-4 py-2 text-sm bg-blue-600 dark:bg-blue-600 text-white rounded-lg hover:bg-blue-700 dark:hover:bg-blue-700 disabled:opacity-50 disabled:cursor-not-allowed transition-colors font-medium"
             >
@@ -90,6 +301,19 @@ export default function AlbumsPage() {
         >
           {loading ? t.loading : t.refresh}
         </button>
+        <label className="ml-auto flex items-center gap-2 text-sm text-gray-500 dark:text-gray-400">
+          {t.sortBy}
+          <select
+            value={sort}
+            onChange={(e) => setSort(e.target.value as AlbumSort)}
+            className="px-2 py-1 border border-gray-300 dark:border-gray-700 rounded-lg bg-white dark:bg-gray-800 text-gray-700 dark:text-gray-300"
+          >
+            <option value="kidplan">{t.sortKidplan}</option>
+            <option value="newest">{t.sortNewest}</option>
+            <option value="oldest">{t.sortOldest}</option>
+            <option value="title">{t.sortTitle}</option>
+          </select>
+        </label>
       </div>
 
       {/* Error */}
@@ -117,35 +341,18 @@ export default function AlbumsPage() {
       )}
 
       <div className="flex-1 overflow-y-auto px-6 py-4">
-        <div className="space-y-2">
-          {albums.map((album) => (
-            <label
-              key={album.id}
-              className={`flex items-center p-4 bg-white dark:bg-gray-900 border rounded-lg cursor-pointer transition-all ${
-                selectedAlbumIds.has(album.id)
-                  ? "border-blue-500 dark:border-blue-600 bg-blue-50 dark:bg-blue-950/30"
-                  : "border-gray-200 dark:border-gray-800 hover:border-gray-300 dark:hover:border-gray-700"
-              }`}
-            >
-              <input
-                type="checkbox"
-                checked={selectedAlbumIds.has(album.id)}
-                onChange={() => toggleAlbum(album.id)}
-                className="mr-3 h-4 w-4 rounded border-gray-300 dark:border-gray-700 text-blue-600 focus:ring-blue-500"
-              />
-              <div className="flex-1 min-w-0">
-                <span className="text-gray-900 dark:text-gray-100 text-sm font-medium truncate block">
-                  {album.title}
-                </span>
-              </div>
-              {album.image_count != null && (
-                <span className="text-gray-400 dark:text-gray-500 text-xs ml-2 shrink-0">
-                  {album.image_count} {t.images}
-                </span>
-              )}
-            </label>
-          ))}
-        </div>
+        {sections.map((section) => (
+          <div key={section.kindergarten?.id ?? "albums"} className="mb-4">
+            {section.kindergarten && (
+              <h2 className="text-sm font-semibold text-gray-700 dark:text-gray-300 mb-2">
+                {section.kindergarten.name}
+              </h2>
+            )}
+            <div className="space-y-2">
+              {section.albums.map((album) => renderAlbum(album, section.key(album)))}
+            </div>
+          </div>
+        ))}
       </div>
     </div>
   );
@@ -1,14 +1,19 @@
-import { useEffect, useRef } from "react";
+import { useEffect, useRef, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import { useNavigate } from "react-router-dom";
 import { useApp } from "../context/AppContext";
 import { useLanguage } from "../i18n/LanguageContext";
-import type { DownloadProgress, DownloadResult } from "../types";
+import type { DownloadProgress, DownloadResult, KindergartenAlbums, SyncSummary } from "../types";
+import JobQueue from "../components/JobQueue";
+import { albumKey } from "../utils/albums";
+import { errorMessage, isAppError } from "../utils/errors";
 
 export default function DownloadPage() {
   const {
     albums,
+    albumGroups,
+    credentials,
     selectedAlbumIds,
     settings,
     progressLog,
@@ -18,12 +23,16 @@ export default function DownloadPage() {
     setResult,
     isDownloading,
     setIsDownloading,
+    syncMode,
+    syncSummary,
+    setSyncSummary,
   } = useApp();
 
   const navigate = useNavigate();
 
   const { t } = useLanguage();
 
+  const [isPaused, setIsPaused] = useState(false);
   const logEndRef = useRef<HTMLDivElement>(null);
   const hasAutoStarted = useRef(false);
 
@@ -33,16 +42,25 @@ export default function DownloadPage() {
 
   // Auto-start download on page load if albums are selected
   useEffect(() => {
-    if (!hasAutoStarted.current && selectedAlbumIds.size > 0 && !isDownloading && !result) {
+    if (!hasAutoStarted.current && (syncMode || selectedAlbumIds.size > 0) && !isDownloading && !result) {
       hasAutoStarted.current = true;
       handleStart();
     }
   }, []);
 
   const handleStart = async () => {
-    const selectedAlbums = albums.filter((a) => selectedAlbumIds.has(a.id));
+    const multiKindergarten = albumGroups.length > 1;
+    const selections: KindergartenAlbums[] = albumGroups
+      .map((g) => ({
+        kindergarten: g.kindergarten,
+        albums: g.albums.filter((a) => selectedAlbumIds.has(albumKey(g.kindergarten.id, a.id))),
+      }))
+      .filter((g) => g.albums.length > 0);
+    const selectedAlbums = multiKindergarten
+      ? selections.flatMap((g) => g.albums)
+      : albums.filter((a) => selectedAlbumIds.has(a.id));
     console.log("[FRONTEND] handleStart called, selectedAlbums:", selectedAlbums.length);
-    if (selectedAlbums.length === 0) {
+    if (!syncMode && selectedAlbums.length === 0) {
       console.log("[FRONTEND] No albums, returning early");
       return;
     }
@@ -50,6 +68,8 @@ export default function DownloadPage() {
     console.log("[FRONTEND] Clearing progress and setting isDownloading=true");
     clearProgress();
     setResult(null);
+    setSyncSummary(null);
+    setIsPaused(false);
     setIsDownloading(true);
 
     console.log("[FRONTEND] About to listen for download-progress events...");
@@ -58,6 +78,9 @@ export default function DownloadPage() {
       (event) => {
         console.log("[FRONTEND] Progress event received:", event.payload);
         addProgress(event.payload);
+        if (event.payload.result) {
+          setResult(event.payload.result);
+        }
       }
     );
     console.log("[FRONTEND] Event listener registered");
@@ -65,18 +88,33 @@ export default function DownloadPage() {
     try {
       console.log("[FRONTEND] About to invoke start_download with albums:", selectedAlbums.map(a => ({id: a.id, title: a.title})));
       console.log("[FRONTEND] Settings:", settings);
-      const res = await invoke<DownloadResult>("start_download", {
-        albums: selectedAlbums,
-        settings,
-      });
-      console.log("[FRONTEND] invoke returned:", res);
-      setResult(res);
+      if (syncMode) {
+        const summary = await invoke<SyncSummary>("start_sync", { settings });
+        console.log("[FRONTEND] start_sync returned:", summary);
+        setSyncSummary(summary);
+        setResult(summary.result);
+      } else if (multiKindergarten) {
+        const res = await invoke<DownloadResult>("start_multi_download", {
+          credentials,
+          selections,
+          settings,
+        });
+        setResult(res);
+      } else {
+        const res = await invoke<DownloadResult>("start_download", {
+          albums: selectedAlbums,
+          settings,
+        });
+        console.log("[FRONTEND] invoke returned:", res);
+        setResult(res);
+      }
     } catch (err) {
       console.error("[FRONTEND] invoke threw error:", err);
       setResult({
         total_albums: selectedAlbums.length,
         total_images: 0,
         skipped: 0,
+        duplicates: 0,
         failed: 0,
       });
       addProgress({
@@ -86,15 +124,28 @@ export default function DownloadPage() {
         image_index: 0,
         image_total: 0,
         filename: "",
-        status: `Error: ${err}`,
+        status: `Error: ${errorMessage(err)}`,
+        error_type: isAppError(err) ? err.code : undefined,
       });
     } finally {
       console.log("[FRONTEND] invoke completed (success or error), cleaning up");
       setIsDownloading(false);
+      setIsPaused(false);
       unlisten();
     }
   };
 
+  const handlePauseResume = async () => {
+    const command = isPaused ? "resume_download" : "pause_download";
+    console.log("[FRONTEND]", command);
+    try {
+      await invoke(command);
+      setIsPaused(!isPaused);
+    } catch (err) {
+      console.error(`[FRONTEND] ${command} error:`, err);
+    }
+  };
+
   const handleCancel = async () => {
     console.log("[FRONTEND] Cancel clicked");
     try {
@@ -127,7 +178,11 @@ export default function DownloadPage() {
           <div>
             <h1 className="text-xl font-semibold text-gray-900 dark:text-gray-100">
               {isDownloading
-                ? t.downloading
+                ? isPaused
+                  ? t.paused
+                  : t.downloading
+                : result?.cancelled
+                ? t.downloadCancelled
                 : result
                 ? t.downloadComplete
                 : t.readyToDownload}
@@ -148,12 +203,20 @@ export default function DownloadPage() {
               {t.backToAlbums}
             </button>
             {isDownloading ? (
-              <button
-                onClick={handleCancel}
-                className="px-4 py-2 text-sm bg-red-600 dark:bg-red-600 text-white rounded-lg hover:bg-red-700 dark:hover:bg-red-700 transition-colors font-medium"
-              >
-                {t.cancel}
-              </button>
+              <>
+                <button
+                  onClick={handlePauseResume}
+                  className="px-4 py-2 text-sm text-gray-700 dark:text-gray-300 border border-gray-300 dark:border-gray-700 rounded-lg hover:bg-gray-50 dark:hover:bg-gray-800 transition-colors font-medium"
+                >
+                  {isPaused ? t.resume : t.pause}
+                </button>
+                <button
+                  onClick={handleCancel}
+                  className="px-4 py-2 text-sm bg-red-600 dark:bg-red-600 text-white rounded-lg hover:bg-red-700 dark:hover:bg-red-700 transition-colors font-medium"
+                >
+                  {t.cancel}
+                </button>
+              </>
             ) : (
               <>
                 {failedCount > 0 && result && (
@@ -166,7 +229,7 @@ export default function DownloadPage() {
                 )}
                 <button
                   onClick={handleStart}
-                  disabled={selectedAlbumIds.size === 0}
+                  disabled={!syncMode && selectedAlbumIds.size === 0}
                   className="px-4 py-2 text-sm bg-blue-600 dark:bg-blue-600 text-white rounded-lg hover:bg-blue-700 dark:hover:bg-blue-700 disabled:opacity-50 disabled:cursor-not-allowed transition-colors font-medium"
                 >
                   {result ? t.downloadAgain : t.startDownload}
@@ -190,7 +253,9 @@ export default function DownloadPage() {
           </div>
           <div className="w-full bg-gray-200 dark:bg-gray-800 rounded-full h-2">
             <div
-              className="bg-blue-600 dark:bg-blue-500 h-2 rounded-full transition-all duration-300"
+              className={`${
+                isPaused ? "bg-gray-400 dark:bg-gray-600" : "bg-blue-600 dark:bg-blue-500"
+              } h-2 rounded-full transition-all duration-300`}
               style={{
                 width: `${
                   lastProgress.album_total > 0
@@ -204,18 +269,32 @@ export default function DownloadPage() {
         </div>
       )}
 
+      <JobQueue />
+
       {/* Result summary */}
       {result && !isDownloading && (
         <div className="mx-6 mt-4 bg-green-50 dark:bg-green-950/50 border border-green-200 dark:border-green-900 rounded-lg p-4">
           <h3 className="font-medium text-green-800 dark:text-green-400 mb-2">
-            {t.downloadFinished}
+            {result.cancelled ? t.downloadCancelled : t.downloadFinished}
           </h3>
+          {syncSummary && (
+            <p className="text-sm text-green-700 dark:text-green-500 mb-2">
+              {t.syncResult
+                .replace("{albums}", String(syncSummary.new_albums))
+                .replace("{photos}", String(syncSummary.new_images))}
+            </p>
+          )}
           <div className="grid grid-cols-2 gap-2 text-sm text-green-700 dark:text-green-500">
             <div>{t.totalAlbums}: {result.total_albums}</div>
             <div>{t.totalImages}: {result.total_images}</div>
             <div>{t.skipped.charAt(0).toUpperCase() + t.skipped.slice(1)}: {result.skipped}</div>
             <div>{t.failed.charAt(0).toUpperCase() + t.failed.slice(1)}: {result.failed}</div>
           </div>
+          {result.export_error && (
+            <p className="text-sm text-red-600 dark:text-red-500 mt-2">
+              {t.zipFailed.replace("{error}", result.export_error)}
+            </p>
+          )}
         </div>
       )}
 
@@ -241,7 +320,11 @@ export default function DownloadPage() {
                 ? "text-yellow-600 dark:text-yellow-500"
                 : p.status.startsWith("failed")
                 ? "text-red-600 dark:text-red-500"
-                : p.status.startsWith("scanning")
+                : p.paused || p.status === "cancelled"
+                ? "text-gray-500 dark:text-gray-400 italic"
+                : p.status.startsWith("scanning") ||
+                  p.status === "zipped" ||
+                  p.status.startsWith("uploaded")
                 ? "text-blue-600 dark:text-blue-500 font-medium"
                 : "text-gray-600 dark:text-gray-400"
               }`}
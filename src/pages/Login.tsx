@@ -4,15 +4,19 @@ import { load, Store } from "@tauri-apps/plugin-store";
 import { useNavigate } from "react-router-dom";
 import { useApp } from "../context/AppContext";
 import { useLanguage } from "../i18n/LanguageContext";
-import type { Kindergarten } from "../types";
+import type { Kindergarten, RestoredSession } from "../types";
+import { errorMessage } from "../utils/errors";
 
 export default function LoginPage() {
   const {
     setCredentials,
     setKindergartens,
     kindergartens,
-    selectedKid,
     setSelectedKid,
+    setSelectedKids,
+    setAlbums,
+    setAlbumGroups,
+    deselectAllAlbums,
   } = useApp();
   
   const navigate = useNavigate();
@@ -25,8 +29,10 @@ export default function LoginPage() {
   const [error, setError] = useState("");
   const [loading, setLoading] = useState(false);
   const [step, setStep] = useState<"credentials" | "select_kid">("credentials");
+  const [chosenKidIds, setChosenKidIds] = useState<Set<number>>(new Set());
 
-  // Load saved credentials on mount
+  // Load saved credentials on mount, and skip the login entirely when the
+  // session from the last launch is still valid
   useEffect(() => {
     const loadSavedCredentials = async () => {
       try {
@@ -37,12 +43,27 @@ export default function LoginPage() {
           setEmail(savedEmail);
           setPassword(savedPassword);
           setRememberMe(true);
+          setCredentials({ email: savedEmail, password: savedPassword });
         }
       } catch (err) {
         console.log("No saved credentials found");
       }
     };
-    loadSavedCredentials();
+    const restoreSession = async () => {
+      try {
+        const session = await invoke<RestoredSession | null>("restore_session");
+        if (session?.kindergarten) {
+          setKindergartens(session.kindergartens);
+          setSelectedKid(session.kindergarten);
+          setSelectedKids([session.kindergarten]);
+          navigate("/albums");
+        }
+      } catch (err) {
+        console.log("Could not restore session:", err);
+      }
+    };
+    loadSavedCredentials().then(restoreSession);
+    // eslint-disable-next-line react-hooks/exhaustive-deps
   }, []);
 
   const saveCredentials = async (store: Store) => {
@@ -77,37 +98,54 @@ export default function LoginPage() {
       }
       
       if (kids.length === 1) {
-        setSelectedKid(kids[0]);
-        await doLogin(kids[0].id);
+        await doLogin(kids);
       } else {
         setStep("select_kid");
         setLoading(false);
       }
     } catch (err) {
-      setError(String(err));
+      setError(errorMessage(err));
       setLoading(false);
     }
   };
 
-  const doLogin = async (kidId: number) => {
+  // The session is opened for the first kindergarten; the others are logged
+  // in to as needed when fetching and downloading their albums
+  const doLogin = async (kids: Kindergarten[]) => {
     setLoading(true);
     setError("");
     try {
       await invoke("login", {
         credentials: { email, password },
-        kidId,
+        kidId: kids[0].id,
+        remember: rememberMe,
       });
+      setSelectedKid(kids[0]);
+      setSelectedKids(kids);
+      setAlbums([]);
+      setAlbumGroups([]);
+      deselectAllAlbums();
       navigate("/albums");
     } catch (err) {
-      setError(String(err));
+      setError(errorMessage(err));
       setLoading(false);
     }
   };
 
+  const toggleKid = (id: number) => {
+    setChosenKidIds((prev) => {
+      const next = new Set(prev);
+      if (next.has(id)) next.delete(id);
+      else next.add(id);
+      return next;
+    });
+  };
+
   const handleSelectKid = async (e: React.FormEvent) => {
     e.preventDefault();
-    if (!selectedKid) return;
-    await doLogin(selectedKid.id);
+    const kids = kindergartens.filter((k) => chosenKidIds.has(k.id));
+    if (kids.length === 0) return;
+    await doLogin(kids);
   };
 
   return (
@@ -190,17 +228,16 @@ export default function LoginPage() {
                   <label
                     key={kid.id}
                     className={`flex items-center p-3 border rounded-lg cursor-pointer transition-all ${
-                      selectedKid?.id === kid.id
+                      chosenKidIds.has(kid.id)
                         ? "border-blue-500 dark:border-blue-600 bg-blue-50 dark:bg-blue-950/30"
                         : "border-gray-200 dark:border-gray-800 hover:border-gray-300 dark:hover:border-gray-700"
                     }`}
                   >
                     <input
-                      type="radio"
-                      name="kindergarten"
-                      checked={selectedKid?.id === kid.id}
-                      onChange={() => setSelectedKid(kid)}
-                      className="mr-3 h-4 w-4 text-blue-600 focus:ring-blue-500"
+                      type="checkbox"
+                      checked={chosenKidIds.has(kid.id)}
+                      onChange={() => toggleKid(kid.id)}
+                      className="mr-3 h-4 w-4 rounded text-blue-600 focus:ring-blue-500"
                     />
                     <span className="text-gray-900 dark:text-gray-100">{kid.name}</span>
                     <span className="text-gray-400 dark:text-gray-500 text-xs ml-auto">
@@ -211,7 +248,7 @@ export default function LoginPage() {
               </div>
               <button
                 type="submit"
-                disabled={loading || !selectedKid}
+                disabled={loading || chosenKidIds.size === 0}
                 className="w-full bg-blue-600 dark:bg-blue-600 text-white py-2.5 px-4 rounded-lg hover:bg-blue-700 dark:hover:bg-blue-700 disabled:opacity-50 disabled:cursor-not-allowed transition-colors font-medium"
               >
                 {loading ? t.loggingIn : t.continue}
@@ -5,16 +5,24 @@ import { useLanguage } from "../i18n/LanguageContext";
 import { open } from "@tauri-apps/plugin-dialog";
 import { getName, getVersion } from "@tauri-apps/api/app";
 import { openUrl } from "@tauri-apps/plugin-opener";
+import { invoke } from "@tauri-apps/api/core";
 import { checkForUpdates } from "../utils/updater";
 import type { Language } from "../i18n/translations";
+import type { DuplicateMode, ZipLayout } from "../types";
+import { errorMessage } from "../utils/errors";
+import ScheduledSync from "../components/ScheduledSync";
+import StorageSettings from "../components/StorageSettings";
+import UploadSettings from "../components/UploadSettings";
 
 export default function SettingsPage() {
-  const { settings, setSettings } = useApp();
+  const { settings, setSettings, albums } = useApp();
   const { language, setLanguage, t } = useLanguage();
   const navigate = useNavigate();
   const [checkingUpdates, setCheckingUpdates] = useState(false);
   const [appVersion, setAppVersion] = useState<string>("");
   const [appName, setAppName] = useState<string>("");
+  const [templatePreview, setTemplatePreview] = useState<string[]>([]);
+  const [templateError, setTemplateError] = useState<string | null>(null);
 
   useEffect(() => {
     const loadAppInfo = async () => {
@@ -30,6 +38,29 @@ export default function SettingsPage() {
     loadAppInfo();
   }, []);
 
+  useEffect(() => {
+    const template = settings.path_template;
+    if (!template) {
+      setTemplatePreview([]);
+      setTemplateError(null);
+      return;
+    }
+    const timer = setTimeout(async () => {
+      try {
+        const paths = await invoke<string[]>("preview_paths", {
+          template,
+          albums,
+        });
+        setTemplatePreview(paths);
+        setTemplateError(null);
+      } catch (err) {
+        setTemplatePreview([]);
+        setTemplateError(errorMessage(err));
+      }
+    }, 300);
+    return () => clearTimeout(timer);
+  }, [settings.path_template, albums]);
+
   const handleBrowse = async () => {
     const selected = await open({
       directory: true,
@@ -160,6 +191,206 @@ export default function SettingsPage() {
             </p>
           </div>
 
+          <div>
+            <label className="block text-sm font-medium text-gray-700 dark:text-gray-300 mb-2">
+              {t.parallelDownloads}
+            </label>
+            <input
+              type="number"
+              value={settings.concurrency}
+              onChange={(e) =>
+                setSettings({
+                  ...settings,
+                  concurrency: Math.min(
+                    8,
+                    Math.max(1, parseInt(e.target.value) || 1)
+                  ),
+                })
+              }
+              className="w-full px-4 py-2.5 bg-white dark:bg-gray-950 border border-gray-300 dark:border-gray-700 rounded-lg focus:outline-none focus:ring-2 focus:ring-blue-500 dark:focus:ring-blue-600 focus:border-transparent text-gray-900 dark:text-gray-100"
+              min={1}
+              max={8}
+            />
+            <p className="text-xs text-gray-400 dark:text-gray-500 mt-2">
+              {t.parallelDownloadsHelp}
+            </p>
+          </div>
+
+          <div>
+            <label className="block text-sm font-medium text-gray-700 dark:text-gray-300 mb-2">
+              {t.duplicatePhotos}
+            </label>
+            <select
+              value={settings.duplicates}
+              onChange={(e) =>
+                setSettings({
+                  ...settings,
+                  duplicates: e.target.value as DuplicateMode,
+                })
+              }
+              className="w-full px-4 py-2.5 bg-white dark:bg-gray-950 border border-gray-300 dark:border-gray-700 rounded-lg focus:outline-none focus:ring-2 focus:ring-blue-500 dark:focus:ring-blue-600 focus:border-transparent text-gray-900 dark:text-gray-100"
+            >
+              <option value="skip">{t.duplicatesSkip}</option>
+              <option value="hardlink">{t.duplicatesHardlink}</option>
+              <option value="symlink">{t.duplicatesSymlink}</option>
+            </select>
+            <p className="text-xs text-gray-400 dark:text-gray-500 mt-2">
+              {t.duplicatePhotosHelp}
+            </p>
+          </div>
+
+          <div>
+            <label className="block text-sm font-medium text-gray-700 dark:text-gray-300 mb-2">
+              {t.fileNameTemplate}
+            </label>
+            <input
+              type="text"
+              value={settings.path_template ?? ""}
+              onChange={(e) =>
+                setSettings({
+                  ...settings,
+                  path_template: e.target.value || null,
+                })
+              }
+              placeholder="{year}/{year}-{month} {album_title}/{index:03} {image_id}.{ext}"
+              className="w-full px-4 py-2.5 bg-white dark:bg-gray-950 border border-gray-300 dark:border-gray-700 rounded-lg focus:outline-none focus:ring-2 focus:ring-blue-500 dark:focus:ring-blue-600 focus:border-transparent text-gray-900 dark:text-gray-100 font-mono text-sm"
+            />
+            <p className="text-xs text-gray-400 dark:text-gray-500 mt-2">
+              {t.fileNameTemplateHelp}
+            </p>
+            {templateError && (
+              <p className="text-xs text-red-600 dark:text-red-400 mt-2">
+                {templateError}
+              </p>
+            )}
+            {templatePreview.length > 0 && (
+              <div className="mt-2 p-3 bg-gray-100 dark:bg-gray-900 rounded-lg">
+                <p className="text-xs font-medium text-gray-500 dark:text-gray-400 mb-1">
+                  {t.templatePreview}
+                </p>
+                {templatePreview.map((path) => (
+                  <div
+                    key={path}
+                    className="text-xs font-mono text-gray-700 dark:text-gray-300 truncate"
+                  >
+                    {path}
+                  </div>
+                ))}
+              </div>
+            )}
+          </div>
+
+          <div>
+            <div className="flex items-center">
+              <input
+                type="checkbox"
+                id="writeExif"
+                checked={settings.write_exif}
+                onChange={(e) =>
+                  setSettings({ ...settings, write_exif: e.target.checked })
+                }
+                className="mr-2 h-4 w-4 rounded border-gray-300 dark:border-gray-700 text-blue-600 focus:ring-blue-500 dark:focus:ring-blue-600"
+              />
+              <label
+                htmlFor="writeExif"
+                className="text-sm font-medium text-gray-700 dark:text-gray-300"
+              >
+                {t.writeExif}
+              </label>
+            </div>
+            <p className="text-xs text-gray-400 dark:text-gray-500 mt-2">
+              {t.writeExifHelp}
+            </p>
+          </div>
+
+          <div>
+            <div className="flex items-center">
+              <input
+                type="checkbox"
+                id="writeXmp"
+                checked={settings.write_xmp}
+                onChange={(e) =>
+                  setSettings({ ...settings, write_xmp: e.target.checked })
+                }
+                className="mr-2 h-4 w-4 rounded border-gray-300 dark:border-gray-700 text-blue-600 focus:ring-blue-500 dark:focus:ring-blue-600"
+              />
+              <label
+                htmlFor="writeXmp"
+                className="text-sm font-medium text-gray-700 dark:text-gray-300"
+              >
+                {t.writeXmp}
+              </label>
+            </div>
+            <p className="text-xs text-gray-400 dark:text-gray-500 mt-2">
+              {t.writeXmpHelp}
+            </p>
+          </div>
+
+          <div>
+            <div className="flex items-center">
+              <input
+                type="checkbox"
+                id="zipAfterDownload"
+                checked={settings.zip != null}
+                onChange={(e) =>
+                  setSettings({
+                    ...settings,
+                    zip: e.target.checked ? { level: 0, layout: "per_album" } : null,
+                  })
+                }
+                className="mr-2 h-4 w-4 rounded border-gray-300 dark:border-gray-700 text-blue-600 focus:ring-blue-500 dark:focus:ring-blue-600"
+              />
+              <label
+                htmlFor="zipAfterDownload"
+                className="text-sm font-medium text-gray-700 dark:text-gray-300"
+              >
+                {t.zipAfterDownload}
+              </label>
+            </div>
+            {settings.zip && (
+              <div className="grid grid-cols-2 gap-2 mt-2">
+                <select
+                  aria-label={t.zipLevel}
+                  value={settings.zip.level}
+                  onChange={(e) =>
+                    setSettings({
+                      ...settings,
+                      zip: { ...settings.zip!, level: parseInt(e.target.value) },
+                    })
+                  }
+                  className="px-3 py-2 bg-white dark:bg-gray-950 border border-gray-300 dark:border-gray-700 rounded-lg focus:outline-none focus:ring-2 focus:ring-blue-500 dark:focus:ring-blue-600 focus:border-transparent text-gray-900 dark:text-gray-100 text-sm"
+                >
+                  <option value={0}>{t.zipLevelStored}</option>
+                  <option value={1}>{t.zipLevelFast}</option>
+                  <option value={9}>{t.zipLevelSmallest}</option>
+                </select>
+                <select
+                  aria-label={t.zipLayout}
+                  value={settings.zip.layout}
+                  onChange={(e) =>
+                    setSettings({
+                      ...settings,
+                      zip: { ...settings.zip!, layout: e.target.value as ZipLayout },
+                    })
+                  }
+                  className="px-3 py-2 bg-white dark:bg-gray-950 border border-gray-300 dark:border-gray-700 rounded-lg focus:outline-none focus:ring-2 focus:ring-blue-500 dark:focus:ring-blue-600 focus:border-transparent text-gray-900 dark:text-gray-100 text-sm"
+                >
+                  <option value="per_album">{t.zipPerAlbum}</option>
+                  <option value="combined">{t.zipCombined}</option>
+                </select>
+              </div>
+            )}
+            <p className="text-xs text-gray-400 dark:text-gray-500 mt-2">
+              {t.zipAfterDownloadHelp}
+            </p>
+          </div>
+
+          <StorageSettings />
+
+          <UploadSettings />
+
+          <ScheduledSync />
+
           <div className="pt-4 border-t border-gray-200 dark:border-gray-800">
             <button
               onClick={handleCheckUpdates}
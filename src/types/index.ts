@@ -8,19 +8,79 @@ export interface Kindergarten {
   name: string;
 }
 
+/** A session saved by an earlier launch that Kidplan still accepts. */
+export interface RestoredSession {
+  kindergarten: Kindergarten | null;
+  kindergartens: Kindergarten[];
+}
+
+/** Albums of one kindergarten, when several are downloaded together. */
+export interface KindergartenAlbums {
+  kindergarten: Kindergarten;
+  albums: Album[];
+}
+
 export interface Album {
   id: string;
   title: string;
   url: string;
   image_count: number | null;
+  date?: string | null;
+  description?: string | null;
+  cover_url?: string | null;
+  author?: string | null;
+  department?: string | null;
+  /** Fields from Kidplan's album list that the app does not interpret. */
+  extra?: Record<string, unknown>;
 }
 
+export type AlbumSort = "kidplan" | "newest" | "oldest" | "title";
+
 export interface DownloadSettings {
   out_dir: string;
   delay_ms: number;
   limit_per_album: number;
+  concurrency: number;
+  duplicates: DuplicateMode;
+  path_template: string | null;
+  write_exif: boolean;
+  write_xmp: boolean;
+  /** Pack the albums into ZIP archives in `out_dir` once downloaded. */
+  zip?: ZipSettings | null;
+  /** Where the images go; bookkeeping always stays in `out_dir`. */
+  storage?: StorageConfig;
+  /** Push each saved image into this photo library. */
+  upload?: UploadConfig | null;
+}
+
+export type UploadConfig =
+  | { kind: "immich"; url: string; api_key: string }
+  | { kind: "photoprism"; url: string; token: string };
+
+export type StorageConfig =
+  | { kind: "local" }
+  | { kind: "webdav"; url: string; username: string | null; password: string | null }
+  | {
+      kind: "s3";
+      endpoint: string;
+      bucket: string;
+      region: string;
+      access_key: string;
+      secret_key: string;
+      /** Folder inside the bucket. */
+      prefix: string;
+    };
+
+export type ZipLayout = "per_album" | "combined";
+
+export interface ZipSettings {
+  /** 0 stores files as they are; 1–9 deflate, 9 smallest. */
+  level: number;
+  layout: ZipLayout;
 }
 
+export type DuplicateMode = "skip" | "hardlink" | "symlink";
+
 export interface DownloadProgress {
   album_title: string;
   album_index: number;
@@ -29,7 +89,12 @@ export interface DownloadProgress {
   image_total: number;
   filename: string;
   status: string;
-  error_type?: string;
+  error_type?: ErrorCode;
+  paused?: boolean;
+  /** What was done before a cancel, on the final "cancelled" update. */
+  result?: DownloadResult;
+  /** The queued job the update belongs to. */
+  job_id?: number;
 }
 
 export interface DownloadResult {
@@ -37,4 +102,87 @@ export interface DownloadResult {
   total_images: number;
   skipped: number;
   failed: number;
+  duplicates: number;
+  cancelled?: boolean;
+  /** Images pushed into the configured photo library. */
+  uploaded?: number;
+  /** Why packing the albums as ZIP failed after the download. */
+  export_error?: string | null;
+}
+
+/** A static HTML gallery written into the download folder. */
+export interface Gallery {
+  index: string;
+  albums: number;
+  images: number;
+}
+
+export type JobStatus = "queued" | "running" | "completed" | "failed" | "cancelled";
+
+/** What a queued job downloads. */
+export type JobKind =
+  | { type: "download" }
+  | { type: "kindergartens"; selections: KindergartenAlbums[] }
+  | { type: "sync" };
+
+export interface Job {
+  id: number;
+  status: JobStatus;
+  kind: JobKind;
+  kindergarten: Kindergarten | null;
+  albums: Album[];
+  settings: DownloadSettings;
+  created_at: string;
+  result: DownloadResult | null;
+  error: string | null;
+}
+
+export type Schedule =
+  | { kind: "daily"; at: string }
+  | { kind: "every"; minutes: number };
+
+export interface ScheduleConfig {
+  enabled: boolean;
+  schedule: Schedule;
+  /** Empty means the kindergarten the session is logged in to. */
+  kid_ids: number[];
+  settings: DownloadSettings;
+}
+
+export interface ScheduledRun {
+  started_at: string;
+  finished_at: string;
+  new_albums: number;
+  new_images: number;
+  result: DownloadResult;
+  error: { code: ErrorCode; message: string } | null;
+}
+
+export interface SyncSummary {
+  new_albums: number;
+  changed_albums: number;
+  unchanged_albums: number;
+  new_images: number;
+  result: DownloadResult;
+}
+
+export type ErrorCode =
+  | "invalid_credentials"
+  | "not_logged_in"
+  | "session_expired"
+  | "network_error"
+  | "http_status"
+  | "rate_limited"
+  | "parse_error"
+  | "filesystem_error"
+  | "invalid_template"
+  | "cancelled"
+  | "job_not_found"
+  | "unsupported_image";
+
+/** Error returned by backend commands. */
+export interface AppError {
+  code: ErrorCode;
+  message: string;
+  status?: number;
 }
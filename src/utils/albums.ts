@@ -0,0 +1,7 @@
+/**
+ * Selection key of an album when albums from several kindergartens are shown,
+ * since album ids are only unique within one kindergarten.
+ */
+export function albumKey(kidId: number, albumId: string): string {
+  return `${kidId}:${albumId}`;
+}
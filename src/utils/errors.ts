@@ -0,0 +1,15 @@
+import type { AppError } from "../types";
+
+export function isAppError(err: unknown): err is AppError {
+  return (
+    typeof err === "object" &&
+    err !== null &&
+    "code" in err &&
+    "message" in err
+  );
+}
+
+/** Human-readable message for an error thrown by `invoke`. */
+export function errorMessage(err: unknown): string {
+  return isAppError(err) ? err.message : String(err);
+}